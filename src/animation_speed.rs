@@ -0,0 +1,34 @@
+/// How fast things move on screen. Only affects the presentation, the
+/// turns play out the same at any speed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AnimationSpeed {
+    Normal,
+    Fast,
+    /// Moves are close to instant, and the squishing, bobbing and
+    /// lurching is left out entirely.
+    ReduceMotion,
+}
+
+impl AnimationSpeed {
+    /// How many times faster than normal the animations play.
+    pub fn multiplier(self) -> f32 {
+        match self {
+            AnimationSpeed::Normal => 1.0,
+            AnimationSpeed::Fast => 2.0,
+            AnimationSpeed::ReduceMotion => 5.0,
+        }
+    }
+
+    pub fn reduce_motion(self) -> bool {
+        self == AnimationSpeed::ReduceMotion
+    }
+
+    /// The next speed, for cycling through them in the settings.
+    pub fn next(self) -> AnimationSpeed {
+        match self {
+            AnimationSpeed::Normal => AnimationSpeed::Fast,
+            AnimationSpeed::Fast => AnimationSpeed::ReduceMotion,
+            AnimationSpeed::ReduceMotion => AnimationSpeed::Normal,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{Dungeon, DungeonEvent, Terrain};
+use sdl2::rect::Point;
+use std::collections::{HashSet, VecDeque};
+
+/// How far from the player tiles count as explored, if they're in
+/// line of sight.
+const EXPLORE_RADIUS: i32 = 7;
+
+/// Walks the player towards the nearest tile they haven't seen yet, or
+/// to the exit once there's nothing left to see. Runs on the client
+/// side, so the dungeon (and its replays) only ever sees the
+/// individual moves.
+#[derive(Default)]
+pub struct AutoExplore {
+    pub active: bool,
+    explored: HashSet<Point>,
+    seed: u64,
+    level: usize,
+    round: u64,
+    last_position: Option<Point>,
+}
+
+impl AutoExplore {
+    pub fn new() -> AutoExplore {
+        AutoExplore::default()
+    }
+
+    /// Should be called every frame, remembers what the player has
+    /// seen, and takes the next step if exploring.
+    pub fn update(&mut self, dungeon: &mut Dungeon) {
+        if dungeon.seed() != self.seed || dungeon.level_nth() != self.level || dungeon.round() < self.round {
+            // A new level, or a new run or an older save was loaded
+            self.explored.clear();
+            self.seed = dungeon.seed();
+            self.level = dungeon.level_nth();
+            self.last_position = None;
+            self.active = false;
+        }
+        self.round = dungeon.round();
+
+        let position = dungeon.player().position();
+        if self.last_position != Some(position) {
+            self.last_position = Some(position);
+            self.mark_explored(dungeon, position);
+        }
+
+        if !self.active {
+            return;
+        }
+        if !dungeon.can_run_events() || enemy_in_sight(dungeon) {
+            self.active = false;
+            return;
+        }
+        if dungeon.player().is_animating() {
+            return;
+        }
+
+        match self.next_step(dungeon, position) {
+            Some((event, step)) => {
+                dungeon.run_event(event);
+                let player = dungeon.player();
+                let (x, y) = (player.x, player.y);
+                let level = dungeon.level_mut();
+                level.line_of_sight_x = x;
+                level.line_of_sight_y = y;
+                if (x, y) == (position.x, position.y) && level.get_terrain(step.x, step.y) != Terrain::DoorOpen {
+                    // Something was in the way, let the player decide what to do
+                    self.active = false;
+                }
+            }
+            None => self.active = false,
+        }
+    }
+
+    /// Whether the player has seen the tile on this level. Walls are
+    /// never in line of sight themselves, so they count as seen if the
+    /// player has seen a tile next to them.
+    pub fn has_seen(&self, dungeon: &Dungeon, x: i32, y: i32) -> bool {
+        let point = Point::new(x, y);
+        self.explored.contains(&point)
+            || (dungeon.level().get_terrain(x, y).unwalkable()
+                && [(0, -1), (0, 1), (-1, 0), (1, 0)]
+                    .iter()
+                    .any(|&(dx, dy)| self.explored.contains(&point.offset(dx, dy))))
+    }
+
+    fn mark_explored(&mut self, dungeon: &Dungeon, position: Point) {
+        let level = dungeon.level();
+        let room = level.room_at_position(position);
+        for y in position.y - EXPLORE_RADIUS..=position.y + EXPLORE_RADIUS {
+            for x in position.x - EXPLORE_RADIUS..=position.x + EXPLORE_RADIUS {
+                let point = Point::new(x, y);
+                if room.is_some_and(|rect| rect.contains_point(point)) || level.is_visible_from(position, x, y) {
+                    self.explored.insert(point);
+                }
+            }
+        }
+    }
+
+    /// The first move towards the closest unexplored tile, or the exit
+    /// if everything reachable has been explored. Walls never come into
+    /// line of sight, so only walkable tiles are worth exploring.
+    fn next_step(&self, dungeon: &Dungeon, from: Point) -> Option<(DungeonEvent, Point)> {
+        let level = dungeon.level();
+        let unexplored =
+            |point: Point| !self.explored.contains(&point) && explorable(level.get_terrain(point.x, point.y));
+        let target = self.closest(dungeon, from, unexplored).or_else(|| {
+            self.closest(dungeon, from, |point| {
+                level.get_terrain(point.x, point.y) == Terrain::Exit
+            })
+        })?;
+        let path = level.find_path(from, target, usize::MAX, |point, terrain| {
+            explorable(terrain) && self.explored.contains(&point)
+        })?;
+        let step = *path.first()?;
+        let event = match (step.x - from.x, step.y - from.y) {
+            (0, -1) => DungeonEvent::MoveUp,
+            (0, 1) => DungeonEvent::MoveDown,
+            (-1, 0) => DungeonEvent::MoveLeft,
+            _ => DungeonEvent::MoveRight,
+        };
+        Some((event, step))
+    }
+
+    /// Searches outwards from `from` through explored, walkable tiles
+    /// for the closest tile that `is_target`.
+    fn closest<F: Fn(Point) -> bool>(&self, dungeon: &Dungeon, from: Point, is_target: F) -> Option<Point> {
+        let level = dungeon.level();
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(from);
+        queue.push_back(from);
+        while let Some(point) = queue.pop_front() {
+            if point != from && is_target(point) {
+                return Some(point);
+            }
+            if point != from && !self.explored.contains(&point) {
+                continue;
+            }
+            for (dx, dy) in &[(0, -1), (0, 1), (-1, 0), (1, 0)] {
+                let next = point.offset(*dx, *dy);
+                let terrain = level.get_terrain(next.x, next.y);
+                let passable = explorable(terrain) || (terrain == Terrain::Exit && is_target(next));
+                if passable && !visited.contains(&next) {
+                    visited.insert(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Tiles the explorer walks through. Doors count, since bumping into
+/// them opens them, but the exit doesn't, so exploring doesn't end the
+/// level early.
+fn explorable(terrain: Terrain) -> bool {
+    matches!(terrain, Terrain::Floor | Terrain::Door | Terrain::DoorOpen)
+}
+
+fn enemy_in_sight(dungeon: &Dungeon) -> bool {
+    let visible_ids = dungeon.get_selectable_fighter_ids();
+    dungeon
+        .enemies()
+        .any(|fighter| fighter.stats.health > 0 && visible_ids.contains(&fighter.id))
+}
@@ -0,0 +1,121 @@
+use crate::Dungeon;
+use std::cmp::Reverse;
+use std::time::SystemTime;
+
+/// How many autosaves are kept, the oldest one is overwritten first.
+const AUTOSAVE_SLOTS: usize = 3;
+/// Besides level transitions, the game is autosaved this often.
+const AUTOSAVE_INTERVAL_ROUNDS: u64 = 100;
+
+fn autosave_file(slot: usize) -> String {
+    format!("excavation-site-mercury-autosave-{}.bin", slot)
+}
+
+/// What the autosave list shows about a save.
+pub struct AutosaveInfo {
+    pub slot: usize,
+    pub level: usize,
+    pub round: u64,
+    pub treasure: i32,
+    pub saved_at: SystemTime,
+}
+
+/// Keeps a rotation of autosaves, written on level transitions and
+/// every [AUTOSAVE_INTERVAL_ROUNDS] rounds.
+pub struct Autosaves {
+    next_slot: usize,
+    last_saved_round: u64,
+    last_saved_level: usize,
+}
+
+impl Default for Autosaves {
+    fn default() -> Autosaves {
+        Autosaves::new()
+    }
+}
+
+impl Autosaves {
+    pub fn new() -> Autosaves {
+        // Continue the rotation after the most recent existing save
+        let next_slot = Autosaves::newest_slot().map_or(0, |slot| (slot + 1) % AUTOSAVE_SLOTS);
+        Autosaves {
+            next_slot,
+            last_saved_round: 0,
+            last_saved_level: 0,
+        }
+    }
+
+    /// Starts counting towards the next autosave from the dungeon's
+    /// current state, e.g. right after loading it.
+    pub fn reset(&mut self, dungeon: &Dungeon) {
+        self.last_saved_round = dungeon.round();
+        self.last_saved_level = dungeon.level_nth();
+    }
+
+    /// Should be called every frame, saves the dungeon if it's time.
+    pub fn update(&mut self, dungeon: &Dungeon) {
+        if dungeon.round() < self.last_saved_round {
+            // A new run was started
+            self.reset(dungeon);
+            return;
+        }
+
+        let level_changed = dungeon.level_nth() != self.last_saved_level;
+        let interval_passed = dungeon.round() >= self.last_saved_round + AUTOSAVE_INTERVAL_ROUNDS;
+        if dungeon.is_game_over() || !(level_changed || interval_passed) {
+            return;
+        }
+
+        self.reset(dungeon);
+        let file = autosave_file(self.next_slot);
+        match dungeon
+            .to_bytes()
+            .ok()
+            .and_then(|bytes| std::fs::write(&file, bytes).ok())
+        {
+            Some(_) => log::info!("Autosaved to {}.", file),
+            None => log::error!("Failed autosaving to {}.", file),
+        }
+        self.next_slot = (self.next_slot + 1) % AUTOSAVE_SLOTS;
+    }
+
+    /// The existing autosaves, newest first.
+    pub fn list() -> Vec<AutosaveInfo> {
+        let mut infos = (0..AUTOSAVE_SLOTS)
+            .filter_map(|slot| {
+                let saved_at = Autosaves::saved_at(slot)?;
+                let dungeon = Autosaves::load(slot)?;
+                Some(AutosaveInfo {
+                    slot,
+                    level: dungeon.level_nth(),
+                    round: dungeon.round(),
+                    treasure: dungeon.treasure(),
+                    saved_at,
+                })
+            })
+            .collect::<Vec<AutosaveInfo>>();
+        infos.sort_by_key(|info| Reverse(info.saved_at));
+        infos
+    }
+
+    /// The slot of the most recently written autosave. Goes by the file
+    /// times, so the saves don't need to be loaded and replayed.
+    pub fn newest_slot() -> Option<usize> {
+        (0..AUTOSAVE_SLOTS)
+            .filter_map(|slot| Some((slot, Autosaves::saved_at(slot)?)))
+            .max_by_key(|&(_, saved_at)| saved_at)
+            .map(|(slot, _)| slot)
+    }
+
+    /// When the autosave in the slot was written, if there is one.
+    fn saved_at(slot: usize) -> Option<SystemTime> {
+        std::fs::metadata(autosave_file(slot))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    pub fn load(slot: usize) -> Option<Dungeon> {
+        let bytes = std::fs::read(autosave_file(slot)).ok()?;
+        Dungeon::from_bytes(&bytes).ok()
+    }
+}
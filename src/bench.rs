@@ -0,0 +1,60 @@
+use crate::{Dungeon, DungeonEvent, StatIncrease};
+use rand_core::{RngCore, SeedableRng};
+use rand_pcg::Pcg32;
+use std::time::{Duration, Instant};
+
+const DEFAULT_GENERATIONS: usize = 100;
+const DEFAULT_TURNS: usize = 10_000;
+
+/// Times dungeon generation and simulated turns without opening a
+/// window, for comparing performance between changes. Everything is
+/// seeded, so each run does exactly the same work.
+///
+/// Runs with `--bench [generations] [turns]`.
+pub fn run() {
+    let mut numbers = std::env::args()
+        .skip_while(|s| s != "--bench")
+        .skip(1)
+        .map_while(|s| s.parse::<usize>().ok());
+    let generations = numbers.next().unwrap_or(DEFAULT_GENERATIONS);
+    let turns = numbers.next().unwrap_or(DEFAULT_TURNS);
+
+    let start = Instant::now();
+    for seed in 0..generations {
+        Dungeon::new(seed as u64);
+    }
+    report("Dungeon generation", generations, start.elapsed());
+
+    let mut rng = Pcg32::seed_from_u64(0);
+    let mut seed = 0;
+    let mut dungeon = Dungeon::new(seed);
+    let start = Instant::now();
+    for _ in 0..turns {
+        if dungeon.is_game_over() {
+            seed += 1;
+            dungeon = Dungeon::new(seed);
+        } else if dungeon.stat_increase_pending() {
+            dungeon.run_event(DungeonEvent::LevelUp(StatIncrease::Arm));
+        } else if !dungeon.can_run_events() {
+            dungeon.try_load_next_level(true);
+        } else {
+            dungeon.run_event(match rng.next_u32() % 4 {
+                0 => DungeonEvent::MoveUp,
+                1 => DungeonEvent::MoveDown,
+                2 => DungeonEvent::MoveLeft,
+                _ => DungeonEvent::MoveRight,
+            });
+        }
+    }
+    report("Simulated turns", turns, start.elapsed());
+}
+
+fn report(name: &str, count: usize, elapsed: Duration) {
+    println!(
+        "{}: {} in {:.3} s, {:.3} ms each",
+        name,
+        count,
+        elapsed.as_secs_f64(),
+        elapsed.as_secs_f64() * 1000.0 / count.max(1) as f64,
+    );
+}
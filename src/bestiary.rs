@@ -0,0 +1,139 @@
+use crate::level::{FighterSpawn, SPAWN_METAL_OVERSEER, SPAWN_ROACH, SPAWN_ROCKMAN, SPAWN_SENTIENT_METAL, SPAWN_SLIME};
+use crate::{interface, Dungeon, LocalizableString, Name, TextPainter, TilePainter, UserInterface, TILE_STRIDE};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+
+/// The names of the enemies that have been encountered are stored
+/// here, so the bestiary fills up across runs.
+static BESTIARY_FILE: &str = "excavation-site-mercury-bestiary.txt";
+
+/// The enemies listed in the bestiary, roughly in the order they're met.
+const ENTRIES: [FighterSpawn; 5] = [
+    SPAWN_SLIME,
+    SPAWN_ROACH,
+    SPAWN_ROCKMAN,
+    SPAWN_SENTIENT_METAL,
+    SPAWN_METAL_OVERSEER,
+];
+
+/// A reference of the enemies, each one filled in when the player
+/// first sees it.
+pub struct Bestiary {
+    encountered: Vec<Name>,
+    /// Set when the back button is pressed.
+    pub should_close: bool,
+}
+
+impl Bestiary {
+    pub fn load() -> Bestiary {
+        let encountered_names = std::fs::read_to_string(BESTIARY_FILE).unwrap_or_default();
+        let encountered = ENTRIES
+            .iter()
+            .map(|spawn| spawn.name.clone())
+            .filter(|name| encountered_names.lines().any(|line| line == format!("{:?}", name)))
+            .collect();
+        Bestiary {
+            encountered,
+            should_close: false,
+        }
+    }
+
+    /// Records the living enemies among `seen_ids`, the fighters the
+    /// player can currently see.
+    pub fn update(&mut self, dungeon: &Dungeon, seen_ids: &[usize]) {
+        let mut changed = false;
+        for fighter in seen_ids.iter().filter_map(|id| dungeon.get_fighter(*id)) {
+            let listed = ENTRIES.iter().any(|spawn| spawn.name == fighter.name);
+            if listed && fighter.stats.health > 0 && !self.encountered.contains(&fighter.name) {
+                self.encountered.push(fighter.name.clone());
+                changed = true;
+            }
+        }
+
+        if changed {
+            let encountered_names = self
+                .encountered
+                .iter()
+                .map(|name| format!("{:?}\n", name))
+                .collect::<String>();
+            if let Err(err) = std::fs::write(BESTIARY_FILE, encountered_names) {
+                log::error!("Failed to save the bestiary to {}: {}", BESTIARY_FILE, err);
+            }
+        }
+    }
+
+    pub fn run<RT: RenderTarget>(
+        &mut self,
+        canvas: &mut Canvas<RT>,
+        text_painter: &mut TextPainter,
+        tile_painter: &mut TilePainter,
+        ui: &mut UserInterface,
+    ) {
+        let (width, height) = canvas.output_size().unwrap();
+        let margin = 10;
+        let row_height = TILE_STRIDE + 12;
+
+        let header = LocalizableString::BestiaryHeader {
+            encountered: self.encountered.len(),
+            total: ENTRIES.len(),
+        };
+        ui.text(canvas, text_painter, &header, margin, margin);
+
+        let list_rect = Rect::new(
+            margin,
+            50,
+            width.saturating_sub(margin as u32 * 2),
+            height.saturating_sub(50 + 70),
+        );
+        canvas.set_clip_rect(list_rect);
+        for (i, spawn) in ENTRIES.iter().enumerate() {
+            let row_rect = Rect::new(
+                list_rect.x,
+                list_rect.y + row_height * i as i32,
+                list_rect.width(),
+                row_height as u32,
+            );
+            canvas.set_draw_color(if i % 2 == 0 {
+                interface::ROW_BACKGROUND
+            } else {
+                interface::ROW_BACKGROUND_ALT
+            });
+            let _ = canvas.fill_rect(row_rect);
+
+            let text_x = row_rect.x + TILE_STRIDE + 16;
+            if self.encountered.contains(&spawn.name) {
+                tile_painter.draw_tile(canvas, spawn.tile, row_rect.x + 8, row_rect.y + 6, false, false);
+                let entry = LocalizableString::BestiaryEntry {
+                    name: spawn.name.clone(),
+                    stats: spawn.stats.clone(),
+                };
+                ui.text(canvas, text_painter, &entry, text_x, row_rect.y + 8);
+            } else {
+                ui.text(
+                    canvas,
+                    text_painter,
+                    &LocalizableString::BestiaryUnknown,
+                    text_x,
+                    row_rect.y + 8,
+                );
+            }
+        }
+        canvas.set_clip_rect(None);
+
+        let back_width = 120;
+        if ui.button(
+            canvas,
+            text_painter,
+            &LocalizableString::BackButton,
+            Rect::new(
+                (width as i32 - back_width as i32) / 2,
+                height as i32 - margin - 50,
+                back_width,
+                40,
+            ),
+            true,
+        ) {
+            self.should_close = true;
+        }
+    }
+}
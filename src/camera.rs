@@ -1,20 +1,118 @@
-use crate::move_towards;
+use crate::{move_towards, AnimationSpeed, TILE_STRIDE};
+use sdl2::rect::{Point, Rect};
+
+/// The fraction of the viewport's width and height, centered, that
+/// the focus can move around in without the camera following.
+const DEAD_ZONE_FRACTION: f32 = 0.4;
 
 pub struct Camera {
     pub x: i32,
     pub y: i32,
+    /// Where the camera is panning towards, see [Camera::frame].
+    target: Point,
+    /// The level the camera is looking at, see [Camera::enter_level].
+    current_level: Option<usize>,
+    /// The last camera position on each level visited, for returning
+    /// to the same view.
+    level_views: Vec<Option<Point>>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::new()
+    }
 }
 
 impl Camera {
     pub fn new() -> Camera {
-        Camera { x: 0, y: 0 }
+        Camera {
+            x: 0,
+            y: 0,
+            target: Point::new(0, 0),
+            current_level: None,
+            level_views: Vec::new(),
+        }
+    }
+
+    /// Forgets the level views, and snaps to the focus on the next
+    /// [Camera::enter_level]. For when a run is started or loaded.
+    pub fn reset(&mut self) {
+        self.current_level = None;
+        self.level_views.clear();
+    }
+
+    /// Should be called every frame with the current level. When the
+    /// level changes, the view of the previous level is stored, and
+    /// the camera jumps to where it last was on the new level, or to
+    /// the focus if the level hasn't been seen yet.
+    pub fn enter_level(&mut self, level: usize, focus: Point, viewport: Rect) {
+        if self.current_level == Some(level) {
+            return;
+        }
+        if let Some(previous_level) = self.current_level {
+            if self.level_views.len() <= previous_level {
+                self.level_views.resize(previous_level + 1, None);
+            }
+            self.level_views[previous_level] = Some(Point::new(self.x, self.y));
+        }
+        self.current_level = Some(level);
+
+        match self.level_views.get(level).copied().flatten() {
+            Some(view) => {
+                self.x = view.x;
+                self.y = view.y;
+                self.target = view;
+            }
+            None => {
+                self.target = Point::new(focus.x - viewport.center().x, focus.y - viewport.center().y);
+                self.x = self.target.x;
+                self.y = self.target.y;
+            }
+        }
+    }
+
+    /// The coordinates of the tile under the given on-screen point.
+    pub fn screen_to_tile(&self, point: Point) -> (i32, i32) {
+        (
+            (point.x + self.x).div_euclid(TILE_STRIDE),
+            (point.y + self.y).div_euclid(TILE_STRIDE),
+        )
+    }
+
+    /// Pans the camera target just enough to keep `focus` (in world
+    /// pixels) inside the dead zone of `viewport`, the on-screen area
+    /// not covered by the HUD.
+    pub fn frame(&mut self, focus: Point, viewport: Rect) {
+        let dead_zone_width = (viewport.width() as f32 * DEAD_ZONE_FRACTION) as i32;
+        let dead_zone_height = (viewport.height() as f32 * DEAD_ZONE_FRACTION) as i32;
+        let dead_zone_left = viewport.center().x - dead_zone_width / 2;
+        let dead_zone_top = viewport.center().y - dead_zone_height / 2;
+        let on_screen = focus - self.target;
+        let push = |position: i32, start: i32, length: i32| {
+            if position < start {
+                position - start
+            } else if position > start + length {
+                position - (start + length)
+            } else {
+                0
+            }
+        };
+        self.target.x += push(on_screen.x, dead_zone_left, dead_zone_width);
+        self.target.y += push(on_screen.y, dead_zone_top, dead_zone_height);
     }
 
-    pub fn update(&mut self, delta_seconds: f32, target_x: i32, target_y: i32) {
+    pub fn update(&mut self, delta_seconds: f32, speed: AnimationSpeed) {
+        let (target_x, target_y) = (self.target.x, self.target.y);
+        if speed.reduce_motion() {
+            // Cut straight to the target instead of panning
+            self.x = target_x;
+            self.y = target_y;
+            return;
+        }
         let dx = (target_x - self.x) as f32;
         let dy = (target_y - self.y) as f32;
         let camera_distance = (dx * dx + dy * dy).sqrt();
-        let camera_movement_speed = delta_seconds * 1000.0;
+        let camera_movement_speed = delta_seconds * speed.multiplier() * 1000.0;
         let camera_movement_speed_x = dx.abs() * camera_movement_speed / camera_distance;
         let camera_movement_speed_y = dy.abs() * camera_movement_speed / camera_distance;
         self.x = move_towards(self.x, target_x, camera_movement_speed_x.max(1.0) as i32);
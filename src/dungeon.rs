@@ -1,6 +1,8 @@
-// TODO: DungeonEvents (and DungeonSaves) should be versioned.
-
-use crate::{EnemyAi, Fighter, FighterSpawn, GameLog, Level, StatIncrease, Terrain};
+use crate::level::SPAWN_REPAIR_DRONE;
+use crate::{
+    enemy_ai, stats, Die, EnemyAi, Fighter, FighterSpawn, GameLog, Level, LocalizableString, RunStats, StatIncrease,
+    StatusEffect, StatusKind, Team, Terrain,
+};
 use bincode::config::DefaultOptions;
 use bincode::Options;
 use rand_core::SeedableRng;
@@ -16,6 +18,21 @@ pub enum DungeonEvent {
     MoveLeft,
     MoveRight,
     LevelUp(StatIncrease),
+    /// Like [DungeonEvent::LevelUp], but +1 to each of the two stats,
+    /// which may also be the same stat twice.
+    LevelUpSplit(StatIncrease, StatIncrease),
+    /// Attack towards (dx, dy) without moving. Each is -1, 0 or 1, so
+    /// diagonals are allowed.
+    Attack(i32, i32),
+    /// Leave some of the carried treasure on the player's tile. Takes
+    /// no time, and the amount is capped to what's being carried.
+    DropTreasure(i32),
+    /// End the run early. The treasure still counts, but the run
+    /// isn't ranked by time.
+    Abandon,
+    /// Pick up the treasure on the player's tile, with
+    /// [RunOptions::manual_pickup]. Takes no time.
+    PickUp,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -26,13 +43,21 @@ struct DungeonState {
     current_level: usize,
     fighters: Vec<Fighter>,
     ais: Vec<Option<EnemyAi>>,
+    /// The id of the fighter controlled by the player. Don't assume
+    /// it's the first one in `fighters`, see [DungeonState::player_index].
+    player_id: usize,
     round: u64,
     level_changed: bool,
     stat_increase_pending: bool,
+    run_stats: RunStats,
+    options: RunOptions,
+    /// Rounds of oxygen left, only used with [RunOptions::oxygen].
+    oxygen: i32,
+    abandoned: bool,
 }
 
 impl DungeonState {
-    pub fn new(seed: u64) -> DungeonState {
+    pub fn new(seed: u64, options: RunOptions) -> DungeonState {
         let mut rng = Pcg32::seed_from_u64(seed);
         let log = GameLog::new();
         let mut levels = Vec::new();
@@ -47,9 +72,14 @@ impl DungeonState {
             current_level: 0,
             fighters: Vec::new(),
             ais: Vec::new(),
+            player_id: 0,
             round: 1,
             level_changed: false,
             stat_increase_pending: false,
+            run_stats: RunStats::default(),
+            options,
+            oxygen: MAX_OXYGEN,
+            abandoned: false,
         };
 
         for level in &state.levels {
@@ -61,7 +91,7 @@ impl DungeonState {
     }
 
     pub fn spawn_fighter(&mut self, spawn: FighterSpawn, levels_up: bool) {
-        self.fighters.push(Fighter::new(
+        let mut fighter = Fighter::new(
             self.fighters.len(),
             spawn.name,
             spawn.tile,
@@ -69,38 +99,218 @@ impl DungeonState {
             spawn.y,
             spawn.stats,
             levels_up,
-        ));
-        self.ais.push(spawn.ai);
+        );
+        fighter.scale = spawn.scale;
+        fighter.size = spawn.size;
+        fighter.loot = spawn.loot;
+        fighter.team = spawn.team;
+        fighter.invulnerable = self.options.peaceful && spawn.team == Team::Player;
+        fighter.die = self.options.die;
+        let ai = if self.options.peaceful && spawn.team == Team::Enemy {
+            Some(enemy_ai::PASSIVE)
+        } else {
+            spawn.ai
+        };
+        self.fighters.push(fighter);
+        self.ais.push(ai);
+    }
+
+    /// The index of the player in `fighters`. Look it up before
+    /// swapping fighters out, the dummy swapped in isn't the player.
+    fn player_index(&self) -> usize {
+        self.fighters
+            .iter()
+            .position(|fighter| fighter.id == self.player_id)
+            .expect("the player should always be among the fighters")
+    }
+
+    fn player(&self) -> &Fighter {
+        &self.fighters[self.player_index()]
+    }
+
+    fn player_mut(&mut self) -> &mut Fighter {
+        let index = self.player_index();
+        &mut self.fighters[index]
+    }
+
+    fn incapacitated_enemies(&self) -> u32 {
+        self.fighters
+            .iter()
+            .filter(|f| f.team == Team::Enemy && f.stats.health <= 0)
+            .count() as u32
+    }
+
+    /// Moves the player and processes the turn, twice over if the
+    /// player is carrying too much, see [RunOptions::carry_cap].
+    fn step_player(&mut self, dx: i32, dy: i32) {
+        let overburdened = self.overburdened();
+        self.move_player(dx, dy);
+        self.process_turn();
+        if overburdened && self.player().stats.health > 0 {
+            self.process_turn();
+        }
+    }
+
+    fn overburdened(&self) -> bool {
+        self.options.carry_cap && self.player().stats.treasure > CARRY_CAP
+    }
+
+    fn drop_treasure(&mut self, amount: i32) {
+        let player_index = self.player_index();
+        let player = &mut self.fighters[player_index];
+        let amount = amount.min(player.stats.treasure);
+        if amount <= 0 || player.stats.health <= 0 {
+            return;
+        }
+        player.stats.treasure -= amount;
+        self.levels[self.current_level].put_treasure(player.x, player.y, amount);
+        self.log.combat(self.round, LocalizableString::TreasureDropped(amount));
+    }
+
+    fn pick_up_treasure(&mut self) {
+        if !self.options.manual_pickup {
+            return;
+        }
+        let player_index = self.player_index();
+        let player = &mut self.fighters[player_index];
+        if player.stats.health <= 0 {
+            return;
+        }
+        let amount = self.levels[self.current_level].take_treasure(player.x, player.y);
+        if amount > 0 {
+            player.stats.treasure += amount;
+            self.log.combat(self.round, LocalizableString::TreasurePickedUp(amount));
+        }
+    }
+
+    fn abandon(&mut self) {
+        if self.player().stats.health > 0 && !self.levels[self.current_level].final_treasure_found {
+            self.abandoned = true;
+        }
+    }
+
+    /// Returns true if the player can't act this turn, logging why.
+    fn player_stunned(&mut self) -> bool {
+        let player = self.player();
+        let stunned = player.stats.health > 0 && player.has_status(StatusKind::Stun);
+        if stunned {
+            self.log
+                .combat(self.round, LocalizableString::TurnLostToStun(player.name.clone()));
+        }
+        stunned
     }
 
     pub fn move_player(&mut self, dx: i32, dy: i32) {
+        if self.player_stunned() {
+            return;
+        }
+        let incapacitated_before = self.incapacitated_enemies();
+        let player_index = self.player_index();
+        let (old_x, old_y) = (self.fighters[player_index].x, self.fighters[player_index].y);
+        let target_terrain = self.levels[self.current_level].get_terrain(old_x + dx, old_y + dy);
+
+        // The player trades places with allies instead of bumping into
+        // them. The ally is set aside for the step, so that the step
+        // checks the destination like it was empty.
+        let ally_index = self.fighters.iter().position(|fighter| {
+            fighter.id != self.player_id
+                && fighter.team == Team::Player
+                && fighter.stats.health > 0
+                && fighter.size == (1, 1)
+                && fighter.occupies(old_x + dx, old_y + dy)
+        });
+        let mut ally = Fighter::dummy();
+        if let Some(ally_index) = ally_index {
+            std::mem::swap(&mut ally, &mut self.fighters[ally_index]);
+        }
+
         let mut player = Fighter::dummy();
-        std::mem::swap(&mut player, &mut self.fighters[0]);
+        std::mem::swap(&mut player, &mut self.fighters[player_index]);
         player.step(
-            dx,
-            dy,
+            (dx, dy),
+            &mut self.fighters,
+            &mut self.levels[self.current_level],
+            &mut self.rng,
+            &mut self.log,
+            self.round,
+        );
+        if let Some(ally_index) = ally_index {
+            if (player.x, player.y) != (old_x, old_y) {
+                ally.displace_to(old_x, old_y);
+            }
+            std::mem::swap(&mut self.fighters[ally_index], &mut ally);
+        }
+        let level = &mut self.levels[self.current_level];
+        // The final treasure is the goal of the run, so it's always picked up
+        if !self.options.manual_pickup || level.get_terrain(player.x, player.y) == Terrain::FinalTreasure {
+            player.stats.treasure += level.take_treasure(player.x, player.y);
+        }
+        if self.options.treasure_magnet && self.current_level >= TREASURE_MAGNET_LEVEL && !self.options.manual_pickup {
+            for (dx, dy) in &[(0, -1), (0, 1), (-1, 0), (1, 0)] {
+                let (x, y) = (player.x + dx, player.y + dy);
+                // Only the scattered treasure, the final treasure is too heavy to pull
+                if level.get_terrain(x, y) == Terrain::Floor {
+                    player.stats.treasure += level.take_treasure(x, y);
+                }
+            }
+        }
+        std::mem::swap(&mut self.fighters[player_index], &mut player);
+
+        let player = &self.fighters[player_index];
+        if (player.x, player.y) != (old_x, old_y) {
+            self.run_stats.tiles_walked += 1;
+        }
+        if let Terrain::LockedDoor { .. } = target_terrain {
+            if self.levels[self.current_level].get_terrain(old_x + dx, old_y + dy) == Terrain::DoorOpen {
+                self.run_stats.locked_doors_opened += 1;
+            }
+        }
+        self.run_stats.enemies_incapacitated += self.incapacitated_enemies() - incapacitated_before;
+    }
+
+    pub fn player_attack(&mut self, dx: i32, dy: i32) {
+        if self.player_stunned() {
+            return;
+        }
+        let incapacitated_before = self.incapacitated_enemies();
+        let player_index = self.player_index();
+        let mut player = Fighter::dummy();
+        std::mem::swap(&mut player, &mut self.fighters[player_index]);
+        player.attack(
+            (dx, dy),
             &mut self.fighters,
             &mut self.levels[self.current_level],
             &mut self.rng,
             &mut self.log,
             self.round,
         );
-        player.stats.treasure += self.levels[self.current_level].take_treasure(player.x, player.y);
-        std::mem::swap(&mut self.fighters[0], &mut player);
+        std::mem::swap(&mut self.fighters[player_index], &mut player);
+        self.run_stats.enemies_incapacitated += self.incapacitated_enemies() - incapacitated_before;
     }
 
     pub fn process_turn(&mut self) {
         debug_assert_eq!(self.fighters.len(), self.ais.len());
+        let incapacitated_before = self.incapacitated_enemies();
+        let health_before = self.player().stats.health;
+        if self.options.oxygen {
+            self.breathe();
+        }
         let mut current_fighter = Fighter::dummy();
         let mut current_ai = None;
-        for i in 0..self.fighters.len() {
+        // Fighters take their turns in the order of their ids, wherever they are in the array
+        let mut turn_order = (0..self.fighters.len()).collect::<Vec<usize>>();
+        turn_order.sort_by_key(|&i| self.fighters[i].id);
+        for i in turn_order {
             // Swap out the fighter being processed for the dummy
             std::mem::swap(&mut current_fighter, &mut self.fighters[i]);
             std::mem::swap(&mut current_ai, &mut self.ais[i]);
 
-            if let Some(ai) = current_ai.as_mut() {
+            if current_fighter.has_status(StatusKind::Stun) {
+                // Stunned fighters lose their turn
+            } else if let Some(ai) = current_ai.as_mut() {
                 ai.process(
                     &mut current_fighter,
+                    self.player_id,
                     &mut self.fighters,
                     &mut self.levels[self.current_level],
                     &mut self.rng,
@@ -113,12 +323,82 @@ impl DungeonState {
             std::mem::swap(&mut self.fighters[i], &mut current_fighter);
             std::mem::swap(&mut self.ais[i], &mut current_ai);
         }
+        self.tick_status_effects();
+        self.spawn_pending_fighters();
+        self.run_stats.damage_taken += (health_before - self.player().stats.health).max(0);
+        self.run_stats.enemies_incapacitated += self.incapacitated_enemies() - incapacitated_before;
         self.round += 1;
         self.level_changed = false;
     }
 
+    /// Spreads fires to the fighters next to burning ones, and then
+    /// ticks everyone's status effects.
+    fn tick_status_effects(&mut self) {
+        let mut catching_fire = Vec::new();
+        for burning in &self.fighters {
+            let effect = burning.status_effects.iter().find(|e| e.kind == StatusKind::Burning);
+            let rounds_left = match effect {
+                Some(effect) if burning.stats.health > 0 && effect.rounds_left > 1 => effect.rounds_left - 1,
+                _ => continue,
+            };
+            for (i, fighter) in self.fighters.iter().enumerate() {
+                let adjacent = [(0, -1), (0, 1), (-1, 0), (1, 0)].iter().any(|&(dx, dy)| {
+                    (0..burning.size.1 as i32).any(|y| {
+                        (0..burning.size.0 as i32).any(|x| fighter.occupies(burning.x + x + dx, burning.y + y + dy))
+                    })
+                });
+                if adjacent && fighter.id != burning.id && !fighter.has_status(StatusKind::Burning) {
+                    catching_fire.push((i, rounds_left));
+                }
+            }
+        }
+        for (i, rounds_left) in catching_fire {
+            let effect = StatusEffect {
+                kind: StatusKind::Burning,
+                rounds_left,
+            };
+            self.fighters[i].add_status_effect(effect, &mut self.log, self.round);
+        }
+
+        let level = &mut self.levels[self.current_level];
+        for fighter in &mut self.fighters {
+            fighter.tick_status_effects(level, &mut self.log, self.round);
+        }
+    }
+
+    /// Uses up a round's worth of oxygen, and suffocates the player
+    /// every now and then if there's none left.
+    fn breathe(&mut self) {
+        if self.player().stats.health <= 0 {
+            return;
+        }
+        if self.oxygen > 0 {
+            self.oxygen -= 1;
+            if self.oxygen == 0 {
+                self.log.combat(self.round, LocalizableString::OxygenRanOut);
+            }
+        } else if self.round.is_multiple_of(SUFFOCATION_INTERVAL) {
+            self.player_mut().stats.health -= 1;
+            self.log.combat(self.round, LocalizableString::Suffocating);
+        }
+    }
+
     pub fn load_level(&mut self) {
-        let player = self.fighters.get(0).map(|f| f.clone());
+        let player = self
+            .fighters
+            .iter()
+            .find(|fighter| fighter.id == self.player_id)
+            .cloned();
+        let allies = self
+            .fighters
+            .iter()
+            .cloned()
+            .zip(self.ais.iter().cloned())
+            .filter(|(fighter, _)| {
+                fighter.team == Team::Player && fighter.id != self.player_id && fighter.stats.health > 0
+            })
+            .collect::<Vec<_>>();
+        let first_level = player.is_none();
         self.fighters.clear();
         self.ais.clear();
         self.level_changed = true;
@@ -130,61 +410,325 @@ impl DungeonState {
             let player_spawn = &self.levels[self.current_level].spawns[0];
             player.x = player_spawn.x;
             player.y = player_spawn.y;
+            self.player_id = player.id;
             self.fighters.push(player);
             self.ais.push(None);
             let _ = spawns_iter.next();
         } else {
+            self.player_id = self.fighters.len();
             self.spawn_fighter(spawns_iter.next().unwrap(), true);
         }
 
+        if self.options.oxygen {
+            // Suffocating eats into the health, so the deeper levels are
+            // entered with more of it
+            let starting_health = stats::PLAYER.health + OXYGEN_HEALTH_PER_LEVEL * self.current_level as i32;
+            let player = self.player_mut();
+            player.stats.max_health = player.stats.max_health.max(starting_health);
+            player.stats.health = player.stats.health.max(starting_health);
+        }
+
         for spawn in spawns_iter {
             self.spawn_fighter(spawn, false);
         }
+
+        if first_level && self.options.companion {
+            if let Some((x, y)) = self.free_tile_next_to_player() {
+                self.spawn_fighter(
+                    FighterSpawn {
+                        x,
+                        y,
+                        ..SPAWN_REPAIR_DRONE
+                    },
+                    false,
+                );
+            }
+        }
+        for (mut ally, ai) in allies {
+            // Allies that don't fit next to the player are left behind
+            if let Some((x, y)) = self.free_tile_next_to_player() {
+                ally.id = self.fighters.len();
+                ally.x = x;
+                ally.y = y;
+                self.fighters.push(ally);
+                self.ais.push(ai);
+            }
+        }
+
+        // There's a fresh oxygen tank waiting at the bottom of each rope
+        self.oxygen = MAX_OXYGEN;
+
+        if self.options.treasure_magnet && self.current_level == TREASURE_MAGNET_LEVEL {
+            self.log
+                .level_up(self.round, LocalizableString::TreasureMagnetActivated);
+        }
+    }
+
+    /// Adds the fighters queued during the turn next to where they
+    /// were queued from, in the order they were queued, so replays end
+    /// up with the same ids. The ones with no room around them don't
+    /// spawn.
+    fn spawn_pending_fighters(&mut self) {
+        for spawn in self.levels[self.current_level].take_pending_spawns() {
+            if let Some((x, y)) = self.free_spot_next_to(spawn.x, spawn.y, spawn.size) {
+                self.spawn_fighter(FighterSpawn { x, y, ..spawn }, false);
+            }
+        }
+    }
+
+    fn free_tile_next_to_player(&self) -> Option<(i32, i32)> {
+        let player = self.player();
+        self.free_spot_next_to(player.x, player.y, (1, 1))
+    }
+
+    /// Finds a position next to (x, y) where a fighter of the given
+    /// size would fit without covering walls or other fighters.
+    fn free_spot_next_to(&self, x: i32, y: i32, size: (u32, u32)) -> Option<(i32, i32)> {
+        let level = &self.levels[self.current_level];
+        let (width, height) = (size.0 as i32, size.1 as i32);
+        [(0, 1), (1, 0), (-1, 0), (0, -1)]
+            .iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .find(|&(x, y)| {
+                (0..height).all(|oy| (0..width).all(|ox| level.is_walkable(x + ox, y + oy, &self.fighters)))
+            })
     }
 
     pub fn increase_stat(&mut self, inc: StatIncrease) {
         if self.stat_increase_pending {
-            self.fighters[0].stats.apply_increase(inc);
+            self.player_mut().stats.apply_increase(inc);
+            self.stat_increase_pending = false;
+        }
+    }
+
+    pub fn increase_stats_split(&mut self, first: StatIncrease, second: StatIncrease) {
+        if self.stat_increase_pending {
+            let player = self.player_mut();
+            *player.stats.get_mut(first) += 1;
+            *player.stats.get_mut(second) += 1;
             self.stat_increase_pending = false;
         }
     }
 }
 
+/// From this level onwards, the player also picks up treasure from the
+/// tiles next to them, with [RunOptions::treasure_magnet].
+const TREASURE_MAGNET_LEVEL: usize = 2;
+
+/// How much treasure the player can carry without being slowed down,
+/// with [RunOptions::carry_cap].
+pub const CARRY_CAP: i32 = 50;
+
+/// How many events back [Dungeon::undo] can go.
+pub const MAX_UNDO_DEPTH: usize = 10;
+
+/// How many rounds a full oxygen tank lasts.
+pub const MAX_OXYGEN: i32 = 400;
+/// How many rounds it takes to lose a point of health without oxygen.
+const SUFFOCATION_INTERVAL: u64 = 5;
+/// With [RunOptions::oxygen], the player enters each level with at
+/// least this much more health than the last, on top of their starting
+/// health.
+const OXYGEN_HEALTH_PER_LEVEL: i32 = 2;
+
+/// Optional rules for a run, chosen when it starts.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Debug)]
+pub struct RunOptions {
+    /// The player has a limited supply of oxygen, refilled on every
+    /// level, and starts suffocating when it runs out. The deeper levels
+    /// are entered with more health to make up for it. These runs
+    /// aren't ranked on the leaderboards.
+    pub oxygen: bool,
+    /// The die thrown for attacks and lockpicking. Runs with any other
+    /// than the default die aren't ranked on the leaderboards.
+    pub die: Die,
+    /// Carrying more than [CARRY_CAP] treasure makes every step take
+    /// two rounds, which also burns oxygen twice as fast.
+    pub carry_cap: bool,
+    /// The player starts with a [SPAWN_REPAIR_DRONE] that follows
+    /// them between levels and fights on their side. These runs aren't
+    /// ranked on the leaderboards.
+    pub companion: bool,
+    /// The enemies never attack, and the player can't be hurt. These
+    /// runs have their own leaderboard.
+    pub peaceful: bool,
+    /// The last few turns can be taken back with [Dungeon::undo]. These
+    /// runs aren't ranked on the leaderboards.
+    pub undo: bool,
+    /// Treasure is only picked up with [DungeonEvent::PickUp], instead
+    /// of by walking over it. The treasure magnet doesn't pull either.
+    pub manual_pickup: bool,
+    /// From [TREASURE_MAGNET_LEVEL] onwards, the player also picks up
+    /// the treasure next to them.
+    pub treasure_magnet: bool,
+}
+
+impl RunOptions {
+    /// Whether runs with these options can be submitted to the
+    /// leaderboards. Peaceful runs are ranked, but on their own board.
+    pub fn ranked(&self) -> bool {
+        !self.undo && !self.companion && !self.oxygen && self.die == Die::default()
+    }
+}
+
+/// The version of the save format, written into every save. Bump it
+/// whenever [DungeonSave], [RunOptions] or [DungeonEvent] change in a
+/// way that older saves can't be read as. Saves from before the format
+/// was versioned are read as [LegacyDungeonSave].
+const SAVE_FORMAT_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize)]
 pub struct DungeonSave {
+    game_version: String,
+    format_version: u32,
+    seed: u64,
+    events: Vec<DungeonEvent>,
+    options: RunOptions,
+}
+
+/// The save format from before the format was versioned, and before
+/// [RunOptions] existed.
+#[derive(Deserialize)]
+struct LegacyDungeonSave {
     game_version: String,
     seed: u64,
     events: Vec<DungeonEvent>,
 }
 
+impl DungeonSave {
+    pub fn from_bytes(bytes: &[u8]) -> Result<DungeonSave, bincode::Error> {
+        let result = match Options::deserialize::<DungeonSave>(DefaultOptions::new(), bytes) {
+            Ok(save) if save.format_version == SAVE_FORMAT_VERSION => return Ok(save),
+            Ok(save) => Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "unsupported save format version {}",
+                save.format_version
+            )))),
+            Err(err) => Err(err),
+        };
+        match Options::deserialize::<LegacyDungeonSave>(DefaultOptions::new(), bytes) {
+            Ok(legacy) => Ok(DungeonSave {
+                game_version: legacy.game_version,
+                format_version: SAVE_FORMAT_VERSION,
+                seed: legacy.seed,
+                events: legacy.events,
+                options: RunOptions::default(),
+            }),
+            Err(_) => result,
+        }
+    }
+
+    pub fn options(&self) -> RunOptions {
+        self.options
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RunOutcome {
+    Died,
+    Finished,
+    /// Ended early with [DungeonEvent::Abandon].
+    Abandoned,
+    Unfinished,
+}
+
+/// The final statistics of a run, as calculated by
+/// [Dungeon::simulate].
+#[derive(Clone, PartialEq, Debug)]
+pub struct SimResult {
+    pub treasure: i32,
+    pub rounds: u64,
+    pub outcome: RunOutcome,
+    pub levels_reached: usize,
+    pub run_stats: RunStats,
+}
+
 /// The main game-logic runner and bookkeeper.
 pub struct Dungeon {
     seed: u64,
     events: Vec<DungeonEvent>,
+    options: RunOptions,
     state: DungeonState,
+    /// How many of the latest events can still be undone, see
+    /// [Dungeon::undo]. Not saved, so loading starts from zero.
+    undoable_events: usize,
 }
 
 impl Dungeon {
     pub fn new(seed: u64) -> Dungeon {
+        Dungeon::with_options(seed, RunOptions::default())
+    }
+
+    pub fn with_options(seed: u64, options: RunOptions) -> Dungeon {
         Dungeon {
             seed,
             events: Vec::new(),
-            state: DungeonState::new(seed),
+            options,
+            state: DungeonState::new(seed, options),
+            undoable_events: 0,
         }
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Dungeon, bincode::Error> {
-        let save: DungeonSave = Options::deserialize(DefaultOptions::new(), bytes)?;
-        let mut dungeon = Dungeon {
-            seed: save.seed,
-            events: Vec::new(),
-            state: DungeonState::new(save.seed),
-        };
+        Ok(Dungeon::from_save(&DungeonSave::from_bytes(bytes)?))
+    }
+
+    pub fn from_save(save: &DungeonSave) -> Dungeon {
+        let mut dungeon = Dungeon::with_options(save.seed, save.options);
         for event in &save.events {
             dungeon.run_event(*event);
-            dungeon.try_load_next_level(true);
         }
-        Ok(dungeon)
+        dungeon.undoable_events = 0;
+        dungeon
+    }
+
+    /// Takes back the latest event, by replaying the run from the
+    /// start without it. Only for runs with [RunOptions::undo], and at
+    /// most [MAX_UNDO_DEPTH] events back. Returns false if there was
+    /// nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        if !self.options.undo || self.undoable_events == 0 {
+            return false;
+        }
+        let mut events = self.events.clone();
+        events.pop();
+        let undoable_events = self.undoable_events - 1;
+        *self = Dungeon::from_save(&DungeonSave {
+            game_version: String::new(),
+            format_version: SAVE_FORMAT_VERSION,
+            seed: self.seed,
+            events,
+            options: self.options,
+        });
+        self.undoable_events = undoable_events;
+        true
+    }
+
+    pub fn undoable_events(&self) -> usize {
+        self.undoable_events
+    }
+
+    /// Replays the run without any rendering, and returns its final
+    /// statistics. This is how the leaderboard server validates runs.
+    pub fn simulate(save: &DungeonSave) -> SimResult {
+        Dungeon::from_save(save).sim_result()
+    }
+
+    pub fn sim_result(&self) -> SimResult {
+        SimResult {
+            treasure: self.treasure(),
+            rounds: self.round(),
+            outcome: if self.is_game_over() {
+                RunOutcome::Died
+            } else if self.final_treasure_found() {
+                RunOutcome::Finished
+            } else if self.is_abandoned() {
+                RunOutcome::Abandoned
+            } else {
+                RunOutcome::Unfinished
+            },
+            levels_reached: self.level_nth() + 1,
+            run_stats: self.run_stats(),
+        }
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
@@ -192,13 +736,21 @@ impl Dungeon {
             DefaultOptions::new(),
             &DungeonSave {
                 game_version: format!("\r\nexcavation-site-mercury version: {}\r\n", env!("CARGO_PKG_VERSION")),
+                format_version: SAVE_FORMAT_VERSION,
                 seed: self.seed,
                 events: self.events.clone(),
+                options: self.options,
             },
         )
     }
 
     pub fn run_event(&mut self, event: DungeonEvent) {
+        // Live, the next level is loaded once the exit animation is
+        // over, but replays don't animate. Entering the level before
+        // anything else happens keeps the two in sync, wherever the
+        // run was saved.
+        self.try_load_next_level(true);
+
         // First, run the event and save the results:
         let state_before_event = self.state.clone();
         self.apply_event_to_state(event);
@@ -211,35 +763,36 @@ impl Dungeon {
 
         // Finally, register it to the event list.
         self.events.push(event);
+        self.undoable_events = (self.undoable_events + 1).min(MAX_UNDO_DEPTH);
     }
 
     fn apply_event_to_state(&mut self, event: DungeonEvent) {
         use DungeonEvent::*;
         match event {
-            MoveUp => {
-                self.state.move_player(0, -1);
-                self.state.process_turn();
-            }
-            MoveDown => {
-                self.state.move_player(0, 1);
-                self.state.process_turn();
-            }
-            MoveLeft => {
-                self.state.move_player(-1, 0);
-                self.state.process_turn();
-            }
-            MoveRight => {
-                self.state.move_player(1, 0);
-                self.state.process_turn();
-            }
+            MoveUp => self.state.step_player(0, -1),
+            MoveDown => self.state.step_player(0, 1),
+            MoveLeft => self.state.step_player(-1, 0),
+            MoveRight => self.state.step_player(1, 0),
             LevelUp(inc) => self.state.increase_stat(inc),
+            LevelUpSplit(first, second) => self.state.increase_stats_split(first, second),
+            Attack(dx, dy) => {
+                let (dx, dy) = (dx.signum(), dy.signum());
+                if (dx, dy) != (0, 0) {
+                    self.state.player_attack(dx, dy);
+                    self.state.process_turn();
+                }
+            }
+            DropTreasure(amount) => self.state.drop_treasure(amount),
+            Abandon => self.state.abandon(),
+            PickUp => self.state.pick_up_treasure(),
         }
     }
 
     pub fn can_run_events(&self) -> bool {
-        let player = &self.state.fighters[0];
+        let player = self.player();
         self.state.levels[self.state.current_level].get_terrain(player.x, player.y) != Terrain::Exit
             && !self.is_game_over()
+            && !self.is_abandoned()
             && !self.stat_increase_pending()
     }
 
@@ -248,7 +801,11 @@ impl Dungeon {
     }
 
     pub fn is_game_over(&self) -> bool {
-        self.state.fighters[0].stats.health <= 0
+        self.player().stats.health <= 0
+    }
+
+    pub fn is_abandoned(&self) -> bool {
+        self.state.abandoned
     }
 
     pub fn final_treasure_found(&self) -> bool {
@@ -256,7 +813,7 @@ impl Dungeon {
     }
 
     pub fn try_load_next_level(&mut self, skip_animation: bool) {
-        let player = &self.state.fighters[0];
+        let player = self.player();
         let on_exit = self.state.levels[self.state.current_level].get_terrain(player.x, player.y) == Terrain::Exit;
         if on_exit && (!player.is_animating() || skip_animation) {
             self.state.current_level += 1;
@@ -264,10 +821,30 @@ impl Dungeon {
         }
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn level_nth(&self) -> usize {
         self.state.current_level
     }
 
+    /// The details needed to reproduce a bug in this run, for pasting
+    /// into bug reports.
+    pub fn diagnostics(&self) -> String {
+        let player = self.player();
+        format!(
+            "Version: {}\nSeed: {}\nLevel: {}\nRound: {}\nPosition: ({}, {})\nOptions: {:?}",
+            env!("CARGO_PKG_VERSION"),
+            self.seed,
+            self.state.current_level,
+            self.state.round,
+            player.x,
+            player.y,
+            self.options,
+        )
+    }
+
     pub fn level(&self) -> &Level {
         &self.state.levels[self.state.current_level]
     }
@@ -285,7 +862,16 @@ impl Dungeon {
     }
 
     pub fn player(&self) -> &Fighter {
-        &self.state.fighters[0]
+        self.state.player()
+    }
+
+    pub fn player_id(&self) -> usize {
+        self.state.player_id
+    }
+
+    /// Every fighter on the enemy team, living or not.
+    pub fn enemies(&self) -> impl Iterator<Item = &Fighter> {
+        self.state.fighters.iter().filter(|fighter| fighter.team == Team::Enemy)
     }
 
     pub fn log(&self) -> &GameLog {
@@ -296,29 +882,85 @@ impl Dungeon {
         self.state.round
     }
 
+    pub fn run_stats(&self) -> RunStats {
+        self.state.run_stats
+    }
+
     pub fn treasure(&self) -> i32 {
         self.player().stats.treasure
     }
 
+    /// The living fighter at (x, y) on the current level, if any.
+    pub fn fighter_at(&self, x: i32, y: i32) -> Option<&Fighter> {
+        Fighter::living_at(&self.state.fighters, x, y)
+    }
+
+    /// Returns true if the player could step on (x, y), see [Level::is_walkable].
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        self.level().is_walkable(x, y, &self.state.fighters)
+    }
+
+    pub fn living_enemy_count(&self) -> usize {
+        self.enemies().filter(|f| f.stats.health > 0).count()
+    }
+
     pub fn get_fighter(&self, id: usize) -> Option<&Fighter> {
-        if id < self.state.fighters.len() {
-            Some(&self.state.fighters[id])
+        self.state.fighters.iter().find(|fighter| fighter.id == id)
+    }
+
+    /// The player, the fighters in the player's room, and the ones
+    /// the player can see from where they are, e.g. through a door.
+    pub fn get_selectable_fighter_ids(&self) -> Vec<usize> {
+        let player_position = self.player().position();
+        let room = self.level().room_at_position(player_position);
+        self.state
+            .fighters
+            .iter()
+            .filter(|fighter| {
+                let position = fighter.position();
+                fighter.id == self.state.player_id
+                    || room.is_some_and(|rect| rect.contains_point(position))
+                    || self.level().is_visible_from(player_position, position.x, position.y)
+            })
+            .map(|fighter| fighter.id)
+            .collect()
+    }
+
+    /// Ids of the enemies that are going to attack the player next turn
+    /// if the player stays put, see [EnemyAi::threatens].
+    pub fn threatening_fighter_ids(&self) -> Vec<usize> {
+        let player = self.player();
+        self.state
+            .fighters
+            .iter()
+            .zip(&self.state.ais)
+            .filter_map(|(fighter, ai)| ai.as_ref().map(|ai| (fighter, ai)))
+            .filter(|(fighter, ai)| ai.threatens(fighter, player, self.level(), self.state.round))
+            .map(|(fighter, _)| fighter.id)
+            .collect()
+    }
+
+    pub fn options(&self) -> RunOptions {
+        self.options
+    }
+
+    /// The oxygen left and the size of a full tank, if the run uses
+    /// [RunOptions::oxygen].
+    pub fn oxygen(&self) -> Option<(i32, i32)> {
+        if self.options.oxygen {
+            Some((self.state.oxygen, MAX_OXYGEN))
         } else {
             None
         }
     }
 
-    pub fn get_selectable_fighter_ids(&self) -> Vec<usize> {
-        if let Some(rect) = self.level().room_at_position(self.player().position()) {
-            let mut result = Vec::with_capacity(self.state.fighters.len());
-            for fighter in &self.state.fighters {
-                if rect.contains_point(fighter.position()) {
-                    result.push(fighter.id);
-                }
-            }
-            result
+    /// The treasure carried and the most that can be carried without
+    /// slowing down, if the run uses [RunOptions::carry_cap].
+    pub fn carry_load(&self) -> Option<(i32, i32)> {
+        if self.options.carry_cap {
+            Some((self.treasure(), CARRY_CAP))
         } else {
-            vec![0]
+            None
         }
     }
 
@@ -326,3 +968,698 @@ impl Dungeon {
         self.state.level_changed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{SPAWN_ROACH, SPAWN_SLIME};
+    use crate::Name;
+    use rand_core::RngCore;
+    use std::collections::VecDeque;
+
+    fn assert_same_run(a: &Dungeon, b: &Dungeon) {
+        assert_eq!(a.treasure(), b.treasure());
+        assert_eq!(a.round(), b.round());
+        assert_eq!(a.level_nth(), b.level_nth());
+        assert_eq!(a.fighters().len(), b.fighters().len());
+        for (fa, fb) in a.fighters().iter().zip(b.fighters()) {
+            assert_eq!((fa.x, fa.y), (fb.x, fb.y));
+            assert_eq!(fa.stats, fb.stats);
+        }
+        assert_eq!(a.state, b.state);
+    }
+
+    fn round_trip(dungeon: &Dungeon) -> Dungeon {
+        Dungeon::from_bytes(&dungeon.to_bytes().unwrap()).unwrap()
+    }
+
+    fn dungeon_with(options: RunOptions) -> Dungeon {
+        Dungeon::with_options(7, options)
+    }
+
+    fn run_events(dungeon: &mut Dungeon, events: &[DungeonEvent]) {
+        for event in events {
+            dungeon.run_event(*event);
+        }
+    }
+
+    /// The direction of the first step on the shortest path to the
+    /// exit, ignoring fighters. Doors count as walkable, since
+    /// bumping into them opens them.
+    fn step_towards_exit(level: &Level, from: (i32, i32)) -> Option<DungeonEvent> {
+        let mut came_from = std::collections::HashMap::new();
+        let mut queue = VecDeque::new();
+        came_from.insert(from, from);
+        queue.push_back(from);
+        while let Some((x, y)) = queue.pop_front() {
+            if level.get_terrain(x, y) == Terrain::Exit {
+                let mut current = (x, y);
+                while came_from[&current] != from {
+                    current = came_from[&current];
+                }
+                return Some(match (current.0 - from.0, current.1 - from.1) {
+                    (0, -1) => DungeonEvent::MoveUp,
+                    (0, 1) => DungeonEvent::MoveDown,
+                    (-1, 0) => DungeonEvent::MoveLeft,
+                    _ => DungeonEvent::MoveRight,
+                });
+            }
+            for (dx, dy) in &[(0, -1), (0, 1), (-1, 0), (1, 0)] {
+                let next = (x + dx, y + dy);
+                let passable = matches!(
+                    level.get_terrain(next.0, next.1),
+                    Terrain::Floor | Terrain::Door | Terrain::DoorOpen | Terrain::Exit
+                );
+                if passable && !came_from.contains_key(&next) {
+                    came_from.insert(next, (x, y));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn scripted_moves_survive_round_trip() {
+        let script = [
+            DungeonEvent::MoveUp,
+            DungeonEvent::MoveLeft,
+            DungeonEvent::MoveLeft,
+            DungeonEvent::MoveDown,
+            DungeonEvent::MoveRight,
+            DungeonEvent::MoveRight,
+            DungeonEvent::MoveRight,
+            DungeonEvent::MoveUp,
+        ];
+        for seed in 0..8 {
+            let mut dungeon = Dungeon::new(seed);
+            for _ in 0..4 {
+                for event in &script {
+                    dungeon.run_event(*event);
+                    dungeon.try_load_next_level(true);
+                }
+            }
+            assert_same_run(&dungeon, &round_trip(&dungeon));
+        }
+    }
+
+    #[test]
+    fn level_transition_survives_round_trip() {
+        let mut transitioned = false;
+        for seed in 0..32 {
+            let mut dungeon = Dungeon::new(seed);
+            for _ in 0..500 {
+                if dungeon.is_game_over() || dungeon.level_nth() > 0 {
+                    break;
+                }
+                let player = dungeon.player();
+                match step_towards_exit(dungeon.level(), (player.x, player.y)) {
+                    Some(event) => dungeon.run_event(event),
+                    None => break,
+                }
+                dungeon.try_load_next_level(true);
+            }
+
+            if dungeon.level_nth() > 0 {
+                if seed % 2 == 0 {
+                    dungeon.run_event(DungeonEvent::LevelUp(StatIncrease::Arm));
+                } else {
+                    dungeon.run_event(DungeonEvent::LevelUpSplit(StatIncrease::Leg, StatIncrease::Finger));
+                }
+                dungeon.run_event(DungeonEvent::MoveDown);
+                dungeon.run_event(DungeonEvent::MoveUp);
+                assert_same_run(&dungeon, &round_trip(&dungeon));
+                transitioned = true;
+            }
+        }
+        assert!(transitioned, "no seed made it to the second level");
+    }
+
+    #[test]
+    fn saves_on_the_exit_tile_enter_the_next_level_once() {
+        let mut on_exit = false;
+        for seed in 0..32 {
+            let mut dungeon = Dungeon::new(seed);
+            for _ in 0..500 {
+                let player = dungeon.player();
+                if dungeon.is_game_over() || dungeon.level().get_terrain(player.x, player.y) == Terrain::Exit {
+                    break;
+                }
+                match step_towards_exit(dungeon.level(), (player.x, player.y)) {
+                    Some(event) => dungeon.run_event(event),
+                    None => break,
+                }
+            }
+
+            let player = dungeon.player();
+            if dungeon.level().get_terrain(player.x, player.y) == Terrain::Exit {
+                // Saved before the exit animation finished
+                let mut loaded = round_trip(&dungeon);
+                assert_same_run(&dungeon, &loaded);
+                assert_eq!(loaded.level_nth(), 0);
+
+                dungeon.try_load_next_level(true);
+                for event in &[DungeonEvent::LevelUp(StatIncrease::Arm), DungeonEvent::MoveDown] {
+                    dungeon.run_event(*event);
+                    loaded.run_event(*event);
+                }
+                assert_eq!(dungeon.level_nth(), 1);
+                assert_same_run(&dungeon, &loaded);
+                assert_same_run(&dungeon, &round_trip(&dungeon));
+                on_exit = true;
+            }
+        }
+        assert!(on_exit, "no seed made it to the exit");
+    }
+
+    #[test]
+    fn queries_find_the_fighters() {
+        let dungeon = Dungeon::new(5);
+        let player = dungeon.player();
+        assert_eq!(
+            dungeon.fighter_at(player.x, player.y).map(|f| f.id),
+            Some(dungeon.player_id())
+        );
+        assert!(!dungeon.is_walkable(player.x, player.y));
+        for enemy in dungeon.enemies() {
+            assert_eq!(dungeon.fighter_at(enemy.x, enemy.y).map(|f| f.id), Some(enemy.id));
+        }
+        assert_eq!(dungeon.living_enemy_count(), dungeon.fighters().len() - 1);
+    }
+
+    #[test]
+    fn the_player_is_found_by_id() {
+        let mut dungeon = Dungeon::new(5);
+        let player_id = dungeon.player_id();
+        let last = dungeon.fighters().len() - 1;
+        dungeon.state.fighters.swap(0, last);
+        dungeon.state.ais.swap(0, last);
+        assert_eq!(dungeon.player().id, player_id);
+        assert_eq!(dungeon.player().name, Name::Astronaut);
+        assert!(dungeon.enemies().all(|enemy| enemy.id != player_id));
+        assert_eq!(
+            dungeon.get_fighter(player_id).map(|f| f.name.clone()),
+            Some(Name::Astronaut)
+        );
+
+        let round = dungeon.round();
+        dungeon.run_event(DungeonEvent::Attack(1, 0));
+        assert_eq!(dungeon.round(), round + 1);
+        assert_eq!(dungeon.player().id, player_id);
+    }
+
+    #[test]
+    fn big_fighters_block_their_whole_footprint() {
+        let mut dungeon = Dungeon::new(5);
+        let player = dungeon.player().position();
+        let mut spawn = SPAWN_SLIME;
+        spawn.x = player.x + 1;
+        spawn.y = player.y - 1;
+        spawn.size = (2, 2);
+        dungeon.state.spawn_fighter(spawn, false);
+        let id = dungeon.fighters().len() - 1;
+
+        // The bottom-left tile of the 2x2 slime is right of the player
+        assert_eq!(dungeon.fighter_at(player.x + 1, player.y).map(|f| f.id), Some(id));
+        assert_eq!(dungeon.fighter_at(player.x + 2, player.y - 1).map(|f| f.id), Some(id));
+        assert!(!dungeon.is_walkable(player.x + 1, player.y));
+        assert_eq!(dungeon.fighter_at(player.x + 3, player.y).map(|f| f.id), None);
+    }
+
+    // A slime next to the player that every hit deals 6 damage to,
+    // with `behind` on the tile behind it
+    fn heavy_hit_fixture(dungeon: &mut Dungeon, behind: Terrain) -> usize {
+        let player = dungeon.player().position();
+        dungeon.level_mut().set_terrain(player.x + 1, player.y, Terrain::Floor);
+        dungeon.level_mut().set_terrain(player.x + 2, player.y, behind);
+        let mut spawn = SPAWN_SLIME;
+        spawn.x = player.x + 1;
+        spawn.y = player.y;
+        dungeon.state.spawn_fighter(spawn, false);
+        let slime = dungeon.state.fighters.last_mut().unwrap();
+        slime.stats.health = 100;
+        let (id, leg) = (slime.id, slime.stats.leg);
+        // With a 29 point advantage, every roll deals 6 damage
+        dungeon.state.player_mut().stats.arm = leg + 29;
+        assert!(dungeon.fighter_at(player.x + 2, player.y).is_none());
+        id
+    }
+
+    #[test]
+    fn heavy_hits_push_onto_open_floor() {
+        let mut dungeon = Dungeon::new(5);
+        let player = dungeon.player().position();
+        let id = heavy_hit_fixture(&mut dungeon, Terrain::Floor);
+
+        dungeon.run_event(DungeonEvent::Attack(1, 0));
+        let slime = dungeon.fighter_at(player.x + 2, player.y).unwrap();
+        assert_eq!((slime.id, slime.stats.health), (id, 94));
+    }
+
+    #[test]
+    fn heavy_hits_slam_into_walls() {
+        let mut dungeon = Dungeon::new(5);
+        let player = dungeon.player().position();
+        let id = heavy_hit_fixture(&mut dungeon, Terrain::Wall);
+
+        dungeon.run_event(DungeonEvent::Attack(1, 0));
+        let slime = dungeon.fighter_at(player.x + 1, player.y).unwrap();
+        assert_eq!((slime.id, slime.stats.health), (id, 93));
+    }
+
+    #[test]
+    fn status_effects_tick_and_expire() {
+        let mut dungeon = Dungeon::new(11);
+        let health = dungeon.player().stats.health;
+        let position = dungeon.player().position();
+        let poison = StatusEffect {
+            kind: StatusKind::Poison,
+            rounds_left: 3,
+        };
+        let stun = StatusEffect {
+            kind: StatusKind::Stun,
+            rounds_left: 2,
+        };
+        dungeon.state.player_mut().status_effects = vec![poison, stun];
+
+        // The stun takes up this round's move, and wears off at the end of the next
+        for &event in &[
+            DungeonEvent::MoveLeft,
+            DungeonEvent::Attack(1, 1),
+            DungeonEvent::Attack(1, 1),
+        ] {
+            dungeon.run_event(event);
+        }
+        assert_eq!(dungeon.player().position(), position);
+        assert_eq!(dungeon.player().stats.health, health - 3);
+        assert!(dungeon.player().status_effects.is_empty());
+    }
+
+    #[test]
+    fn fighters_never_share_a_tile() {
+        let mut rng = Pcg32::seed_from_u64(3);
+        for seed in 0..5 {
+            let mut dungeon = Dungeon::new(seed);
+            for _ in 0..300 {
+                if !dungeon.can_run_events() {
+                    break;
+                }
+                dungeon.run_event(match rng.next_u32() % 4 {
+                    0 => DungeonEvent::MoveUp,
+                    1 => DungeonEvent::MoveDown,
+                    2 => DungeonEvent::MoveLeft,
+                    _ => DungeonEvent::MoveRight,
+                });
+                let living = dungeon
+                    .fighters()
+                    .iter()
+                    .filter(|f| f.stats.health > 0)
+                    .collect::<Vec<_>>();
+                for (i, a) in living.iter().enumerate() {
+                    for b in &living[i + 1..] {
+                        assert!(
+                            !(0..b.size.1 as i32).any(|y| (0..b.size.0 as i32).any(|x| a.occupies(b.x + x, b.y + y)))
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn attacking_stays_in_place() {
+        let mut dungeon = Dungeon::new(11);
+        let (x, y) = (dungeon.player().x, dungeon.player().y);
+        for &(dx, dy) in &[(1, 1), (-1, 0), (0, 0), (1, -1)] {
+            dungeon.run_event(DungeonEvent::Attack(dx, dy));
+        }
+        assert_eq!((dungeon.player().x, dungeon.player().y), (x, y));
+        // The (0, 0) attack doesn't take a turn
+        assert_eq!(dungeon.round(), 4);
+        assert_same_run(&dungeon, &round_trip(&dungeon));
+    }
+
+    #[test]
+    fn run_options_survive_round_trip() {
+        let options = RunOptions {
+            oxygen: true,
+            die: Die::D4,
+            carry_cap: true,
+            companion: true,
+            peaceful: true,
+            undo: true,
+            manual_pickup: true,
+            treasure_magnet: true,
+        };
+        let mut dungeon = dungeon_with(options);
+        run_events(
+            &mut dungeon,
+            &[DungeonEvent::MoveLeft, DungeonEvent::MoveUp, DungeonEvent::MoveRight],
+        );
+        let loaded = round_trip(&dungeon);
+        assert_eq!(loaded.options(), options);
+        assert_same_run(&dungeon, &loaded);
+    }
+
+    #[test]
+    fn only_runs_with_the_default_rules_are_ranked() {
+        assert!(RunOptions::default().ranked());
+        assert!(RunOptions {
+            peaceful: true,
+            ..RunOptions::default()
+        }
+        .ranked());
+        assert!(!RunOptions {
+            oxygen: true,
+            ..RunOptions::default()
+        }
+        .ranked());
+        assert!(!RunOptions {
+            die: Die::D4,
+            ..RunOptions::default()
+        }
+        .ranked());
+        assert!(!RunOptions {
+            companion: true,
+            ..RunOptions::default()
+        }
+        .ranked());
+        assert!(!RunOptions {
+            undo: true,
+            ..RunOptions::default()
+        }
+        .ranked());
+    }
+
+    #[test]
+    fn oxygen_drains_every_round() {
+        let mut dungeon = dungeon_with(RunOptions {
+            oxygen: true,
+            ..RunOptions::default()
+        });
+        let round = dungeon.round();
+        run_events(&mut dungeon, &[DungeonEvent::MoveUp, DungeonEvent::MoveDown].repeat(5));
+        let rounds = (dungeon.round() - round) as i32;
+        assert_eq!(dungeon.oxygen(), Some((MAX_OXYGEN - rounds, MAX_OXYGEN)));
+    }
+
+    #[test]
+    fn oxygen_runs_enter_deeper_levels_with_more_health() {
+        let mut dungeon = dungeon_with(RunOptions {
+            oxygen: true,
+            ..RunOptions::default()
+        });
+        assert_eq!(dungeon.player().stats.health, stats::PLAYER.health);
+        dungeon.state.current_level = 2;
+        dungeon.state.load_level();
+        let starting_health = stats::PLAYER.health + OXYGEN_HEALTH_PER_LEVEL * 2;
+        assert_eq!(dungeon.player().stats.health, starting_health);
+        assert_eq!(dungeon.player().stats.max_health, starting_health);
+    }
+
+    #[test]
+    fn saves_from_before_run_options_still_load() {
+        let events = vec![DungeonEvent::MoveUp, DungeonEvent::MoveLeft];
+        let legacy_bytes =
+            Options::serialize(DefaultOptions::new(), &(String::from("old version"), 42u64, events)).unwrap();
+        let dungeon = Dungeon::from_bytes(&legacy_bytes).unwrap();
+        assert_eq!(dungeon.options(), RunOptions::default());
+        assert_eq!(dungeon.round(), 3);
+    }
+
+    #[test]
+    fn saves_from_unknown_format_versions_are_rejected() {
+        let future_bytes = Options::serialize(
+            DefaultOptions::new(),
+            &(
+                String::from("future version"),
+                SAVE_FORMAT_VERSION + 1,
+                42u64,
+                vec![DungeonEvent::MoveUp],
+                RunOptions::default(),
+            ),
+        )
+        .unwrap();
+        assert!(Dungeon::from_bytes(&future_bytes).is_err());
+    }
+
+    #[test]
+    fn nothing_hurts_the_player_in_peaceful_runs() {
+        let mut dungeon = dungeon_with(RunOptions {
+            peaceful: true,
+            ..RunOptions::default()
+        });
+        assert!(dungeon.state.ais.iter().flatten().all(|ai| *ai == enemy_ai::PASSIVE));
+        let mut rng = Pcg32::seed_from_u64(7);
+        for _ in 0..300 {
+            dungeon.run_event(match rng.next_u32() % 4 {
+                0 => DungeonEvent::MoveUp,
+                1 => DungeonEvent::MoveDown,
+                2 => DungeonEvent::MoveLeft,
+                _ => DungeonEvent::MoveRight,
+            });
+        }
+        assert_eq!(dungeon.player().stats.health, dungeon.player().stats.max_health);
+    }
+
+    #[test]
+    fn undo_replays_the_run_without_the_last_events() {
+        let events = [
+            DungeonEvent::MoveLeft,
+            DungeonEvent::MoveUp,
+            DungeonEvent::MoveRight,
+            DungeonEvent::MoveDown,
+        ];
+        let mut dungeon = Dungeon::new(7);
+        dungeon.run_event(events[0]);
+        assert!(!dungeon.undo(), "undo is only for runs with the option");
+
+        let options = RunOptions {
+            undo: true,
+            ..RunOptions::default()
+        };
+        let mut dungeon = dungeon_with(options);
+        let mut expected = dungeon_with(options);
+        run_events(&mut dungeon, &events);
+        run_events(&mut expected, &events[..2]);
+        assert!(dungeon.undo());
+        assert!(dungeon.undo());
+        assert_same_run(&dungeon, &expected);
+        assert_eq!(dungeon.events.len(), 2);
+
+        for _ in 0..MAX_UNDO_DEPTH + 5 {
+            dungeon.run_event(DungeonEvent::MoveLeft);
+        }
+        let mut undone = 0;
+        while dungeon.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO_DEPTH);
+    }
+
+    #[test]
+    fn manual_pickup_leaves_treasure_until_picked_up() {
+        let mut dungeon = dungeon_with(RunOptions {
+            manual_pickup: true,
+            ..RunOptions::default()
+        });
+        let (x, y) = (dungeon.player().x, dungeon.player().y);
+        let (dx, dy, event) = [
+            (0, -1, DungeonEvent::MoveUp),
+            (0, 1, DungeonEvent::MoveDown),
+            (-1, 0, DungeonEvent::MoveLeft),
+            (1, 0, DungeonEvent::MoveRight),
+        ]
+        .iter()
+        .copied()
+        .find(|&(dx, dy, _)| dungeon.is_walkable(x + dx, y + dy))
+        .unwrap();
+        let lying_around = dungeon.level().get_treasure(x + dx, y + dy).map_or(0, |t| t.amount);
+        dungeon.state.levels[0].put_treasure(x + dx, y + dy, 5);
+        dungeon.run_event(event);
+        assert_eq!((dungeon.player().x, dungeon.player().y), (x + dx, y + dy));
+        assert_eq!(dungeon.treasure(), 0);
+
+        let round = dungeon.round();
+        dungeon.run_event(DungeonEvent::PickUp);
+        assert_eq!(dungeon.treasure(), lying_around + 5);
+        assert_eq!(dungeon.round(), round);
+        assert_eq!(dungeon.level().get_treasure(x + dx, y + dy), None);
+    }
+
+    #[test]
+    fn the_companion_starts_next_to_the_player_on_the_players_team() {
+        let dungeon = dungeon_with(RunOptions {
+            companion: true,
+            ..RunOptions::default()
+        });
+        let player = dungeon.player();
+        let drone = dungeon
+            .state
+            .fighters
+            .iter()
+            .find(|f| f.name == Name::RepairDrone)
+            .unwrap();
+        assert_eq!(drone.team, Team::Player);
+        assert_eq!((drone.x - player.x).abs() + (drone.y - player.y).abs(), 1);
+        assert!(dungeon.enemies().all(|enemy| enemy.id != drone.id));
+
+        let dungeon = dungeon_with(RunOptions::default());
+        assert!(dungeon.state.fighters.iter().all(|f| f.name != Name::RepairDrone));
+    }
+
+    #[test]
+    fn pending_spawns_are_placed_next_to_where_they_came_from_after_the_turn() {
+        let mut dungeon = Dungeon::new(7);
+        let (x, y) = (dungeon.player().x, dungeon.player().y);
+        let fighters_before = dungeon.state.fighters.len();
+        let current_level = dungeon.state.current_level;
+        dungeon.state.levels[current_level].queue_spawn(FighterSpawn { x, y, ..SPAWN_SLIME });
+        dungeon.state.process_turn();
+
+        assert_eq!(dungeon.state.fighters.len(), fighters_before + 1);
+        let slime = dungeon.state.fighters.last().unwrap();
+        assert_eq!(slime.name, Name::Slime);
+        assert_eq!(slime.id, fighters_before);
+        assert_eq!((slime.x - x).abs() + (slime.y - y).abs(), 1);
+        assert!(dungeon.state.levels[current_level].take_pending_spawns().is_empty());
+    }
+
+    #[test]
+    fn killed_slimes_sometimes_split_in_two_halves() {
+        let dungeon = Dungeon::new(7);
+        let (x, y) = dungeon.state.free_tile_next_to_player().unwrap();
+        let (dx, dy) = (x - dungeon.player().x, y - dungeon.player().y);
+        let half_health = stats::SLIME.max_health / 2;
+        let mut splits = 0;
+        for round in 1..33 {
+            let mut state = dungeon.state.clone();
+            state.round = round;
+            let slime_id = state.fighters.len();
+            let weak_slime = FighterSpawn {
+                x,
+                y,
+                stats: stats::Stats {
+                    health: 1,
+                    ..stats::SLIME
+                },
+                ..SPAWN_SLIME
+            };
+            state.spawn_fighter(weak_slime, false);
+            state.player_attack(dx, dy);
+            state.process_turn();
+
+            assert_eq!(state.fighters[slime_id].stats.health, 0);
+            let halves = state.fighters[slime_id + 1..]
+                .iter()
+                .filter(|f| f.name == Name::Slime && f.stats.max_health == half_health)
+                .collect::<Vec<_>>();
+            assert!(halves.is_empty() || halves.len() == 2);
+            for half in &halves {
+                assert_eq!((half.x - x).abs() + (half.y - y).abs(), 1);
+            }
+            splits += halves.len() / 2;
+        }
+        // With a one in two chance each, some of the 32 slimes split and some don't
+        assert!(splits > 0 && splits < 32);
+    }
+
+    #[test]
+    fn pending_spawns_get_their_ids_in_the_order_they_were_queued() {
+        let mut dungeon = Dungeon::new(7);
+        let (x, y) = (dungeon.player().x, dungeon.player().y);
+        let fighters_before = dungeon.state.fighters.len();
+        let current_level = dungeon.state.current_level;
+        let level = &mut dungeon.state.levels[current_level];
+        level.queue_spawn(FighterSpawn { x, y, ..SPAWN_ROACH });
+        level.queue_spawn(FighterSpawn { x, y, ..SPAWN_SLIME });
+        dungeon.state.process_turn();
+
+        let spawned = &dungeon.state.fighters[fighters_before..];
+        assert_eq!(spawned.len(), 2);
+        assert_eq!((spawned[0].name.clone(), spawned[0].id), (Name::Roach, fighters_before));
+        assert_eq!(
+            (spawned[1].name.clone(), spawned[1].id),
+            (Name::Slime, fighters_before + 1)
+        );
+        assert_ne!((spawned[0].x, spawned[0].y), (spawned[1].x, spawned[1].y));
+    }
+
+    #[test]
+    fn the_player_trades_places_with_the_companion() {
+        let mut dungeon = dungeon_with(RunOptions {
+            companion: true,
+            ..RunOptions::default()
+        });
+        let (player_x, player_y) = (dungeon.player().x, dungeon.player().y);
+        let drone_index = dungeon
+            .state
+            .fighters
+            .iter()
+            .position(|f| f.name == Name::RepairDrone)
+            .unwrap();
+        let (drone_x, drone_y) = (
+            dungeon.state.fighters[drone_index].x,
+            dungeon.state.fighters[drone_index].y,
+        );
+        dungeon.state.move_player(drone_x - player_x, drone_y - player_y);
+
+        assert_eq!((dungeon.player().x, dungeon.player().y), (drone_x, drone_y));
+        let drone = &dungeon.state.fighters[drone_index];
+        assert_eq!((drone.x, drone.y), (player_x, player_y));
+        assert_eq!(drone.stats.health, drone.stats.max_health);
+    }
+
+    #[test]
+    fn overburdened_steps_take_two_rounds() {
+        let mut dungeon = dungeon_with(RunOptions {
+            carry_cap: true,
+            ..RunOptions::default()
+        });
+        dungeon.state.player_mut().stats.treasure = CARRY_CAP + 20;
+        let round = dungeon.round();
+        dungeon.run_event(DungeonEvent::Attack(1, 1));
+        assert_eq!(dungeon.round(), round + 1);
+        dungeon.run_event(DungeonEvent::MoveLeft);
+        assert_eq!(dungeon.round(), round + 3);
+
+        // Dropping takes no time, and is capped to what's carried
+        let position = dungeon.player().position();
+        dungeon.run_event(DungeonEvent::DropTreasure(20));
+        dungeon.run_event(DungeonEvent::DropTreasure(1000));
+        assert_eq!(dungeon.treasure(), 0);
+        assert_eq!(dungeon.round(), round + 3);
+        let dropped = dungeon.level().get_treasure(position.x, position.y).map(|t| t.amount);
+        assert!(dropped.unwrap_or(0) >= CARRY_CAP + 20);
+        dungeon.run_event(DungeonEvent::Attack(1, 1));
+        assert_eq!(dungeon.round(), round + 4);
+    }
+
+    #[test]
+    fn simulation_matches_live_run() {
+        let mut dungeon = Dungeon::new(1234);
+        for event in &[DungeonEvent::MoveLeft, DungeonEvent::MoveUp, DungeonEvent::MoveRight] {
+            dungeon.run_event(*event);
+        }
+        let save = DungeonSave::from_bytes(&dungeon.to_bytes().unwrap()).unwrap();
+        let result = Dungeon::simulate(&save);
+        assert_eq!(result, dungeon.sim_result());
+        assert_eq!(result.outcome, RunOutcome::Unfinished);
+        assert_eq!(result.levels_reached, 1);
+        assert_eq!(result.rounds, 4);
+    }
+
+    #[test]
+    fn abandoned_runs_end_without_time() {
+        let mut dungeon = Dungeon::new(1234);
+        dungeon.run_event(DungeonEvent::MoveLeft);
+        dungeon.run_event(DungeonEvent::Abandon);
+        assert!(dungeon.is_abandoned());
+        assert!(!dungeon.can_run_events());
+
+        let save = DungeonSave::from_bytes(&dungeon.to_bytes().unwrap()).unwrap();
+        let result = Dungeon::simulate(&save);
+        assert_eq!(result, dungeon.sim_result());
+        assert_eq!(result.outcome, RunOutcome::Abandoned);
+    }
+}
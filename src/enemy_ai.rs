@@ -1,11 +1,33 @@
 use crate::{Fighter, GameLog, Level, Terrain};
 use rand_core::RngCore;
 use rand_pcg::Pcg32;
+use sdl2::rect::Point;
 
+/// Replaces the enemies' own AI in peaceful runs.
+pub const PASSIVE: EnemyAi = EnemyAi::new(Personality::Passive);
 pub const SLIME: EnemyAi = EnemyAi::new(Personality::SelfDefense { was_attacked: false });
-pub const ROACH: EnemyAi = EnemyAi::new(Personality::Skitterer);
-pub const ROCKMAN: EnemyAi = EnemyAi::new(Personality::Hunter { distance: 4.0 });
+pub const ROACH: EnemyAi = EnemyAi::new(Personality::Skitterer { direction: None });
+pub const ROCKMAN: EnemyAi = EnemyAi::new(Personality::Hunter {
+    distance: 4.0,
+    last_seen: None,
+    path: Vec::new(),
+});
+
+/// One in this many turns, a skitterer picks a new direction even if
+/// it could keep going.
+const SKITTERER_TURN_CHANCE: u32 = 4;
+
 pub const SENTIENT_METAL: EnemyAi = EnemyAi::new(Personality::Tower { attack_interval: 4 });
+pub const METAL_OVERSEER: EnemyAi = EnemyAi::new(Personality::PhasedTower);
+pub const COMPANION: EnemyAi = EnemyAi::new(Personality::Companion { path: Vec::new() });
+
+/// How many steps away a hunter will try to path towards its target.
+const HUNTER_MAX_PATH_LENGTH: usize = 16;
+/// How far away a companion notices enemies, in tiles.
+const COMPANION_SIGHT_DISTANCE: i32 = 5;
+/// How many tiles a companion lets the player get ahead before
+/// following them.
+const COMPANION_LEASH: i32 = 2;
 
 #[derive(Clone, PartialEq, Debug)]
 enum Personality {
@@ -13,12 +35,33 @@ enum Personality {
     Passive,
     /// Stands still until attacked, and attacks back.
     SelfDefense { was_attacked: bool },
-    /// Runs around randomly.
-    Skitterer,
-    /// Runs towards the player to attack once they're in range.
-    Hunter { distance: f32 },
-    /// Avoids the player, deals damage in a '+' shape periodically.
+    /// Scurries around, mostly keeping its direction, and turning when
+    /// it's blocked or on a whim.
+    Skitterer { direction: Option<(i32, i32)> },
+    /// Runs towards the nearest hostile fighter once one is in range,
+    /// and keeps going towards where it was last seen. The path there
+    /// is kept between turns.
+    Hunter {
+        distance: f32,
+        last_seen: Option<Point>,
+        path: Vec<Point>,
+    },
+    /// Avoids the nearest hostile fighter, deals damage in a '+' shape
+    /// periodically, standing still to warn about it the turn before.
     Tower { attack_interval: u64 },
+    /// Like the Tower, but attacks more often as it gets hurt.
+    PhasedTower,
+    /// Fights on the player's side: goes after the closest enemy in
+    /// sight, and otherwise follows the player around.
+    Companion { path: Vec<Point> },
+}
+
+impl Personality {
+    /// Whether the enemy walks through (and opens) unlocked doors
+    /// instead of staying in its room.
+    fn opens_doors(&self) -> bool {
+        matches!(self, Personality::Hunter { .. } | Personality::Companion { .. })
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -37,9 +80,14 @@ impl EnemyAi {
         }
     }
 
+    /// Takes `fighter`'s turn. The fighter is swapped out of `fighters`
+    /// while it acts. Targets are picked by [crate::Team], `player_id`
+    /// is only needed for following the player around.
+    #[allow(clippy::too_many_arguments)]
     pub fn process(
         &mut self,
         fighter: &mut Fighter,
+        player_id: usize,
         fighters: &mut [Fighter],
         level: &mut Level,
         rng: &mut Pcg32,
@@ -50,6 +98,18 @@ impl EnemyAi {
             return;
         }
 
+        let opens_doors = self.personality.opens_doors();
+        let can_walk = |fighter: &Fighter, fighters: &[Fighter], level: &Level, (dx, dy): (i32, i32)| {
+            let new_x = fighter.x + dx;
+            let new_y = fighter.y + dy;
+            // Hostile fighters aren't in the way, they're targets
+            let enemy_in_way =
+                Fighter::living_at(fighters, new_x, new_y).is_some_and(|f| !fighter.team.is_hostile_to(f.team));
+            let terrain = level.get_terrain(new_x, new_y);
+            let avoided = terrain.enemies_avoid() && !(opens_doors && terrain.is_unlocked_door());
+            let would_move_behind_wall = dy > 0 && level.get_terrain(new_x, new_y + 1) == Terrain::Wall;
+            !enemy_in_way && !avoided && !would_move_behind_wall
+        };
         let mut random_walk = |rng: &mut Pcg32, fighter: &mut Fighter, fighters: &mut [Fighter], level: &mut Level| {
             let d = (rng.next_u32() % 4) as i32;
             let (dx, dy) = match d {
@@ -59,18 +119,8 @@ impl EnemyAi {
                 3 => (0, -1),
                 _ => unreachable!(),
             };
-            let new_x = fighter.x + dx;
-            let new_y = fighter.y + dy;
-            let enemy_in_way = fighters
-                .iter()
-                .skip(1)
-                .filter(|f| f.stats.health > 0)
-                .find(|f| f.x == new_x && f.y == new_y)
-                .is_some();
-            let avoided = level.get_terrain(new_x, new_y).enemies_avoid();
-            let would_move_behind_wall = dy > 0 && level.get_terrain(new_x, new_y + 1) == Terrain::Wall;
-            if !enemy_in_way && !avoided && !would_move_behind_wall {
-                fighter.step(dx, dy, fighters, level, rng, log, round);
+            if can_walk(fighter, fighters, level, (dx, dy)) {
+                fighter.step((dx, dy), fighters, level, rng, log, round);
             }
         };
 
@@ -79,61 +129,252 @@ impl EnemyAi {
             Personality::SelfDefense { ref mut was_attacked } => {
                 if let Some((dx, dy)) = fighter.previously_hit_from {
                     if *was_attacked {
-                        fighter.step(dx, dy, fighters, level, rng, log, round);
+                        fighter.step((dx, dy), fighters, level, rng, log, round);
                         *was_attacked = false;
                         fighter.previously_hit_from = None;
                     } else {
                         *was_attacked = true;
                     }
-                } else if round % (1 + rng.next_u32() as u64 % 20) == 0 {
+                } else if round.is_multiple_of(1 + rng.next_u32() as u64 % 20) {
                     random_walk(rng, fighter, fighters, level);
                 }
             }
-            Personality::Skitterer => random_walk(rng, fighter, fighters, level),
-            Personality::Hunter { distance } => {
-                let player = &fighters[0];
-                let (dx, dy) = (player.x - fighter.x, player.y - fighter.y);
-                let pd = ((dx * dx + dy * dy) as f32).sqrt();
-                if pd <= distance && round % 4 < 2 {
-                    if dy != 0 {
-                        fighter.step(0, dy.signum(), fighters, level, rng, log, round);
-                    } else {
-                        fighter.step(dx.signum(), 0, fighters, level, rng, log, round);
+            Personality::Skitterer { ref mut direction } => {
+                let open_directions = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                    .iter()
+                    .copied()
+                    .filter(|&d| can_walk(fighter, fighters, level, d))
+                    .collect::<Vec<(i32, i32)>>();
+                if open_directions.is_empty() {
+                    *direction = None;
+                    return;
+                }
+                // Momentum stops at anyone in the way, so running into the
+                // player is as random as any other turn
+                let keep_going = direction.is_some_and(|(dx, dy)| {
+                    open_directions.contains(&(dx, dy))
+                        && Fighter::living_at(fighters, fighter.x + dx, fighter.y + dy).is_none()
+                }) && !rng.next_u32().is_multiple_of(SKITTERER_TURN_CHANCE);
+                if !keep_going {
+                    *direction = Some(open_directions[rng.next_u32() as usize % open_directions.len()]);
+                }
+                if let Some((dx, dy)) = *direction {
+                    fighter.step((dx, dy), fighters, level, rng, log, round);
+                }
+            }
+            Personality::Hunter {
+                distance,
+                ref mut last_seen,
+                ref mut path,
+            } => {
+                if let Some(target) = nearest_hostile(fighter, fighters) {
+                    let (dx, dy) = (target.x - fighter.x, target.y - fighter.y);
+                    let pd = ((dx * dx + dy * dy) as f32).sqrt();
+                    if pd <= distance {
+                        *last_seen = Some(target.position());
+                    }
+                }
+
+                if *last_seen == Some(fighter.position()) {
+                    // Lost track of the target
+                    *last_seen = None;
+                    path.clear();
+                }
+
+                if let Some(target) = *last_seen {
+                    if round % 4 < 2 {
+                        hunt_step(Hunt { target, path }, fighter, fighters, level, rng, log, round);
                     }
-                } else if pd > distance && round % 2 == 0 {
+                } else if round.is_multiple_of(2) {
                     random_walk(rng, fighter, fighters, level);
                 }
             }
             Personality::Tower { attack_interval } => {
-                if round % attack_interval == 0 {
-                    fighter.cast_laser_cross(rng, fighters, level, log, round);
-                } else {
-                    // Run away from the player, avoid getting cornered (somewhat)
-                    let player = &fighters[0];
-                    let (dx, dy) = (player.x - fighter.x, player.y - fighter.y);
-                    if dx.abs() < dy.abs() {
-                        if level.get_terrain(fighter.x - dx.signum(), fighter.y).unwalkable() {
-                            if level.get_terrain(fighter.x, fighter.y - dy.signum()).unwalkable() {
-                                fighter.step(0, dy.signum(), fighters, level, rng, log, round);
-                            } else {
-                                fighter.step(0, -dy.signum(), fighters, level, rng, log, round);
-                            }
-                        } else {
-                            fighter.step(-dx.signum(), 0, fighters, level, rng, log, round);
-                        }
-                    } else {
-                        if level.get_terrain(fighter.x, fighter.y - dy.signum()).unwalkable() {
-                            if level.get_terrain(fighter.x - dx.signum(), fighter.y).unwalkable() {
-                                fighter.step(dx.signum(), 0, fighters, level, rng, log, round);
-                            } else {
-                                fighter.step(-dx.signum(), 0, fighters, level, rng, log, round);
-                            }
-                        } else {
-                            fighter.step(0, -dy.signum(), fighters, level, rng, log, round);
-                        }
-                    }
+                tower_turn(attack_interval, fighter, fighters, level, rng, log, round);
+            }
+            Personality::PhasedTower => {
+                let attack_interval = phased_attack_interval(fighter);
+                tower_turn(attack_interval, fighter, fighters, level, rng, log, round);
+            }
+            Personality::Companion { ref mut path } => {
+                let player = match fighters.iter().find(|f| f.id == player_id) {
+                    Some(player) => player.position(),
+                    None => return,
+                };
+                let distance = |point: Point| (point.x - fighter.x).abs() + (point.y - fighter.y).abs();
+                let closest_enemy = nearest_hostile(fighter, fighters)
+                    .map(|f| f.position())
+                    .filter(|&position| distance(position) <= COMPANION_SIGHT_DISTANCE);
+                if let Some(target) = closest_enemy {
+                    hunt_step(Hunt { target, path }, fighter, fighters, level, rng, log, round);
+                } else if distance(player) > COMPANION_LEASH {
+                    let hunt = Hunt { target: player, path };
+                    hunt_step(hunt, fighter, fighters, level, rng, log, round);
                 }
             }
         }
     }
+
+    /// Returns true if the fighter is going to attack the player on
+    /// `round`, assuming the player stays where they are. Doesn't change
+    /// anything, so it can be used to preview the enemies' intents.
+    /// Random walkers can't be predicted, and are never considered
+    /// threatening.
+    pub fn threatens(&self, fighter: &Fighter, player: &Fighter, level: &Level, round: u64) -> bool {
+        if fighter.stats.health <= 0 || player.stats.health <= 0 {
+            return false;
+        }
+
+        let (dx, dy) = (player.x - fighter.x, player.y - fighter.y);
+        match self.personality {
+            Personality::Passive | Personality::Skitterer { .. } | Personality::Companion { .. } => false,
+            Personality::SelfDefense { was_attacked } => {
+                was_attacked && fighter.previously_hit_from == Some((dx, dy)) && dx.abs() + dy.abs() == 1
+            }
+            Personality::Hunter { distance, .. } => {
+                let pd = ((dx * dx + dy * dy) as f32).sqrt();
+                let hunting = pd <= distance && round % 4 < 2;
+                hunting && ((dx == 0 && dy.abs() == 1) || (dy == 0 && dx.abs() == 1))
+            }
+            Personality::Tower { attack_interval } => {
+                round.is_multiple_of(attack_interval) && fighter.laser_cross_hits(level, player.position())
+            }
+            Personality::PhasedTower => {
+                let attack_interval = phased_attack_interval(fighter);
+                round.is_multiple_of(attack_interval) && fighter.laser_cross_hits(level, player.position())
+            }
+        }
+    }
+}
+
+/// Where a hunting fighter is headed, and the path there that it keeps
+/// between turns.
+struct Hunt<'a> {
+    target: Point,
+    path: &'a mut Vec<Point>,
+}
+
+/// Takes one step along the path to the hunt's target, finding a new
+/// path if the old one leads elsewhere or has been blocked. A target
+/// that has only moved a step from where the path ends, or onto the
+/// path, is still followed along the old path.
+fn hunt_step(
+    Hunt { target, path }: Hunt,
+    fighter: &mut Fighter,
+    fighters: &mut [Fighter],
+    level: &mut Level,
+    rng: &mut Pcg32,
+    log: &mut GameLog,
+    round: u64,
+) {
+    let occupied = |fighters: &[Fighter], point: Point| {
+        point != target && Fighter::living_at(fighters, point.x, point.y).is_some()
+    };
+
+    if let Some(on_path) = path.iter().position(|&point| point == target) {
+        path.truncate(on_path + 1);
+    } else if let Some(&end) = path.last() {
+        let next_to_end = (end.x - target.x).abs() + (end.y - target.y).abs() == 1;
+        if next_to_end && path.len() < HUNTER_MAX_PATH_LENGTH {
+            path.push(target);
+        }
+    }
+
+    let path_still_valid = path.last() == Some(&target)
+        && path.first().is_some_and(|next| {
+            (next.x - fighter.x).abs() + (next.y - fighter.y).abs() == 1 && !occupied(fighters, *next)
+        });
+    if !path_still_valid {
+        // Hunters can open doors, but not pick locks
+        let new_path = level.find_path(fighter.position(), target, HUNTER_MAX_PATH_LENGTH, |point, terrain| {
+            (!terrain.enemies_avoid() || terrain.is_unlocked_door()) && !occupied(fighters, point)
+        });
+        *path = new_path.unwrap_or_default();
+    }
+
+    if let Some(next) = path.first().cloned() {
+        fighter.step(
+            (next.x - fighter.x, next.y - fighter.y),
+            fighters,
+            level,
+            rng,
+            log,
+            round,
+        );
+        if fighter.position() == next {
+            path.remove(0);
+        }
+    }
+}
+
+/// The closest living fighter that `fighter`'s team is hostile to. Ties
+/// go to the one earlier in `fighters`, to keep replays deterministic.
+fn nearest_hostile<'a>(fighter: &Fighter, fighters: &'a [Fighter]) -> Option<&'a Fighter> {
+    fighters
+        .iter()
+        .filter(|f| fighter.team.is_hostile_to(f.team) && f.stats.health > 0)
+        .min_by_key(|f| (f.x - fighter.x).pow(2) + (f.y - fighter.y).pow(2))
+}
+
+fn phased_attack_interval(fighter: &Fighter) -> u64 {
+    let health = fighter.stats.health;
+    let max_health = fighter.stats.max_health;
+    if health > max_health * 2 / 3 {
+        4
+    } else if health > max_health / 3 {
+        3
+    } else {
+        2
+    }
+}
+
+fn tower_turn(
+    attack_interval: u64,
+    fighter: &mut Fighter,
+    fighters: &mut [Fighter],
+    level: &mut Level,
+    rng: &mut Pcg32,
+    log: &mut GameLog,
+    round: u64,
+) {
+    if round.is_multiple_of(attack_interval) {
+        fighter.cast_laser_cross(rng, fighters, level, log, round);
+    } else if (round + 1).is_multiple_of(attack_interval) {
+        // Stay still and warn the player about next turn's attack
+        fighter.telegraph_laser_cross(level);
+    } else {
+        if let Some((dx, dy)) = tower_flee_direction(fighter, fighters, level) {
+            fighter.step((dx, dy), fighters, level, rng, log, round);
+        }
+    }
+}
+
+/// The step that gets the tower furthest from the nearest hostile
+/// fighter, preferring tiles with more room around them so it doesn't
+/// run into a corner. None if no free tile is further away, the tower
+/// stands still then.
+fn tower_flee_direction(fighter: &Fighter, fighters: &[Fighter], level: &Level) -> Option<(i32, i32)> {
+    const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+    let threat = nearest_hostile(fighter, fighters)?;
+    let distance = |x: i32, y: i32| (threat.x - x).pow(2) + (threat.y - y).pow(2);
+    let current_distance = distance(fighter.x, fighter.y);
+    let open_neighbors = |x: i32, y: i32| {
+        DIRECTIONS
+            .iter()
+            .filter(|(dx, dy)| !level.get_terrain(x + dx, y + dy).unwalkable())
+            .count()
+    };
+
+    DIRECTIONS
+        .iter()
+        .filter(|&&(dx, dy)| fighter.can_step(dx, dy, fighters, level))
+        .map(|&(dx, dy)| {
+            let (x, y) = (fighter.x + dx, fighter.y + dy);
+            ((dx, dy), distance(x, y), open_neighbors(x, y))
+        })
+        .filter(|&(_, new_distance, _)| new_distance > current_distance)
+        // max_by_key picks the last of equals, so reverse to keep the first
+        .rev()
+        .max_by_key(|&(_, new_distance, open)| (new_distance, open))
+        .map(|(direction, _, _)| direction)
 }
@@ -1,14 +1,25 @@
+use crate::level::{FighterSpawn, SPAWN_SLIME};
 use crate::{
-    interface, stats, Camera, GameLog, Level, LocalizableString, Name, StatIncrease, Stats, Terrain, TileGraphic,
-    TilePainter, TILE_STRIDE,
+    interface, stats, AnimationSpeed, AttackOutcome, Camera, Die, GameLog, Level, LocalizableString, LootTable, Name,
+    StatIncrease, Stats, StatusEffect, StatusKind, Terrain, TileGraphic, TilePainter, TILE_STRIDE,
 };
-use rand_core::RngCore;
+use rand_core::{RngCore, SeedableRng};
 use rand_pcg::Pcg32;
 use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
 use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 
+/// Slimes with at least this much max health can split in two when
+/// incapacitated, each half getting half the max health.
+const SLIME_SPLIT_MIN_HEALTH: i32 = 4;
+/// One in this many slimes that can split, do.
+const SLIME_SPLIT_CHANCE: u32 = 2;
+/// The stream of the split roll's rng, so that it's separate from the
+/// loot rolls even though both are seeded the same.
+const SLIME_SPLIT_RNG_STREAM: u64 = 0x51_1e;
+
 #[derive(Clone, Debug)]
 struct ParticleEffect {
     x: i32,
@@ -50,6 +61,51 @@ struct Animation {
     move_progress: f32,
     flying_time: f32,
     descent_progress: f32,
+    idle_time: f32,
+    /// Direction away from the latest attacker, and how much of the
+    /// knock-back lurch is left, from 1 to 0.
+    knockback_x: i32,
+    knockback_y: i32,
+    knockback_progress: f32,
+    /// The health shown by the health bar, draining towards the actual
+    /// health after damage. None until the first frame.
+    displayed_health: Option<f32>,
+    health_drain_speed: f32,
+    last_health: Option<i32>,
+    /// How much of the white flash on the health bar is left after
+    /// taking damage, from 1 to 0.
+    health_flash: f32,
+}
+
+/// How a living fighter moves while standing still, see
+/// [Fighter::idle_style].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IdleStyle {
+    Still,
+    /// Slowly squishes and stretches, like a slime.
+    Pulse,
+    /// Jerks to the side every now and then, like a roach.
+    Twitch,
+    /// A slow, heavy rise and fall, like a rockman's breathing.
+    Breathe,
+}
+
+/// Which side a fighter is on. Fighters don't hit their own team, and
+/// the enemy AI goes after the fighters it's hostile to.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub enum Team {
+    /// The player and their allies.
+    Player,
+    Enemy,
+    /// Goes after no one, and no one goes after it, but it can still
+    /// be hit by walking into it.
+    Neutral,
+}
+
+impl Team {
+    pub fn is_hostile_to(self, other: Team) -> bool {
+        matches!((self, other), (Team::Player, Team::Enemy) | (Team::Enemy, Team::Player))
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -64,10 +120,24 @@ pub struct Fighter {
     pub id: usize,
     pub name: Name,
     pub tile: Option<TileGraphic>,
+    pub team: Team,
     pub x: i32,
     pub y: i32,
     pub stats: Stats,
     pub previously_hit_from: Option<(i32, i32)>,
+    /// Size of the sprite relative to the footprint, see [FighterSpawn::scale].
+    pub scale: f32,
+    /// The footprint in tiles, see [FighterSpawn::size].
+    pub size: (u32, u32),
+    pub status_effects: Vec<StatusEffect>,
+    /// See [FighterSpawn::loot].
+    pub loot: Option<LootTable>,
+    /// Takes no damage from attacks, like the player in
+    /// [RunOptions::peaceful](crate::RunOptions::peaceful) runs.
+    pub invulnerable: bool,
+    /// The die thrown for this fighter's attacks and lockpicking, see
+    /// [RunOptions::die](crate::RunOptions::die).
+    pub die: Die,
     animation: RefCell<Animation>,
     experience: Option<Experience>,
 }
@@ -84,38 +154,198 @@ impl Fighter {
             id,
             name,
             tile: Some(tile),
+            team: Team::Enemy,
             x,
             y,
             stats,
             previously_hit_from: None,
+            scale: 1.0,
+            size: (1, 1),
+            status_effects: Vec::new(),
+            loot: None,
+            invulnerable: false,
+            die: Die::default(),
             animation: RefCell::new(Animation::default()),
             experience: if levels_up { Some(Experience::default()) } else { None },
         }
     }
 
+    /// Stands in for a fighter swapped out of the fighter list. The id
+    /// is one no real fighter has, so id lookups never find the dummy.
     pub fn dummy() -> Fighter {
         Fighter {
-            id: 0,
+            id: usize::MAX,
             name: Name::Dummy,
             tile: None,
+            team: Team::Neutral,
             x: 0,
             y: 0,
             stats: stats::DUMMY,
             previously_hit_from: None,
+            scale: 1.0,
+            size: (1, 1),
+            status_effects: Vec::new(),
+            loot: None,
+            invulnerable: false,
+            die: Die::default(),
             animation: RefCell::new(Animation::default()),
             experience: None,
         }
     }
 
+    /// The living fighter standing at (x, y) among `fighters`, if any.
+    /// This is the one check for whether a tile is taken. Dead fighters
+    /// are walkable, and neither does the dummy swapped in for the
+    /// fighter taking its turn count.
+    pub fn living_at(fighters: &[Fighter], x: i32, y: i32) -> Option<&Fighter> {
+        fighters
+            .iter()
+            .find(|fighter| fighter.tile.is_some() && fighter.stats.health > 0 && fighter.occupies(x, y))
+    }
+
+    /// Moves the fighter to (x, y) without it taking a step of its own,
+    /// like when the player trades places with an ally.
+    pub fn displace_to(&mut self, x: i32, y: i32) {
+        {
+            let mut animation = self.animation.borrow_mut();
+            animation.move_from_x = self.x;
+            animation.move_from_y = self.y;
+            animation.move_progress = 1.0;
+        }
+        self.x = x;
+        self.y = y;
+    }
+
+    /// Returns true if (x, y) is one of the tiles of the fighter's
+    /// footprint.
+    pub fn occupies(&self, x: i32, y: i32) -> bool {
+        let (width, height) = (self.size.0 as i32, self.size.1 as i32);
+        x >= self.x && x < self.x + width && y >= self.y && y < self.y + height
+    }
+
+    /// Returns true if every tile the fighter would move onto is free
+    /// of walls, living fighters and the terrain enemies avoid.
+    pub fn can_step(&self, dx: i32, dy: i32, fighters: &[Fighter], level: &Level) -> bool {
+        self.tiles_entered(dx, dy)
+            .iter()
+            .all(|&(x, y)| level.is_walkable(x, y, fighters) && !level.get_terrain(x, y).enemies_avoid())
+    }
+
+    /// The tiles the fighter would newly cover by moving (dx, dy).
+    fn tiles_entered(&self, dx: i32, dy: i32) -> Vec<(i32, i32)> {
+        let (width, height) = (self.size.0 as i32, self.size.1 as i32);
+        let mut tiles = Vec::with_capacity((width * height) as usize);
+        for y in self.y + dy..self.y + dy + height {
+            for x in self.x + dx..self.x + dx + width {
+                if !self.occupies(x, y) {
+                    tiles.push((x, y));
+                }
+            }
+        }
+        tiles
+    }
+
     pub fn position(&self) -> Point {
         Point::new(self.x, self.y)
     }
 
+    /// The status effect this fighter's hits leave on the defender.
+    pub fn inflicts(&self) -> Option<StatusEffect> {
+        let (kind, rounds_left) = match self.tile {
+            Some(TileGraphic::Slime) => (StatusKind::Poison, 3),
+            Some(TileGraphic::Rockman) => (StatusKind::Stun, 2),
+            Some(TileGraphic::SentientMetal) => (StatusKind::Burning, 3),
+            _ => return None,
+        };
+        Some(StatusEffect { kind, rounds_left })
+    }
+
+    pub fn has_status(&self, kind: StatusKind) -> bool {
+        self.status_effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// Adds the effect, or lengthens the existing one of the same kind.
+    pub fn add_status_effect(&mut self, effect: StatusEffect, log: &mut GameLog, round: u64) {
+        if self.stats.health == 0 {
+            return;
+        }
+        if let Some(existing) = self.status_effects.iter_mut().find(|e| e.kind == effect.kind) {
+            existing.rounds_left = existing.rounds_left.max(effect.rounds_left);
+        } else {
+            self.status_effects.push(effect);
+            log.combat(
+                round,
+                LocalizableString::SomeoneGotStatus {
+                    name: self.name.clone(),
+                    status: effect.kind,
+                },
+            );
+        }
+    }
+
+    /// Deals the damage of the status effects, and counts down their
+    /// rounds, removing the ones that ran out. Called at the end of
+    /// every round.
+    pub fn tick_status_effects(&mut self, level: &mut Level, log: &mut GameLog, round: u64) {
+        if self.stats.health == 0 {
+            self.status_effects.clear();
+            return;
+        }
+        for effect in self.status_effects.clone() {
+            let damage = effect.kind.damage_per_round();
+            if damage > 0 && self.stats.health > 0 {
+                log.combat(
+                    round,
+                    LocalizableString::SomeoneHurtByStatus {
+                        name: self.name.clone(),
+                        status: effect.kind,
+                        damage,
+                    },
+                );
+                self.lose_health(damage, level, log, round);
+            }
+        }
+        for effect in &mut self.status_effects {
+            effect.rounds_left = effect.rounds_left.saturating_sub(1);
+        }
+        self.status_effects.retain(|effect| effect.rounds_left > 0);
+    }
+
+    /// Progress towards the next trained +1 in arm, leg and finger,
+    /// each between 0 and 1. None for fighters that don't train.
+    pub fn experience_ratios(&self) -> Option<(f32, f32, f32)> {
+        self.experience.as_ref().map(|exp| (exp.arm, exp.leg, exp.finger))
+    }
+
+    /// How many more hits, steps or picked locks until the stat goes up
+    /// by training. None for fighters that don't train.
+    pub fn actions_until_trained(&self, stat: StatIncrease) -> Option<u32> {
+        let (arm, leg, finger) = self.experience_ratios()?;
+        let ratio = match stat {
+            StatIncrease::Arm => arm,
+            StatIncrease::Leg => leg,
+            StatIncrease::Finger => finger,
+        };
+        let actions = actions_per_training(stat, self.stats.get(stat));
+        Some(((1.0 - ratio) * actions).ceil().max(1.0) as u32)
+    }
+
+    pub fn idle_style(&self) -> IdleStyle {
+        match self.tile {
+            Some(TileGraphic::Slime) => IdleStyle::Pulse,
+            Some(TileGraphic::Roach) => IdleStyle::Twitch,
+            Some(TileGraphic::Rockman) => IdleStyle::Breathe,
+            _ => IdleStyle::Still,
+        }
+    }
+
     pub fn is_animating(&self) -> bool {
         self.animation.borrow().move_progress > 0.0
     }
 
-    pub fn animate(&self, delta_time: f32, level: &Level) {
+    pub fn animate(&self, delta_time: f32, level: &Level, speed: AnimationSpeed) {
+        let delta_time = delta_time * speed.multiplier();
+        let reduce_motion = speed.reduce_motion();
         let exit_animation = level.get_terrain(self.x, self.y) == Terrain::Exit;
         let mut animation = self.animation.borrow_mut();
 
@@ -124,6 +354,18 @@ impl Fighter {
         }
         animation.particles.retain(|particle| particle.opacity > 0.0);
 
+        // The lost health lingers on the health bar for a moment
+        let health = self.stats.health;
+        let displayed_health = animation.displayed_health.unwrap_or(health as f32);
+        if animation.last_health.is_some_and(|last_health| health < last_health) {
+            animation.health_flash = 1.0;
+            animation.health_drain_speed = (displayed_health - health as f32) / 0.3;
+        }
+        animation.last_health = Some(health);
+        let drained = displayed_health - animation.health_drain_speed * delta_time;
+        animation.displayed_health = Some(drained.max(health as f32));
+        animation.health_flash = (animation.health_flash - delta_time / 0.15).max(0.0);
+
         if animation.move_progress > 0.0 {
             let duration = if exit_animation {
                 0.35
@@ -139,7 +381,7 @@ impl Fighter {
             animation.offset_x = ((dx as f32 * animation.move_progress.min(1.0)) * TILE_STRIDE as f32) as i32;
             animation.offset_y = ((dy as f32 * animation.move_progress.min(1.0)) * TILE_STRIDE as f32) as i32;
 
-            if !self.stats.flying {
+            if !self.stats.flying && !reduce_motion {
                 let f = |x: f32| 1.0 + (x * (4.0 - 4.0 * x)) * 0.05;
                 let move_squish_width_ratio = f(animation.move_progress.min(1.0));
                 animation.width_inc = (TILE_STRIDE as f32 * move_squish_width_ratio) as i32 - TILE_STRIDE;
@@ -161,13 +403,55 @@ impl Fighter {
             animation.offset_y -= TILE_STRIDE / 4;
         }
 
+        if reduce_motion {
+            animation.knockback_progress = 0.0;
+        } else if animation.knockback_progress > 0.0 {
+            animation.knockback_progress = (animation.knockback_progress - delta_time / 0.25).max(0.0);
+            // Lurches away from the attacker and back
+            let p = animation.knockback_progress;
+            let lurch = (p * (1.0 - p) * 4.0 * TILE_STRIDE as f32 * 0.12) as i32;
+            animation.offset_x += animation.knockback_x * lurch;
+            animation.offset_y += animation.knockback_y * lurch;
+        }
+
+        if self.stats.health > 0 && animation.move_progress == 0.0 && !self.stats.flying && !reduce_motion {
+            animation.idle_time += delta_time;
+            // Offset by id so that fighters of the same kind don't move in sync
+            let t = animation.idle_time + self.id as f32 * 0.37;
+            match self.idle_style() {
+                IdleStyle::Still => {}
+                IdleStyle::Pulse => {
+                    let ratio = 1.0 + (t * 3.0).sin() * 0.04;
+                    animation.width_inc = (TILE_STRIDE as f32 * ratio) as i32 - TILE_STRIDE;
+                    animation.height_inc = (TILE_STRIDE as f32 / ratio) as i32 - TILE_STRIDE;
+                    animation.offset_x -= animation.width_inc / 2;
+                    animation.offset_y -= animation.height_inc;
+                }
+                IdleStyle::Twitch => {
+                    // A short jitter at the start of every 1.7 second cycle
+                    let cycle = t % 1.7;
+                    if cycle < 0.12 {
+                        animation.offset_x += if cycle < 0.06 { 2 } else { -2 };
+                    }
+                }
+                IdleStyle::Breathe => {
+                    let height_inc = (((t * 1.2).sin() + 1.0) * TILE_STRIDE as f32 * 0.015) as i32;
+                    animation.height_inc = height_inc;
+                    animation.offset_y -= height_inc;
+                }
+            }
+        }
+
         if self.stats.flying && self.stats.health > 0 {
             animation.flying_time += delta_time;
         } else if animation.descent_progress < 1.0 {
             animation.descent_progress = (animation.descent_progress + delta_time * 2.0).min(1.0);
         }
-        animation.offset_y +=
-            (((animation.flying_time * 4.0).cos() - 1.0) * 8.0 * (1.0 - animation.descent_progress)) as i32;
+        // The bob's phase comes from the id, not from how long the game
+        // has run, so flyers don't bob in sync after a load
+        let bob_time = animation.flying_time + self.id as f32 * 0.37;
+        let bob_height = if reduce_motion { 0.0 } else { 8.0 };
+        animation.offset_y += (((bob_time * 4.0).cos() - 1.0) * bob_height * (1.0 - animation.descent_progress)) as i32;
 
         let scale = if exit_animation {
             animation.move_progress.min(1.0).sqrt()
@@ -184,8 +468,7 @@ impl Fighter {
 
     pub fn step(
         &mut self,
-        dx: i32,
-        dy: i32,
+        (dx, dy): (i32, i32),
         fighters: &mut [Fighter],
         level: &mut Level,
         rng: &mut Pcg32,
@@ -193,40 +476,34 @@ impl Fighter {
         round: u64,
     ) {
         let (new_x, new_y) = (self.x + dx, self.y + dy);
-        let mut hit_something = false;
-
-        for hit_fighter in fighters
-            .iter_mut()
-            .filter(|fighter| fighter.x == new_x && fighter.y == new_y && fighter.stats.health > 0)
-        {
-            hit_something = !hit_fighter.walkable();
-            hit_fighter.take_damage(&self, level, rng, log, round);
-            hit_fighter.previously_hit_from = Some((-dx, -dy));
-
-            if let Some(exp) = &mut self.experience {
-                exp.arm += 1.0 / (10.0 + (self.stats.arm - 10) as f32 * 5.0);
-                while exp.arm >= 1.0 {
-                    exp.arm -= 1.0;
-                    self.stats.arm += 1;
-                    log.level_up(
-                        round,
-                        LocalizableString::StatIncreaseByTraining {
-                            stat: StatIncrease::Arm,
-                            name: self.name.clone(),
-                        },
-                    );
-                }
-            }
-        }
+        let mut hit_something = self.hit_fighters_in_direction((dx, dy), fighters, level, rng, log, round);
 
         let hit_terrain = level.get_terrain(new_x, new_y);
         if hit_terrain.unwalkable() {
             hit_something = true;
         }
-        if hit_terrain == Terrain::Door {
+        if self
+            .tiles_entered(dx, dy)
+            .iter()
+            .any(|&(x, y)| Fighter::living_at(fighters, x, y).is_some())
+        {
+            // Whatever was hit is still standing there, and nobody shares a tile
+            hit_something = true;
+        }
+        if self.size != (1, 1) {
+            // Big fighters are too clumsy for doors and locks, they just
+            // need all of the new tiles to be free
+            if self
+                .tiles_entered(dx, dy)
+                .iter()
+                .any(|&(x, y)| level.get_terrain(x, y).unwalkable())
+            {
+                hit_something = true;
+            }
+        } else if hit_terrain == Terrain::Door {
             level.open_door(new_x, new_y);
         } else if let Terrain::LockedDoor { roll_threshold } = hit_terrain {
-            let roll = 1 + (rng.next_u32() % 6) as i32;
+            let roll = self.die.roll_die(rng);
             let finger = self.stats.finger;
             if finger + roll >= roll_threshold {
                 level.open_door(new_x, new_y);
@@ -239,20 +516,7 @@ impl Fighter {
                     },
                 );
 
-                if let Some(exp) = &mut self.experience {
-                    exp.finger += 1.0 / (2.0 + (self.stats.finger - 10) as f32 * 2.0);
-                    while exp.finger >= 1.0 {
-                        exp.finger -= 1.0;
-                        self.stats.finger += 1;
-                        log.level_up(
-                            round,
-                            LocalizableString::StatIncreaseByTraining {
-                                stat: StatIncrease::Finger,
-                                name: self.name.clone(),
-                            },
-                        );
-                    }
-                }
+                self.train(StatIncrease::Finger, log, round);
             } else {
                 log.lockpicking(
                     round,
@@ -260,53 +524,159 @@ impl Fighter {
                         roll_threshold,
                         roll,
                         finger,
+                        die: self.die,
                     },
                 );
             }
         }
 
-        let nth_fighter = fighters.iter().position(|f| f.stats == stats::DUMMY).unwrap_or(0);
-        let anim_offset = nth_fighter as f32 / fighters.len() as f32;
-
-        {
-            let mut animation = self.animation.borrow_mut();
-            animation.move_from_x = self.x;
-            animation.move_from_y = self.y;
-            animation.move_progress = 1.0 + anim_offset;
-            if dx < 0 {
-                animation.flip_h = true;
-            } else if dx > 0 {
-                animation.flip_h = false;
-            }
-        }
+        self.start_move_animation(dx, fighters);
 
         if !hit_something {
             self.x = new_x;
             self.y = new_y;
 
-            if let Some(exp) = &mut self.experience {
-                exp.leg += 1.0 / (50.0 + (self.stats.leg - 10) as f32 * 50.0);
-                while exp.leg >= 1.0 {
-                    exp.leg -= 1.0;
-                    self.stats.leg += 1;
-                    log.level_up(
-                        round,
-                        LocalizableString::StatIncreaseByTraining {
-                            stat: StatIncrease::Leg,
-                            name: self.name.clone(),
-                        },
-                    );
-                }
+            self.train(StatIncrease::Leg, log, round);
+        }
+    }
+
+    /// Attacks in the direction without moving, even if the tile is
+    /// empty or becomes walkable. Unlike [Fighter::step], diagonals work.
+    pub fn attack(
+        &mut self,
+        (dx, dy): (i32, i32),
+        fighters: &mut [Fighter],
+        level: &mut Level,
+        rng: &mut Pcg32,
+        log: &mut GameLog,
+        round: u64,
+    ) {
+        self.hit_fighters_in_direction((dx, dy), fighters, level, rng, log, round);
+        self.start_move_animation(dx, fighters);
+    }
+
+    /// Hits the living fighters at (x + dx, y + dy). Returns true if
+    /// there were any.
+    fn hit_fighters_in_direction(
+        &mut self,
+        (dx, dy): (i32, i32),
+        fighters: &mut [Fighter],
+        level: &mut Level,
+        rng: &mut Pcg32,
+        log: &mut GameLog,
+        round: u64,
+    ) -> bool {
+        let targets = self.tiles_entered(dx, dy);
+        let mut hit_something = false;
+        let hit_indices = fighters
+            .iter()
+            .enumerate()
+            .filter(|(_, fighter)| fighter.team != self.team && fighter.stats.health > 0)
+            .filter(|(_, fighter)| targets.iter().any(|&(x, y)| fighter.occupies(x, y)))
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        for i in hit_indices {
+            let hit_fighter = &mut fighters[i];
+            hit_something = !hit_fighter.walkable();
+            let damage = hit_fighter.take_damage(self, level, rng, log, round);
+            hit_fighter.previously_hit_from = Some((-dx, -dy));
+            if damage >= stats::PUSHING_DAMAGE && hit_fighter.stats.health > 0 {
+                Fighter::push(fighters, i, dx, dy, level, log, round);
             }
+
+            self.train(StatIncrease::Arm, log, round);
         }
+        hit_something
     }
 
-    fn take_damage(&mut self, from: &Fighter, level: &mut Level, rng: &mut Pcg32, log: &mut GameLog, round: u64) {
-        let hit_roll = (rng.next_u32() % 6) as i32 + 1;
-        let modifier = from.stats.arm - self.stats.leg;
-        let damage = if hit_roll >= -modifier {
-            let damage = 1 + (hit_roll + modifier) / 6;
-            self.stats.health = (self.stats.health - damage).max(0);
+    /// Shoves the fighter at `index` one tile in the direction (dx, dy)
+    /// after a heavy hit. Fighters only slide onto floors and open
+    /// doors, and if anything else is in the way, they're slammed
+    /// against it for an extra point of damage instead.
+    fn push(
+        fighters: &mut [Fighter],
+        index: usize,
+        dx: i32,
+        dy: i32,
+        level: &mut Level,
+        log: &mut GameLog,
+        round: u64,
+    ) {
+        let blocked = fighters[index].tiles_entered(dx, dy).into_iter().any(|(x, y)| {
+            let open = matches!(level.get_terrain(x, y), Terrain::Floor | Terrain::DoorOpen);
+            !open || Fighter::living_at(fighters, x, y).is_some()
+        });
+
+        let pushed = &mut fighters[index];
+        if blocked {
+            log.combat(round, LocalizableString::SomeoneWasSlammed(pushed.name.clone()));
+            pushed.lose_health(1, level, log, round);
+        } else {
+            {
+                let mut animation = pushed.animation.borrow_mut();
+                animation.move_from_x = pushed.x;
+                animation.move_from_y = pushed.y;
+                animation.move_progress = 1.0;
+            }
+            pushed.x += dx;
+            pushed.y += dy;
+            log.combat(round, LocalizableString::SomeoneWasPushed(pushed.name.clone()));
+        }
+    }
+
+    fn start_move_animation(&self, dx: i32, fighters: &[Fighter]) {
+        // Staggered by id, so fighters moving on the same turn don't move in lockstep
+        let anim_offset = self.id as f32 / fighters.len().max(1) as f32;
+
+        let mut animation = self.animation.borrow_mut();
+        animation.move_from_x = self.x;
+        animation.move_from_y = self.y;
+        animation.move_progress = 1.0 + anim_offset;
+        if dx < 0 {
+            animation.flip_h = true;
+        } else if dx > 0 {
+            animation.flip_h = false;
+        }
+    }
+
+    /// Counts one hit, step or picked lock towards the stat, raising it
+    /// by one when enough have been done. Only for fighters that train.
+    fn train(&mut self, stat: StatIncrease, log: &mut GameLog, round: u64) {
+        let progress = 1.0 / actions_per_training(stat, self.stats.get(stat));
+        if let Some(exp) = &mut self.experience {
+            let exp_stat = match stat {
+                StatIncrease::Arm => &mut exp.arm,
+                StatIncrease::Leg => &mut exp.leg,
+                StatIncrease::Finger => &mut exp.finger,
+            };
+            *exp_stat += progress;
+            while *exp_stat >= 1.0 {
+                *exp_stat -= 1.0;
+                *self.stats.get_mut(stat) += 1;
+                log.level_up(
+                    round,
+                    LocalizableString::StatIncreaseByTraining {
+                        stat,
+                        name: self.name.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    fn take_damage(
+        &mut self,
+        from: &Fighter,
+        level: &mut Level,
+        rng: &mut Pcg32,
+        log: &mut GameLog,
+        round: u64,
+    ) -> i32 {
+        if self.invulnerable {
+            return 0;
+        }
+        let hit_roll = from.die.roll_die(rng);
+        let damage = if let AttackOutcome::Hit { damage } = stats::resolve_attack(&from.stats, &self.stats, hit_roll) {
             log.combat(
                 round,
                 LocalizableString::SomeoneAttackedSomeone {
@@ -318,12 +688,9 @@ impl Fighter {
                     defender_leg: self.stats.leg,
                 },
             );
-
-            if self.stats.health == 0 {
-                log.combat(round, LocalizableString::SomeoneWasIncapacitated(self.name.clone()));
-                if self.stats.treasure > 0 {
-                    level.put_treasure(self.x, self.y, self.stats.treasure);
-                }
+            self.lose_health(damage, level, log, round);
+            if let Some(effect) = from.inflicts() {
+                self.add_status_effect(effect, log, round);
             }
 
             damage
@@ -342,15 +709,62 @@ impl Fighter {
             0
         };
 
-        self.spawn_hit_particles(damage);
+        let direction = ((from.x - self.x).signum(), (from.y - self.y).signum());
+        self.spawn_hit_particles(damage, direction);
+        damage
     }
 
-    fn spawn_hit_particles(&self, damage: i32) {
+    /// Lowers health by `amount`, dropping the treasure carried and
+    /// the loot if this incapacitates the fighter.
+    fn lose_health(&mut self, amount: i32, level: &mut Level, log: &mut GameLog, round: u64) {
+        self.stats.health = (self.stats.health - amount).max(0);
+        if self.stats.health == 0 {
+            log.combat(round, LocalizableString::SomeoneWasIncapacitated(self.name.clone()));
+            // Seeded separately, so that loot doesn't change the rest of the run's rolls
+            let mut loot_rng = Pcg32::seed_from_u64(round << 16 ^ self.id as u64);
+            let loot = self.loot.map_or(0, |loot| loot.roll(&mut loot_rng));
+            if self.stats.treasure + loot > 0 {
+                level.put_treasure(self.x, self.y, self.stats.treasure + loot);
+            }
+            let mut split_rng = Pcg32::new(round << 16 ^ self.id as u64, SLIME_SPLIT_RNG_STREAM);
+            if self.name == Name::Slime
+                && self.stats.max_health >= SLIME_SPLIT_MIN_HEALTH
+                && split_rng.next_u32().is_multiple_of(SLIME_SPLIT_CHANCE)
+            {
+                let max_health = self.stats.max_health / 2;
+                let half = FighterSpawn {
+                    stats: Stats {
+                        max_health,
+                        health: max_health,
+                        treasure: 0,
+                        ..self.stats.clone()
+                    },
+                    scale: self.scale * 0.7,
+                    x: self.x,
+                    y: self.y,
+                    ..SPAWN_SLIME
+                };
+                level.queue_spawn(half.clone());
+                level.queue_spawn(half);
+            }
+        }
+    }
+
+    /// Spawns the hit or miss markers on the side of the fighter that
+    /// the blow came from, `direction` pointing towards the attacker,
+    /// and knocks the fighter back a bit if it was a hit.
+    fn spawn_hit_particles(&self, damage: i32, direction: (i32, i32)) {
         let mut animation = self.animation.borrow_mut();
+        let (dx, dy) = direction;
+        if damage > 0 {
+            animation.knockback_x = -dx;
+            animation.knockback_y = -dy;
+            animation.knockback_progress = 1.0;
+        }
         for i in 0..damage.max(1) {
             animation.particles.push(ParticleEffect::new(
-                0,
-                TILE_STRIDE / 4 + i * 20 - damage.max(1) * 10,
+                dx * TILE_STRIDE / 3,
+                TILE_STRIDE / 4 + dy * TILE_STRIDE / 3 + i * 20 - damage.max(1) * 10,
                 0.0,
                 if damage > 0 {
                     TileGraphic::AttackHit
@@ -371,18 +785,33 @@ impl Fighter {
         log: &mut GameLog,
         round: u64,
     ) {
-        let (x0, y0, x1, y1) = self.spawn_laser_cross_particles(level);
+        let (x0, y0, x1, y1) = self.spawn_laser_cross_particles(level, false);
+        // Unlike melee, the laser hits everyone in its way, teammates included
         for fighter in fighters {
-            if fighter.x == self.x && fighter.y >= y0 && fighter.y <= y1 {
-                fighter.take_damage(&self, level, rng, log, round);
-            } else if fighter.y == self.y && fighter.x >= x0 && fighter.x <= x1 {
-                fighter.take_damage(&self, level, rng, log, round);
+            let in_column = (y0..=y1).any(|y| fighter.occupies(self.x, y));
+            let in_row = (x0..=x1).any(|x| fighter.occupies(x, self.y));
+            if in_column || in_row {
+                fighter.take_damage(self, level, rng, log, round);
             }
         }
     }
 
-    fn spawn_laser_cross_particles(&self, level: &Level) -> (i32, i32, i32, i32) {
-        let mut animation = self.animation.borrow_mut();
+    /// Shows where the laser cross will hit, without hitting anything.
+    pub fn telegraph_laser_cross(&self, level: &Level) {
+        self.spawn_laser_cross_particles(level, true);
+    }
+
+    /// Returns true if `target` would be hit by this fighter's laser cross.
+    pub fn laser_cross_hits(&self, level: &Level, target: Point) -> bool {
+        let (x0, y0, x1, y1) = self.laser_cross_extents(level);
+        let (x0, y0, x1, y1) = (self.x + x0, self.y + y0, self.x + x1, self.y + y1);
+        (target.x == self.x && target.y >= y0 && target.y <= y1)
+            || (target.y == self.y && target.x >= x0 && target.x <= x1)
+    }
+
+    /// The reach of the laser cross, relative to the fighter, in the
+    /// order left, up, right, down.
+    fn laser_cross_extents(&self, level: &Level) -> (i32, i32, i32, i32) {
         let (mut x0, mut y0, mut x1, mut y1) = (0, 0, 0, 0);
         while !level.get_terrain(self.x + x0, self.y + y0).unwalkable() {
             x0 -= 1;
@@ -396,19 +825,27 @@ impl Fighter {
         while !level.get_terrain(self.x + (x0 + x1) / 2, self.y + y1).unwalkable() {
             y1 += 1;
         }
+        (x0, y0, x1, y1)
+    }
+
+    fn spawn_laser_cross_particles(&self, level: &Level, telegraph: bool) -> (i32, i32, i32, i32) {
+        let (x0, y0, x1, y1) = self.laser_cross_extents(level);
+        let mut animation = self.animation.borrow_mut();
 
+        // The telegraph is a faint, lingering version of the actual beam
+        let (duration, opacity) = if telegraph { (2.5, 0.35) } else { (0.2, 1.0) };
         for x in x0..=x1 {
             let (x, y) = (x * TILE_STRIDE, 0);
-            animation
-                .particles
-                .push(ParticleEffect::new(x, y, 0.0, TileGraphic::LaserBeam, 0.2, false));
+            let mut particle = ParticleEffect::new(x, y, 0.0, TileGraphic::LaserBeam, duration, false);
+            particle.opacity = opacity;
+            animation.particles.push(particle);
         }
 
         for y in y0..=y1 {
             let (x, y) = (0, y * TILE_STRIDE);
-            animation
-                .particles
-                .push(ParticleEffect::new(x, y, 90.0, TileGraphic::LaserBeam, 0.2, false));
+            let mut particle = ParticleEffect::new(x, y, 90.0, TileGraphic::LaserBeam, duration, false);
+            particle.opacity = opacity;
+            animation.particles.push(particle);
         }
 
         (self.x + x0, self.y + y0, self.x + x1, self.y + y1)
@@ -425,7 +862,6 @@ impl Fighter {
         camera: &Camera,
         dead_layer: bool,
         show_debug: bool,
-        selected: bool,
     ) {
         if let Some(tile) = self.tile {
             let is_dead = self.stats.health == 0;
@@ -442,35 +878,102 @@ impl Fighter {
                 let _ = canvas.draw_rect(Rect::new(
                     self.x * TILE_STRIDE - camera.x,
                     self.y * TILE_STRIDE - camera.y,
-                    TILE_STRIDE as u32,
-                    TILE_STRIDE as u32,
+                    TILE_STRIDE as u32 * self.size.0,
+                    TILE_STRIDE as u32 * self.size.1,
                 ));
             }
 
-            if selected {
-                let x = self.x * TILE_STRIDE - camera.x;
-                let y = self.y * TILE_STRIDE - camera.y;
-                tile_painter.draw_tile(canvas, TileGraphic::TileHighlight, x, y, false, false);
-            }
-
             let animation = self.animation.borrow();
-            let x = self.x * TILE_STRIDE - camera.x + animation.offset_x;
-            let y = self.y * TILE_STRIDE - camera.y + animation.offset_y;
+            let (x, y, w, h) = self.sprite_rect(camera);
             if is_dead {
-                tile_painter.draw_tile(canvas, tile.dead(), x, y, animation.flip_h, false);
+                if self.scale == 1.0 && self.size == (1, 1) {
+                    tile_painter.draw_tile(canvas, tile.dead(), x, y, animation.flip_h, false);
+                } else {
+                    tile_painter.draw_tile_ex(canvas, tile.dead(), Rect::new(x, y, w, h), animation.flip_h, false);
+                }
             } else {
-                let w = (TILE_STRIDE + animation.width_inc) as u32;
-                let h = (TILE_STRIDE + animation.height_inc) as u32;
-                tile_painter.draw_tile_shadowed_ex(canvas, tile, x, y, w, h, animation.flip_h, false);
+                tile_painter.draw_tile_shadowed_ex(canvas, tile, Rect::new(x, y, w, h), animation.flip_h, false);
             }
         }
     }
 
-    pub fn draw_health<RT: RenderTarget>(&self, canvas: &mut Canvas<RT>, camera: &Camera) {
+    /// Highlights the tiles under the selected fighter. Should be drawn
+    /// before any of the fighters, so the neighbors' sprites and
+    /// shadows don't cover it.
+    pub fn draw_selection_highlight<RT: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<RT>,
+        tile_painter: &mut TilePainter,
+        camera: &Camera,
+    ) {
+        if self.tile.is_none() {
+            return;
+        }
+        for dy in 0..self.size.1 as i32 {
+            for dx in 0..self.size.0 as i32 {
+                let x = (self.x + dx) * TILE_STRIDE - camera.x;
+                let y = (self.y + dy) * TILE_STRIDE - camera.y;
+                tile_painter.draw_tile(canvas, TileGraphic::TileHighlight, x, y, false, false);
+            }
+        }
+    }
+
+    /// Marks the fighter as a threat for the next turn, see
+    /// [Dungeon::threatening_fighter_ids](crate::Dungeon::threatening_fighter_ids).
+    pub fn draw_threat_highlight<RT: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<RT>,
+        tile_painter: &mut TilePainter,
+        camera: &Camera,
+    ) {
         let animation = self.animation.borrow();
         let x = self.x * TILE_STRIDE - camera.x + animation.offset_x;
         let y = self.y * TILE_STRIDE - camera.y + animation.offset_y;
+        let color = interface::THREAT_HIGHLIGHT;
+        let (w, h) = (TILE_STRIDE as u32 * self.size.0, TILE_STRIDE as u32 * self.size.1);
+        tile_painter.tileset.set_color_mod(color.r, color.g, color.b);
+        tile_painter.draw_tile_ex(canvas, TileGraphic::TileHighlight, Rect::new(x, y, w, h), false, false);
+        tile_painter.tileset.set_color_mod(0xFF, 0xFF, 0xFF);
+    }
 
+    /// The on-screen position and size of the sprite, taking the
+    /// animation, [Fighter::size] and [Fighter::scale] into account.
+    /// Big and scaled sprites grow upwards from the bottom center of
+    /// their first tile's column.
+    fn sprite_rect(&self, camera: &Camera) -> (i32, i32, u32, u32) {
+        let animation = self.animation.borrow();
+        let scale_x = self.scale * self.size.0 as f32;
+        let scale_y = self.scale * self.size.1 as f32;
+        let w = ((TILE_STRIDE + animation.width_inc) as f32 * scale_x) as i32;
+        let h = ((TILE_STRIDE + animation.height_inc) as f32 * scale_y) as i32;
+        let footprint_w = TILE_STRIDE * self.size.0 as i32;
+        let footprint_h = TILE_STRIDE * self.size.1 as i32;
+        let x = self.x * TILE_STRIDE - camera.x + animation.offset_x - (w - footprint_w - animation.width_inc) / 2;
+        let y = self.y * TILE_STRIDE - camera.y + animation.offset_y - (h - footprint_h - animation.height_inc);
+        (x, y, w as u32, h as u32)
+    }
+
+    /// Draws the health bar above the fighter. With `only_when_damaged`,
+    /// fighters at full health only get a bar while `highlighted`.
+    pub fn draw_health<RT: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<RT>,
+        camera: &Camera,
+        only_when_damaged: bool,
+        highlighted: bool,
+    ) {
+        if only_when_damaged && !highlighted && self.stats.health >= self.stats.max_health {
+            return;
+        }
+
+        let (_, y, _, _) = self.sprite_rect(camera);
+        // Centered over the footprint
+        let x = self.x * TILE_STRIDE - camera.x
+            + self.animation.borrow().offset_x
+            + (self.size.0 as i32 - 1) * TILE_STRIDE / 2;
+
+        let animation = self.animation.borrow();
+        let displayed_health = animation.displayed_health.unwrap_or(self.stats.health as f32);
         let gap = (4 - self.stats.max_health / 3).max(1);
         let health_area_width = TILE_STRIDE - 20 + self.stats.max_health * 3;
         let health_rect_width = health_area_width / self.stats.max_health;
@@ -496,6 +999,24 @@ impl Fighter {
             );
             let _ = canvas.fill_rect(health_rect);
 
+            // The ghost of the health just lost, draining away
+            let ghost = (displayed_health - i as f32).min(1.0);
+            if i >= self.stats.health && ghost > 0.0 {
+                let ghost_width = (health_rect.width() as f32 * ghost).ceil() as u32;
+                canvas.set_draw_color(interface::HEALTH_GHOST);
+                let _ = canvas.fill_rect(Rect::new(
+                    health_rect.x,
+                    health_rect.y,
+                    ghost_width,
+                    health_rect.height(),
+                ));
+            }
+            if animation.health_flash > 0.0 && i < self.stats.health {
+                let alpha = (0xAA as f32 * animation.health_flash) as u8;
+                canvas.set_draw_color(Color::RGBA(0xFF, 0xFF, 0xFF, alpha));
+                let _ = canvas.fill_rect(health_rect);
+            }
+
             canvas.set_draw_color(interface::HEALTH_BORDER);
             health_rect.offset(-1, -1);
             health_rect.resize(health_rect.width() + 2, health_rect.height() + 2);
@@ -503,6 +1024,33 @@ impl Fighter {
         }
     }
 
+    /// Draws a small colored square for each status effect, in a row
+    /// above the health bar.
+    pub fn draw_status_effects<RT: RenderTarget>(&self, canvas: &mut Canvas<RT>, camera: &Camera) {
+        if self.stats.health == 0 {
+            return;
+        }
+        let (_, y, _, _) = self.sprite_rect(camera);
+        let x = self.x * TILE_STRIDE - camera.x + self.animation.borrow().offset_x;
+        let size = TILE_STRIDE / 6;
+        for (i, effect) in self.status_effects.iter().enumerate() {
+            canvas.set_draw_color(match effect.kind {
+                StatusKind::Poison => interface::STATUS_POISON,
+                StatusKind::Stun => interface::STATUS_STUN,
+                StatusKind::Burning => interface::STATUS_BURNING,
+            });
+            let icon = Rect::new(
+                x + 4 + i as i32 * (size + 2),
+                y - TILE_STRIDE / 8 - size - 6,
+                size as u32,
+                size as u32,
+            );
+            let _ = canvas.fill_rect(icon);
+            canvas.set_draw_color(interface::HEALTH_BORDER);
+            let _ = canvas.draw_rect(icon);
+        }
+    }
+
     pub fn draw_particles<RT: RenderTarget>(
         &self,
         canvas: &mut Canvas<RT>,
@@ -522,9 +1070,6 @@ impl Fighter {
             let center = Point::new(TILE_STRIDE / 2, TILE_STRIDE / 2);
             if particle.shadowed {
                 // FIXME: Shadowed particles ignore angle, currently
-                tile_painter
-                    .shadow_tileset
-                    .set_alpha_mod((0xFF as f32 * particle.opacity) as u8);
                 tile_painter.draw_tile_shadowed(canvas, particle.tile, x, y, false, false);
             } else {
                 tile_painter.draw_tile_rotated(canvas, particle.tile, x, y, particle.angle, center);
@@ -534,11 +1079,17 @@ impl Fighter {
     }
 
     pub fn mouse_over(&self, camera: &Camera, mouse: Point) -> bool {
-        let animation = self.animation.borrow();
-        let x = self.x * TILE_STRIDE - camera.x + animation.offset_x;
-        let y = self.y * TILE_STRIDE - camera.y + animation.offset_y;
-        let width = (TILE_STRIDE + animation.width_inc) as u32;
-        let height = (TILE_STRIDE + animation.height_inc) as u32;
+        let (x, y, width, height) = self.sprite_rect(camera);
         Rect::new(x, y, width, height).contains_point(mouse)
     }
 }
+
+/// How many hits (arm), steps (leg) or picked locks (finger) it takes to
+/// train a stat from `value` to `value + 1`.
+fn actions_per_training(stat: StatIncrease, value: i32) -> f32 {
+    match stat {
+        StatIncrease::Arm => 10.0 + (value - 10) as f32 * 5.0,
+        StatIncrease::Leg => 50.0 + (value - 10) as f32 * 50.0,
+        StatIncrease::Finger => 2.0 + (value - 10) as f32 * 2.0,
+    }
+}
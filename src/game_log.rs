@@ -1,3 +1,4 @@
+use crate::localization::in_world_timestamp;
 use crate::{interface, Font, Language, LocalizableString, Text, TextPainter};
 use fontdue::layout::{LayoutSettings, VerticalAlign};
 use sdl2::pixels::Color;
@@ -11,6 +12,12 @@ pub struct GameLog {
     messages: Vec<(u64, LocalizableString)>,
 }
 
+impl Default for GameLog {
+    fn default() -> Self {
+        GameLog::new()
+    }
+}
+
 impl GameLog {
     pub fn new() -> GameLog {
         GameLog { messages: Vec::new() }
@@ -32,7 +39,11 @@ impl GameLog {
         let (width, height) = canvas.output_size().map(|(a, b)| (a as i32, b as i32)).unwrap();
         let margin = 10;
         let log_width = width - margin * 2;
-        let log_height = 16 * 12;
+        let log_height = (16 * 12).min(height / 3);
+        if log_width < 100 || log_height < 32 {
+            // Not enough room for a legible log
+            return;
+        }
         // TODO: Hide the log after no activity for N rounds
         let background_rect = Rect::new(
             width - (log_width + margin),
@@ -58,15 +69,9 @@ impl GameLog {
                 Font::RegularUi,
                 14.0,
                 Color::WHITE,
-                format!(
-                    " ::: 21XX-03-{d:x} T {h:02}:{m:02}:{s:02} :::\n",
-                    d = 0x14 + round / 60 / 60 / 24,
-                    h = (5 + round / 60 / 60) % 24,
-                    m = (31 + round / 60) % 60,
-                    s = round % 60
-                ),
+                format!(" ::: {} :::\n", in_world_timestamp(*round)),
             ));
-            localized_texts.extend(message.localize(Language::English).into_iter());
+            localized_texts.extend(message.localize(Language::English));
         }
 
         canvas.set_draw_color(interface::HUD_BACKGROUND_TRANSPARENT);
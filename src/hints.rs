@@ -0,0 +1,84 @@
+use crate::{Dungeon, Terrain};
+use sdl2::rect::Point;
+
+/// The hints that have been dismissed are stored here, so they're
+/// only ever shown once.
+static SEEN_HINTS_FILE: &str = "excavation-site-mercury-hints.txt";
+
+/// Tips shown to new players on the first level, when something they
+/// explain is nearby.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Hint {
+    Combat,
+    LockedDoor,
+    Training,
+    Exit,
+}
+
+impl Hint {
+    const ALL: [Hint; 4] = [Hint::Combat, Hint::LockedDoor, Hint::Training, Hint::Exit];
+
+    fn applies(self, dungeon: &Dungeon) -> bool {
+        let player = dungeon.player();
+        let position = player.position();
+        match self {
+            Hint::Combat => dungeon
+                .enemies()
+                .any(|fighter| fighter.stats.health > 0 && distance(fighter.position(), position) <= 3),
+            Hint::LockedDoor => terrain_nearby(dungeon, position, 2, Terrain::is_locked_door),
+            Hint::Training => player
+                .experience_ratios()
+                .is_some_and(|(arm, leg, finger)| arm > 0.0 || leg > 0.0 || finger > 0.0),
+            Hint::Exit => terrain_nearby(dungeon, position, 3, |terrain| terrain == Terrain::Exit),
+        }
+    }
+}
+
+pub struct Hints {
+    seen: Vec<Hint>,
+    /// The hint currently on screen, if any.
+    pub current: Option<Hint>,
+}
+
+impl Hints {
+    pub fn load() -> Hints {
+        let seen_names = std::fs::read_to_string(SEEN_HINTS_FILE).unwrap_or_default();
+        let seen = Hint::ALL
+            .iter()
+            .copied()
+            .filter(|hint| seen_names.lines().any(|line| line == format!("{:?}", hint)))
+            .collect();
+        Hints { seen, current: None }
+    }
+
+    /// Picks a hint to show if none is showing, and one of the unseen
+    /// ones is relevant to the player's surroundings.
+    pub fn update(&mut self, dungeon: &Dungeon) {
+        if self.current.is_some() || !dungeon.is_first_level() || dungeon.is_game_over() {
+            return;
+        }
+        self.current = Hint::ALL
+            .iter()
+            .copied()
+            .find(|hint| !self.seen.contains(hint) && hint.applies(dungeon));
+    }
+
+    pub fn dismiss(&mut self) {
+        if let Some(hint) = self.current.take() {
+            self.seen.push(hint);
+            let seen_names = self.seen.iter().map(|hint| format!("{:?}\n", hint)).collect::<String>();
+            if let Err(err) = std::fs::write(SEEN_HINTS_FILE, seen_names) {
+                log::error!("Failed to save the seen hints to {}: {}", SEEN_HINTS_FILE, err);
+            }
+        }
+    }
+}
+
+fn distance(a: Point, b: Point) -> i32 {
+    (a.x - b.x).abs().max((a.y - b.y).abs())
+}
+
+fn terrain_nearby<F: Fn(Terrain) -> bool>(dungeon: &Dungeon, position: Point, radius: i32, matches: F) -> bool {
+    (-radius..=radius)
+        .any(|dy| (-radius..=radius).any(|dx| matches(dungeon.level().get_terrain(position.x + dx, position.y + dy))))
+}
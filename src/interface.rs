@@ -4,7 +4,15 @@ use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
 use sdl2::render::{Canvas, RenderTarget};
 
+/// The window can't be resized smaller than this, the HUD doesn't fit.
+pub const MIN_WINDOW_WIDTH: u32 = 480;
+pub const MIN_WINDOW_HEIGHT: u32 = 360;
+/// The combat log covers roughly this much of the bottom of the
+/// window, so the camera keeps the player above it.
+pub const HUD_BOTTOM_HEIGHT: u32 = 150;
+
 pub const DEBUG_TEXT: Color = Color::RGB(0xFF, 0xFF, 0x88);
+pub const DEBUG_GRID: Color = Color::RGBA(0xFF, 0xFF, 0xFF, 0x22);
 pub const WINDOW_BACKGROUND: Color = Color::RGB(0x33, 0x33, 0x33);
 pub const HUD_BACKGROUND_TRANSPARENT: Color = Color::RGBA(0x44, 0x44, 0x44, 0xAA);
 pub const HUD_BACKGROUND_OPAQUE: Color = Color::RGB(0x44, 0x44, 0x44);
@@ -18,6 +26,14 @@ pub const HEALTH_EMPTY: Color = Color::RGBA(0xAA, 0xAA, 0xAA, 0xAA);
 pub const HEALTH_LOW: Color = Color::RGB(0xCC, 0x33, 0x22);
 pub const HEALTH_MEDIUM: Color = Color::RGB(0xEE, 0xAA, 0x22);
 pub const HEALTH_HIGH: Color = Color::RGB(0x66, 0xCC, 0x33);
+pub const HEALTH_GHOST: Color = Color::RGB(0xEE, 0xEE, 0xDD);
+pub const THREAT_HIGHLIGHT: Color = Color::RGB(0xFF, 0x55, 0x44);
+pub const STATUS_POISON: Color = Color::RGB(0x99, 0x44, 0xCC);
+pub const STATUS_STUN: Color = Color::RGB(0xEE, 0xDD, 0x44);
+pub const STATUS_BURNING: Color = Color::RGB(0xFF, 0x77, 0x22);
+pub const OXYGEN_FILL: Color = Color::RGB(0x55, 0xBB, 0xEE);
+pub const EXPERIENCE_EMPTY: Color = Color::RGBA(0x22, 0x22, 0x22, 0xAA);
+pub const EXPERIENCE_FILL: Color = Color::RGB(0x88, 0xAA, 0xDD);
 pub const SCREEN_FADE_COLOR: Color = Color::RGBA(0x33, 0x33, 0x33, 0xBB);
 pub const HOTKEY_TIP: Color = Color::RGBA(0xDD, 0xDD, 0xDD, 0xFF);
 pub const ROW_BACKGROUND: Color = Color::RGB(0x44, 0x44, 0x44);
@@ -39,6 +55,12 @@ pub struct UserInterface {
     pub text_input: Option<String>,
 }
 
+impl Default for UserInterface {
+    fn default() -> Self {
+        UserInterface::new()
+    }
+}
+
 impl UserInterface {
     pub fn new() -> UserInterface {
         UserInterface {
@@ -65,6 +87,17 @@ impl UserInterface {
         self.text_input = None;
     }
 
+    /// Takes this frame's input, so that the widgets drawn before a
+    /// modal dialog don't react to it. Put the returned interface back
+    /// in place before drawing the dialog.
+    pub fn take_input(&mut self) -> UserInterface {
+        let blocked = UserInterface {
+            mouse_position: Point::new(i32::MIN, i32::MIN),
+            ..UserInterface::new()
+        };
+        std::mem::replace(self, blocked)
+    }
+
     pub fn button<RT: RenderTarget>(
         &mut self,
         canvas: &mut Canvas<RT>,
@@ -125,6 +158,39 @@ impl UserInterface {
         enabled && (hotkey_pressed || (hovering && self.mouse_left_released))
     }
 
+    /// Draws a text box in the middle of the screen over a faded
+    /// background, with the buttons in a row at the bottom. Returns the
+    /// index of the button that was pressed, if any.
+    pub fn dialog<RT: RenderTarget>(
+        &mut self,
+        canvas: &mut Canvas<RT>,
+        text_painter: &mut TextPainter,
+        text: &LocalizableString,
+        buttons: &[LocalizableString],
+    ) -> Option<usize> {
+        let (width, height) = canvas.output_size().unwrap_or((MIN_WINDOW_WIDTH, MIN_WINDOW_HEIGHT));
+        canvas.set_draw_color(SCREEN_FADE_COLOR);
+        let _ = canvas.fill_rect(Rect::new(0, 0, width, height));
+
+        let dialog_rect = Rect::new((width as i32 - 460) / 2, (height as i32 - 150) / 2, 460, 150);
+        self.text_box(canvas, text_painter, text, dialog_rect, true);
+
+        let button_width = (dialog_rect.width() - 10) / buttons.len().max(1) as u32 - 10;
+        let mut pressed = None;
+        for (i, button_text) in buttons.iter().enumerate() {
+            let button_rect = Rect::new(
+                dialog_rect.x + 10 + (button_width as i32 + 10) * i as i32,
+                dialog_rect.bottom() - 46,
+                button_width,
+                36,
+            );
+            if self.button(canvas, text_painter, button_text, button_rect, true) {
+                pressed = Some(i);
+            }
+        }
+        pressed
+    }
+
     pub fn text_box<RT: RenderTarget>(
         &self,
         canvas: &mut Canvas<RT>,
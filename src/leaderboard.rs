@@ -1,6 +1,6 @@
 use crate::{
-    interface, leaderboard_server, move_towards, Dungeon, Font, Language, LocalizableString, Text, TextPainter,
-    UserInterface,
+    interface, leaderboard_server, move_towards, Dungeon, Font, Language, LocalizableString, RunOutcome, Text,
+    TextPainter, UserInterface,
 };
 use bincode::config::DefaultOptions;
 use bincode::Options;
@@ -12,9 +12,19 @@ use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::io::{Read, Write};
 use std::net::{Shutdown, TcpStream};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const SERVER_ADDRESS: &str = "excavationsitemercury.neon.moe:8582";
+/// The last successfully downloaded leaderboards are stored here, to
+/// be shown when the server can't be reached.
+const CACHE_FILE: &str = "excavation-site-mercury-leaderboards.bin";
 const VALID_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+/// How quickly the scrolling momentum of the list fades, per second.
+const SCROLL_VELOCITY_DECAY: f32 = 5.0;
+/// Momentum slower than this (in pixels per second) is dropped.
+const MIN_SCROLL_VELOCITY: f32 = 20.0;
+/// The maximum length of a display name, in characters.
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 12;
 
 pub fn valid_name_character(c: char) -> bool {
     VALID_CHARS.contains(c)
@@ -24,12 +34,66 @@ pub fn valid_name(name: [char; 3]) -> bool {
     valid_name_character(name[0]) && valid_name_character(name[1]) && valid_name_character(name[2])
 }
 
-#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+/// Display names can be written in any script, but commas are
+/// reserved for the server's entry file.
+pub fn valid_display_name_character(c: char) -> bool {
+    !c.is_control() && c != ','
+}
+
+pub fn valid_display_name(display_name: &str) -> bool {
+    display_name.chars().count() <= MAX_DISPLAY_NAME_LENGTH && display_name.chars().all(valid_display_name_character)
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub struct LeaderboardEntry {
+    /// The tag, used for sorting.
     pub name: [char; 3],
+    /// An optional longer name shown next to the tag.
+    pub display_name: String,
     pub treasure: i32,
     pub rounds: Option<u64>,
     pub size: usize,
+    /// The [run_hash] of the submitted run, 0 for old entries.
+    pub run_hash: u64,
+    /// Runs with [RunOptions::peaceful](crate::RunOptions::peaceful)
+    /// are listed separately from the rest.
+    pub peaceful: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SortColumn {
+    Name,
+    Treasure,
+    Rounds,
+}
+
+impl SortColumn {
+    /// The direction the column is sorted in when it's first clicked.
+    fn ascending_by_default(self) -> bool {
+        match self {
+            SortColumn::Name => true,
+            SortColumn::Treasure => false,
+            SortColumn::Rounds => true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LeaderboardCache {
+    /// Seconds since the Unix epoch.
+    saved_at: u64,
+    entries: Vec<LeaderboardEntry>,
+}
+
+/// A stable 64-bit FNV-1a hash of the run's bytes, used to notice
+/// runs that have already been submitted.
+pub fn run_hash(run_bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in run_bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
 }
 
 pub struct Leaderboard {
@@ -39,10 +103,28 @@ pub struct Leaderboard {
     highlighted_entry: Option<LeaderboardEntry>,
     scroll_offset: i32,
     scroll_offset_target: i32,
-    pending_run: Option<([char; 3], usize, Vec<u8>)>,
+    /// Pixels per second, keeps the list moving after a drag.
+    scroll_velocity: f32,
+    /// The mouse's y coordinate on the previous frame, while the list
+    /// is being dragged.
+    drag_last_y: Option<i32>,
+    sort_column: Option<SortColumn>,
+    sort_ascending: bool,
+    /// When the shown entries were downloaded, if they're from the
+    /// cache instead of the server.
+    offline_since: Option<u64>,
+    pending_run: Option<([char; 3], usize, String, Vec<u8>)>,
+    /// The runs submitted during this session, so they're not sent twice.
+    submitted_entries: Vec<LeaderboardEntry>,
     error_message: Option<String>,
 }
 
+impl Default for Leaderboard {
+    fn default() -> Self {
+        Leaderboard::new()
+    }
+}
+
 impl Leaderboard {
     pub fn new() -> Leaderboard {
         Leaderboard {
@@ -52,7 +134,13 @@ impl Leaderboard {
             highlighted_entry: None,
             scroll_offset: 0,
             scroll_offset_target: 0,
+            scroll_velocity: 0.0,
+            drag_last_y: None,
+            sort_column: None,
+            sort_ascending: true,
+            offline_since: None,
             pending_run: None,
+            submitted_entries: Vec::new(),
             error_message: None,
         }
     }
@@ -60,28 +148,108 @@ impl Leaderboard {
     pub fn submit_run(&mut self, dungeon: &Dungeon) {
         let name = [' ', ' ', ' '];
         let dungeon_bytes = dungeon.to_bytes().unwrap();
+        let hash = run_hash(&dungeon_bytes);
+        if let Some(entry) = self.submitted_entries.iter().find(|entry| entry.run_hash == hash) {
+            log::info!("This run has already been submitted, just showing the leaderboards.");
+            self.highlighted_entry = Some(entry.clone());
+            self.refresh_entries();
+            return;
+        }
+
+        let result = dungeon.sim_result();
         self.highlighted_entry = Some(LeaderboardEntry {
             name,
-            treasure: dungeon.treasure(),
-            rounds: if dungeon.is_game_over() {
-                None
+            display_name: String::new(),
+            treasure: result.treasure,
+            rounds: if result.outcome == RunOutcome::Finished {
+                Some(result.rounds)
             } else {
-                Some(dungeon.round())
+                None
             },
             size: dungeon_bytes.len(),
+            run_hash: hash,
+            peaceful: dungeon.options().peaceful,
         });
-        self.pending_run = Some((name, 0, dungeon_bytes));
+        self.pending_run = Some((name, 0, String::new(), dungeon_bytes));
     }
 
     fn send_run(&mut self) {
-        if let Some((name, _, dungeon_bytes)) = self.pending_run.take() {
+        if let Some((name, _, display_name, dungeon_bytes)) = self.pending_run.take() {
             if let Some(highlighted_entry) = &mut self.highlighted_entry {
                 highlighted_entry.name = name;
+                highlighted_entry.display_name = display_name.clone();
+            }
+            match upload_run(name, &display_name, &dungeon_bytes) {
+                Ok(_) => self.submitted_entries.extend(self.highlighted_entry.clone()),
+                Err(LeaderboardError::Server(message)) => self.error_message = Some(message),
+                Err(err) => log::warn!("Could not upload the run: {}", err),
+            }
+            self.refresh_entries();
+        }
+    }
+
+    /// Peaceful runs are only shown after submitting one.
+    fn showing_peaceful_runs(&self) -> bool {
+        self.highlighted_entry.as_ref().is_some_and(|entry| entry.peaceful)
+    }
+
+    fn refresh_entries(&mut self) {
+        self.offline_since = None;
+        let peaceful = self.showing_peaceful_runs();
+        self.entries = match download_runs() {
+            Ok(entries) => {
+                cache_write(&entries);
+                entries
             }
-            if let Err(LeaderboardError::Server(message)) = upload_run(name, &dungeon_bytes) {
-                self.error_message = Some(message);
+            Err(err) => {
+                log::warn!("Could not download the leaderboards: {}", err);
+                match cache_read() {
+                    Some(cache) => {
+                        self.offline_since = Some(cache.saved_at);
+                        cache.entries
+                    }
+                    None => Vec::new(),
+                }
             }
-            self.entries = download_runs().unwrap_or_else(|_| Vec::new());
+        };
+        self.entries.retain(|entry| entry.peaceful == peaceful);
+        self.sort_entries();
+    }
+
+    /// Sorts by the column if it isn't the current one, otherwise
+    /// flips the sort direction.
+    fn toggle_sort(&mut self, column: SortColumn) {
+        if self.sort_column == Some(column) {
+            self.sort_ascending = !self.sort_ascending;
+        } else {
+            self.sort_column = Some(column);
+            self.sort_ascending = column.ascending_by_default();
+        }
+        self.sort_entries();
+    }
+
+    fn sort_entries(&mut self) {
+        let ascending = self.sort_ascending;
+        let directed = |ordering: Ordering| if ascending { ordering } else { ordering.reverse() };
+        match self.sort_column {
+            Some(SortColumn::Name) => self.entries.sort_by(|a, b| directed(a.name.cmp(&b.name))),
+            Some(SortColumn::Treasure) => self.entries.sort_by(|a, b| directed(a.treasure.cmp(&b.treasure))),
+            // Runs that ended in death stay at the bottom either way
+            Some(SortColumn::Rounds) => self.entries.sort_by(|a, b| match (a.rounds, b.rounds) {
+                (Some(a), Some(b)) => directed(a.cmp(&b)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }),
+            None => {}
+        }
+    }
+
+    fn sort_direction_of(&self, column: SortColumn) -> Option<bool> {
+        if self.sort_column == Some(column) {
+            Some(self.sort_ascending)
+        } else {
+            None
         }
     }
 
@@ -99,7 +267,7 @@ impl Leaderboard {
             let layout = LayoutSettings {
                 x: 20.0,
                 y: 20.0,
-                max_width: Some((width - 20).min(600) as f32),
+                max_width: Some(width.saturating_sub(20).min(600) as f32),
                 ..LayoutSettings::default()
             };
             text_painter.draw_text(
@@ -119,21 +287,28 @@ impl Leaderboard {
         }
 
         // Show the name prompt when there's a pending run
-        if let Some((pending_name, index, _)) = self.pending_run {
-            if let (Some(input), Some((ref mut pending_name, ref mut index, _))) =
-                (&ui.text_input, &mut self.pending_run)
-            {
-                for c in input
-                    .chars()
-                    .map(|c| c.to_ascii_uppercase())
-                    .filter(|c| valid_name_character(*c))
-                {
+        if let Some((pending_name, index, pending_display_name, _)) = &mut self.pending_run {
+            // The tag is typed first, and the display name after it
+            if let Some(input) = &ui.text_input {
+                for c in input.chars() {
                     if *index < 3 {
-                        pending_name[*index] = c;
-                        *index += 1;
+                        let c = c.to_ascii_uppercase();
+                        if valid_name_character(c) {
+                            pending_name[*index] = c;
+                            *index += 1;
+                        }
+                    } else if valid_display_name_character(c)
+                        && pending_display_name.chars().count() < MAX_DISPLAY_NAME_LENGTH
+                    {
+                        pending_display_name.push(c);
                     }
                 }
             }
+            let (pending_name, index) = (*pending_name, *index);
+            let display_name_input = LocalizableString::DisplayNameInput {
+                display_name: pending_display_name.clone(),
+                active: index >= 3,
+            };
 
             ui.text_box(
                 canvas,
@@ -176,6 +351,14 @@ impl Leaderboard {
                 );
             }
 
+            ui.text(
+                canvas,
+                text_painter,
+                &display_name_input,
+                width as i32 / 2 - 200,
+                height as i32 / 2 + 70,
+            );
+
             if ui.button(
                 canvas,
                 text_painter,
@@ -193,16 +376,28 @@ impl Leaderboard {
                 Rect::new((width as i32 - 200) / 2 + 220, height as i32 / 2 + 115, 95, 40),
                 valid_name(pending_name),
             ) {
-                if let Some((ref mut name, ref mut index, _)) = &mut self.pending_run {
+                if let Some((ref mut name, ref mut index, ref mut display_name, _)) = &mut self.pending_run {
                     *name = [' ', ' ', ' '];
                     *index = 0;
+                    display_name.clear();
                 }
             }
             return;
         }
 
         // The actual leaderboards UI
-        ui.text(canvas, text_painter, &LocalizableString::LeaderboardsHeader, 10, 10);
+        let header = if self.showing_peaceful_runs() {
+            LocalizableString::PeacefulLeaderboardsHeader
+        } else {
+            LocalizableString::LeaderboardsHeader
+        };
+        ui.text(canvas, text_painter, &header, 10, 10);
+        if let Some(offline_since) = self.offline_since {
+            let offline_note = LocalizableString::LeaderboardsOffline {
+                seconds_ago: unix_time().saturating_sub(offline_since),
+            };
+            ui.text(canvas, text_painter, &offline_note, width as i32 / 2, 16);
+        }
 
         let extra_space = (width as i32 - 800).max(0);
         let margin = 10;
@@ -246,24 +441,62 @@ impl Leaderboard {
             let row_height = 20 + padding * 2;
             let entries_start_y = 70 + padding;
             let entries_end_y = height as i32 - (70 + padding);
-            let entries_height = entries_end_y - entries_start_y;
+            let entries_height = (entries_end_y - entries_start_y).max(0);
             canvas.set_clip_rect(Rect::new(
                 margin,
                 entries_start_y,
-                width - margin as u32,
+                width.saturating_sub(margin as u32),
                 entries_height as u32,
             ));
 
             self.scroll_offset_target += ui.scroll * row_height * 3 / 2;
-            self.scroll_offset_target = self
+
+            // Dragging moves the list with the mouse, and releasing
+            // leaves it drifting at the speed it was dragged at
+            let list_rect = Rect::new(
+                margin,
+                entries_start_y,
+                width.saturating_sub(margin as u32),
+                entries_height as u32,
+            );
+            let dragging = match (ui.mouse_left_pressed, self.drag_last_y) {
+                (true, Some(last_y)) => {
+                    let dy = ui.mouse_position.y - last_y;
+                    let velocity = dy as f32 / delta_seconds.max(0.001);
+                    self.scroll_velocity = (self.scroll_velocity + velocity) / 2.0;
+                    self.scroll_offset_target += dy;
+                    true
+                }
+                (true, None) => list_rect.contains_point(ui.mouse_position),
+                (false, _) => false,
+            };
+            self.drag_last_y = if dragging { Some(ui.mouse_position.y) } else { None };
+            if !dragging {
+                self.scroll_offset_target += (self.scroll_velocity * delta_seconds) as i32;
+                self.scroll_velocity *= (-SCROLL_VELOCITY_DECAY * delta_seconds).exp();
+                if self.scroll_velocity.abs() < MIN_SCROLL_VELOCITY {
+                    self.scroll_velocity = 0.0;
+                }
+            }
+
+            let clamped_target = self
                 .scroll_offset_target
                 .max(entries_height - row_height * self.entries.len() as i32)
                 .min(0);
-            self.scroll_offset = move_towards(
-                self.scroll_offset,
-                self.scroll_offset_target,
-                (20.0 * (self.scroll_offset_target - self.scroll_offset).abs().max(30) as f32 * delta_seconds) as i32,
-            );
+            if clamped_target != self.scroll_offset_target {
+                self.scroll_velocity = 0.0;
+            }
+            self.scroll_offset_target = clamped_target;
+            if dragging {
+                self.scroll_offset = self.scroll_offset_target;
+            } else {
+                self.scroll_offset = move_towards(
+                    self.scroll_offset,
+                    self.scroll_offset_target,
+                    (20.0 * (self.scroll_offset_target - self.scroll_offset).abs().max(30) as f32 * delta_seconds)
+                        as i32,
+                );
+            }
 
             let mut y = entries_start_y;
             for (i, entry) in self.entries.iter().enumerate() {
@@ -272,7 +505,7 @@ impl Leaderboard {
                     continue;
                 }
 
-                canvas.set_draw_color(if self.highlighted_entry.filter(|e| e == entry).is_some() {
+                canvas.set_draw_color(if self.highlighted_entry.as_ref() == Some(entry) {
                     interface::ROW_BACKGROUND_HIGHLIGHT
                 } else if i % 2 == 0 {
                     interface::ROW_BACKGROUND
@@ -282,14 +515,14 @@ impl Leaderboard {
                 let _ = canvas.fill_rect(Rect::new(
                     name_x,
                     y + self.scroll_offset,
-                    width - margin as u32 * 2 - scroll_width - 5,
+                    width.saturating_sub(margin as u32 * 2 + scroll_width + 5),
                     row_height as u32,
                 ));
 
                 ui.text(
                     canvas,
                     text_painter,
-                    &LocalizableString::LeaderboardsName(entry.name),
+                    &LocalizableString::LeaderboardsName(entry.name, entry.display_name.clone()),
                     name_x + padding,
                     y + padding + self.scroll_offset,
                 );
@@ -371,57 +604,29 @@ impl Leaderboard {
         }
 
         // Sorting buttons
-        if ui.button(
-            canvas,
-            text_painter,
-            &LocalizableString::LeaderboardsSortByButton,
-            Rect::new(name_x + 58, 49, 105, 22),
-            true,
-        ) {
-            self.entries.sort_by(|a, b| {
-                if a.name[0] == b.name[0] && a.name[1] == b.name[1] {
-                    a.name[2].cmp(&b.name[2])
-                } else if a.name[0] == b.name[0] {
-                    a.name[1].cmp(&b.name[1])
-                } else {
-                    a.name[0].cmp(&b.name[0])
-                }
-            });
-        }
-
-        if ui.button(
-            canvas,
-            text_painter,
-            &LocalizableString::LeaderboardsSortByButton,
-            Rect::new(treasure_x + 185, 49, 105, 22),
-            true,
-        ) {
-            self.entries.sort_by(|a, b| b.treasure.cmp(&a.treasure));
-        }
-
-        if ui.button(
-            canvas,
-            text_painter,
-            &LocalizableString::LeaderboardsSortByButton,
-            Rect::new(rounds_x + 217, 49, 105, 22),
-            true,
-        ) {
-            self.entries.sort_by(|a, b| match (a.rounds, b.rounds) {
-                (Some(a), Some(b)) => a.cmp(&b),
-                (Some(_), None) => Ordering::Less,
-                (None, Some(_)) => Ordering::Greater,
-                (None, None) => Ordering::Equal,
-            });
+        let sort_buttons = [
+            (SortColumn::Name, Rect::new(name_x + 58, 49, 105, 22)),
+            (SortColumn::Treasure, Rect::new(treasure_x + 185, 49, 105, 22)),
+            (SortColumn::Rounds, Rect::new(rounds_x + 217, 49, 105, 22)),
+        ];
+        for (column, rect) in &sort_buttons {
+            let text = LocalizableString::LeaderboardsSortByButton(self.sort_direction_of(*column));
+            if ui.button(canvas, text_painter, &text, *rect, true) {
+                self.toggle_sort(*column);
+            }
         }
     }
 }
 
-pub fn upload_run(name: [char; 3], dungeon_bytes: &[u8]) -> Result<(), LeaderboardError> {
+pub fn upload_run(name: [char; 3], display_name: &str, dungeon_bytes: &[u8]) -> Result<(), LeaderboardError> {
     let mut stream = TcpStream::connect(SERVER_ADDRESS)?;
     stream.write_all(leaderboard_server::UPLOAD_MAGIC_STRING.as_bytes())?;
-    stream.write_all(&['>' as u8])?;
+    stream.write_all(b">")?;
     stream.write_all(&[name[0] as u8, name[1] as u8, name[2] as u8])?;
-    stream.write_all(&['<' as u8])?;
+    // The display name is length-prefixed, and marked with | instead
+    // of < so that the server can still accept runs without one.
+    stream.write_all(&[b'|', display_name.len() as u8])?;
+    stream.write_all(display_name.as_bytes())?;
     stream.write_all(dungeon_bytes)?;
     let _ = stream.shutdown(Shutdown::Write);
     let mut response = String::new();
@@ -442,6 +647,33 @@ fn download_runs() -> Result<Vec<LeaderboardEntry>, LeaderboardError> {
     Ok(entries)
 }
 
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn cache_write(entries: &[LeaderboardEntry]) {
+    let cache = LeaderboardCache {
+        saved_at: unix_time(),
+        entries: entries.to_vec(),
+    };
+    match Options::serialize(DefaultOptions::new(), &cache) {
+        Ok(bytes) => {
+            if let Err(err) = std::fs::write(CACHE_FILE, bytes) {
+                log::warn!("Could not write the leaderboard cache to {}: {}", CACHE_FILE, err);
+            }
+        }
+        Err(err) => log::warn!("Could not serialize the leaderboard cache: {}", err),
+    }
+}
+
+fn cache_read() -> Option<LeaderboardCache> {
+    let bytes = std::fs::read(CACHE_FILE).ok()?;
+    Options::deserialize(DefaultOptions::new(), &bytes).ok()
+}
+
 #[derive(Debug)]
 pub enum LeaderboardError {
     Io(std::io::Error),
@@ -449,6 +681,16 @@ pub enum LeaderboardError {
     Server(String),
 }
 
+impl std::fmt::Display for LeaderboardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LeaderboardError::Io(err) => write!(f, "{}", err),
+            LeaderboardError::Bincode(err) => write!(f, "{}", err),
+            LeaderboardError::Server(message) => write!(f, "{}", message),
+        }
+    }
+}
+
 impl From<std::io::Error> for LeaderboardError {
     fn from(err: std::io::Error) -> Self {
         LeaderboardError::Io(err)
@@ -1,63 +1,273 @@
-use crate::{leaderboard, Dungeon, LeaderboardEntry};
+use crate::{leaderboard, Dungeon, DungeonSave, LeaderboardEntry, RunOutcome};
 use bincode::config::DefaultOptions;
 use bincode::Options;
+use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::RwLock;
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 pub const UPLOAD_MAGIC_STRING: &str = "BEGIN THE MINING LOG";
 pub const DOWNLOAD_MAGIC_STRING: &str = "GIVE ME LEADERBOARDS";
 pub const ENTRY_FILE: &str = "mercury-leaderboards.csv";
 
+/// Connections over this limit are turned away until some finish.
+const MAX_CONNECTIONS: usize = 32;
+/// How many uploads a single IP address can make per [UPLOAD_WINDOW].
+const MAX_UPLOADS_PER_WINDOW: usize = 5;
+const UPLOAD_WINDOW: Duration = Duration::from_secs(60);
+/// Clients that stay quiet for longer than this are dropped.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the request counters are logged.
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
+/// Log target for the periodic stats lines, so they can be filtered
+/// separately, e.g. `RUST_LOG=mercury_server::stats=info`.
+const STATS_TARGET: &str = "mercury_server::stats";
+/// Log target for turned away connections and runs.
+const REJECTIONS_TARGET: &str = "mercury_server::rejections";
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+static ACTIVE_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+static UPLOADS_ACCEPTED: AtomicU64 = AtomicU64::new(0);
+static DOWNLOADS_SERVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static REJECTIONS: [AtomicU64; Rejection::ALL.len()] = [ZERO; Rejection::ALL.len()];
+
+/// The reasons a connection or run can be turned away for.
+#[derive(Clone, Copy, Debug)]
+enum Rejection {
+    Busy,
+    RateLimited,
+    BadMagicString,
+    BadName,
+    BadDisplayName,
+    TooBig,
+    ConnectionIssue,
+    Unfinished,
+    Version,
+    Duplicate,
+    Unranked,
+}
+
+impl Rejection {
+    const ALL: [Rejection; 11] = [
+        Rejection::Busy,
+        Rejection::RateLimited,
+        Rejection::BadMagicString,
+        Rejection::BadName,
+        Rejection::BadDisplayName,
+        Rejection::TooBig,
+        Rejection::ConnectionIssue,
+        Rejection::Unfinished,
+        Rejection::Version,
+        Rejection::Duplicate,
+        Rejection::Unranked,
+    ];
+
+    /// Counts the rejection, and sends the reason to the client.
+    fn send(self, stream: &mut TcpStream) {
+        REJECTIONS[self as usize].fetch_add(1, Ordering::Relaxed);
+        log::info!(target: REJECTIONS_TARGET, "Rejected {:?}: {:?}", stream.peer_addr(), self);
+        let response: &[u8] = match self {
+            Rejection::Busy => b"Server busy.",
+            Rejection::RateLimited => b"Too many submissions, try again in a minute.",
+            Rejection::BadMagicString => b"Wrong magic string.",
+            Rejection::BadName => b"Invalid name.",
+            Rejection::BadDisplayName => b"Invalid display name.",
+            Rejection::TooBig => b"No spam!",
+            Rejection::ConnectionIssue => b"Connection issue.",
+            Rejection::Unfinished => b"No early exits!",
+            Rejection::Version => b"Version too old.",
+            Rejection::Duplicate => b"This run is already on the leaderboards!",
+            Rejection::Unranked => b"Runs with these options aren't ranked.",
+        };
+        let _ = stream.write(response);
+    }
+}
+
+fn log_stats() {
+    let rejections = Rejection::ALL
+        .iter()
+        .map(|rejection| {
+            format!(
+                "{:?}={}",
+                rejection,
+                REJECTIONS[*rejection as usize].load(Ordering::Relaxed)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    log::info!(
+        target: STATS_TARGET,
+        "connections={} uploads={} downloads={} bytes_in={} bytes_out={} rejections: {}",
+        ACTIVE_CONNECTIONS.load(Ordering::Relaxed),
+        UPLOADS_ACCEPTED.load(Ordering::Relaxed),
+        DOWNLOADS_SERVED.load(Ordering::Relaxed),
+        BYTES_RECEIVED.load(Ordering::Relaxed),
+        BYTES_SENT.load(Ordering::Relaxed),
+        rejections,
+    );
+}
+
 lazy_static::lazy_static! {
     static ref LEADERBOARD_ENTRIES: RwLock<Vec<u8>> = RwLock::new(Options::serialize(DefaultOptions::new(), &entry_file_read()).unwrap());
+    static ref RECENT_UPLOADS: Mutex<HashMap<IpAddr, Vec<Instant>>> = Mutex::new(HashMap::new());
 }
 
-/// This starts up a TCP server on 0.0.0.0:5852, listening for
+/// This starts up a TCP server on 0.0.0.0:8582, listening for
 /// incoming leaderboard submissions.
 ///
 /// Submissions over 1MB are declined and the connection is dropped.
+/// At most [MAX_CONNECTIONS] clients are served at a time, and each
+/// IP address can only upload a few runs per minute.
 ///
 /// The runs are simulated to get the final statistics, and then
 /// discarded.
 ///
 /// The final statistics are stored in the working directory, in a
 /// file called `mercury-leaderboards.csv`.
+///
+/// On SIGINT or SIGTERM, the server stops accepting connections, lets
+/// the ongoing ones finish, and then returns.
 pub fn serve() {
     log::info!("Starting up leaderboard server on 0.0.0.0:8582...");
+    install_shutdown_handler();
     let listener = TcpListener::bind("0.0.0.0:8582").unwrap();
-    for stream in listener.incoming() {
-        if let Ok(mut stream) = stream {
-            std::thread::spawn(move || {
-                log::debug!("Client connected: {:?}", stream);
-
-                let mut magic_string = [0; UPLOAD_MAGIC_STRING.len()];
-                if let Err(err) = stream.read_exact(&mut magic_string) {
-                    log::debug!("Failed to read magic string: {}", err);
-                    let _ = stream.write(b"Magic string missing.");
-                    return;
-                }
-                if UPLOAD_MAGIC_STRING.as_bytes() == magic_string {
-                    log::debug!("Client wants to submit a new run, listening for a name.");
-                    handle_upload(stream);
-                } else if DOWNLOAD_MAGIC_STRING.as_bytes() == magic_string {
-                    log::debug!("Client wants the leaderboards, sending them over.");
-                    handle_download(stream);
-                } else {
-                    log::debug!("Client did not start with a valid string of bytes, dropping connection.");
-                    let _ = stream.write(b"Wrong magic string.");
-                    return;
-                }
-            });
+    // Non-blocking, so that the shutdown flag gets checked regularly
+    listener.set_nonblocking(true).unwrap();
+    let mut last_stats_time = Instant::now();
+    while !SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+        if last_stats_time.elapsed() >= STATS_INTERVAL {
+            last_stats_time = Instant::now();
+            log_stats();
         }
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+            Err(err) => {
+                log::debug!("Failed to accept a connection: {}", err);
+                continue;
+            }
+        };
+
+        if ACTIVE_CONNECTIONS.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            ACTIVE_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+            log::debug!("Too many connections, turning away: {:?}", stream);
+            Rejection::Busy.send(&mut stream);
+            continue;
+        }
+
+        std::thread::spawn(move || {
+            handle_connection(stream);
+            ACTIVE_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+        });
     }
+
+    log::info!("Shutting down, waiting for the remaining connections to finish...");
+    while ACTIVE_CONNECTIONS.load(Ordering::SeqCst) > 0 {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    log_stats();
+    log::info!("Leaderboard server shut down.");
 }
 
+fn handle_connection(mut stream: TcpStream) {
+    log::debug!("Client connected: {:?}", stream);
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(CONNECTION_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(CONNECTION_TIMEOUT));
+
+    let mut magic_string = [0; UPLOAD_MAGIC_STRING.len()];
+    if let Err(err) = stream.read_exact(&mut magic_string) {
+        log::debug!("Failed to read magic string: {}", err);
+        Rejection::BadMagicString.send(&mut stream);
+        return;
+    }
+    BYTES_RECEIVED.fetch_add(magic_string.len() as u64, Ordering::Relaxed);
+    if UPLOAD_MAGIC_STRING.as_bytes() == magic_string {
+        if !upload_allowed(&stream) {
+            log::debug!("Client has uploaded too many runs recently, dropping connection.");
+            Rejection::RateLimited.send(&mut stream);
+            return;
+        }
+        log::debug!("Client wants to submit a new run, listening for a name.");
+        handle_upload(stream);
+    } else if DOWNLOAD_MAGIC_STRING.as_bytes() == magic_string {
+        log::debug!("Client wants the leaderboards, sending them over.");
+        handle_download(stream);
+    } else {
+        log::debug!("Client did not start with a valid string of bytes, dropping connection.");
+        Rejection::BadMagicString.send(&mut stream);
+    }
+}
+
+/// Records an upload from the client's address, returning false if
+/// the address has already uploaded too much during [UPLOAD_WINDOW].
+fn upload_allowed(stream: &TcpStream) -> bool {
+    let ip = match stream.peer_addr() {
+        Ok(addr) => addr.ip(),
+        Err(_) => return false,
+    };
+    let mut recent_uploads = match RECENT_UPLOADS.lock() {
+        Ok(recent_uploads) => recent_uploads,
+        Err(err) => {
+            log::error!("Error locking the recent uploads map: {}", err);
+            return false;
+        }
+    };
+    let now = Instant::now();
+    recent_uploads.retain(|_, times| {
+        times.retain(|time| now - *time < UPLOAD_WINDOW);
+        !times.is_empty()
+    });
+    let times = recent_uploads.entry(ip).or_insert_with(Vec::new);
+    if times.len() >= MAX_UPLOADS_PER_WINDOW {
+        false
+    } else {
+        times.push(now);
+        true
+    }
+}
+
+#[cfg(unix)]
+fn install_shutdown_handler() {
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    extern "C" fn request_shutdown(_: i32) {
+        SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+    }
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    // Safety: the handler only stores into an atomic, which is
+    // async-signal-safe.
+    unsafe {
+        signal(SIGINT, request_shutdown);
+        signal(SIGTERM, request_shutdown);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_shutdown_handler() {}
+
 fn handle_download(mut stream: TcpStream) {
     match LEADERBOARD_ENTRIES.read() {
         Ok(data) => match stream.write_all(&data) {
-            Ok(_) => log::debug!("> Done."),
+            Ok(_) => {
+                DOWNLOADS_SERVED.fetch_add(1, Ordering::Relaxed);
+                BYTES_SENT.fetch_add(data.len() as u64, Ordering::Relaxed);
+                log::debug!("> Done.");
+            }
             Err(err) => log::debug!("> Error writing the leaderboard data to the client: {}", err),
         },
         Err(err) => log::debug!("> Error locking the leaderboard data for sending: {}", err),
@@ -68,24 +278,37 @@ fn handle_upload(mut stream: TcpStream) {
     let mut name_bytes = [0; 5];
     if let Err(err) = stream.read_exact(&mut name_bytes) {
         log::debug!("> Failed to read name: {}", err);
-        let _ = stream.write(b"Name missing.");
+        Rejection::BadName.send(&mut stream);
         return;
     }
     let name = if name_bytes[0] as char == '>'
         && leaderboard::valid_name_character(name_bytes[1] as char)
         && leaderboard::valid_name_character(name_bytes[2] as char)
         && leaderboard::valid_name_character(name_bytes[3] as char)
-        && name_bytes[4] as char == '<'
+        && (name_bytes[4] as char == '<' || name_bytes[4] as char == '|')
     {
         let name = [name_bytes[1] as char, name_bytes[2] as char, name_bytes[3] as char];
         log::debug!("> Name {}{}{} is ok, listening for the run.", name[0], name[1], name[2]);
         name
     } else {
         log::debug!("> Invalid name format.");
-        let _ = stream.write(b"Invalid name.");
+        Rejection::BadName.send(&mut stream);
         return;
     };
 
+    let display_name = if name_bytes[4] as char == '|' {
+        match read_display_name(&mut stream) {
+            Some(display_name) => display_name,
+            None => {
+                log::debug!("> Invalid display name.");
+                Rejection::BadDisplayName.send(&mut stream);
+                return;
+            }
+        }
+    } else {
+        String::new()
+    };
+
     let mut run_bytes = Vec::with_capacity(10_000);
     loop {
         let mut buffer = [0; 1024];
@@ -96,48 +319,65 @@ fn handle_upload(mut stream: TcpStream) {
                 run_bytes.extend_from_slice(&buffer[..n]);
                 if run_bytes.len() > 1_000_000 {
                     log::debug!("> Client tried to upload over 1MB of run data, dropping connection.");
-                    let _ = stream.write(b"No spam!");
+                    Rejection::TooBig.send(&mut stream);
                     return;
                 }
             }
             Err(err) => {
                 log::error!("> Error while receiving run: {}", err);
-                let _ = stream.write(b"Connection issue.");
+                Rejection::ConnectionIssue.send(&mut stream);
                 return;
             }
         }
     }
 
+    BYTES_RECEIVED.fetch_add(run_bytes.len() as u64, Ordering::Relaxed);
     log::debug!("> Run received, deserializing.");
-    match Dungeon::from_bytes(&run_bytes) {
-        Ok(dungeon) => {
-            log::debug!("> Deserialization successful, updating leaderboards.");
+    match DungeonSave::from_bytes(&run_bytes) {
+        Ok(save) if !save.options().ranked() => {
+            log::debug!("> Got a run with unranked options ({:?}), dropping.", save.options());
+            Rejection::Unranked.send(&mut stream);
+        }
+
+        Ok(save) => {
+            log::debug!("> Deserialization successful, simulating the run.");
+            let result = Dungeon::simulate(&save);
             log::debug!(
-                "> Name: {:?}, {} treasure, {} rounds.",
+                "> Name: {:?} ({:?}), {} treasure, {} rounds, {:?}.",
                 name,
-                dungeon.treasure(),
-                dungeon.round()
+                display_name,
+                result.treasure,
+                result.rounds,
+                result.outcome
             );
 
             let new_entry = LeaderboardEntry {
                 name,
-                treasure: dungeon.treasure(),
-                rounds: if dungeon.is_game_over() {
-                    None
-                } else if dungeon.final_treasure_found() {
-                    Some(dungeon.round())
-                } else {
-                    log::debug!("> Got a run that hadn't ended, dropping.");
-                    let _ = stream.write(b"No early exits!");
-                    return;
+                display_name,
+                treasure: result.treasure,
+                rounds: match result.outcome {
+                    RunOutcome::Died | RunOutcome::Abandoned => None,
+                    RunOutcome::Finished => Some(result.rounds),
+                    RunOutcome::Unfinished => {
+                        log::debug!("> Got a run that hadn't ended, dropping.");
+                        Rejection::Unfinished.send(&mut stream);
+                        return;
+                    }
                 },
                 size: run_bytes.len(),
+                run_hash: leaderboard::run_hash(&run_bytes),
+                peaceful: save.options().peaceful,
             };
 
             match LEADERBOARD_ENTRIES.write() {
                 Ok(mut entries_bytes) => {
                     let mut entries: Vec<LeaderboardEntry> =
                         Options::deserialize(DefaultOptions::new(), &entries_bytes).unwrap();
+                    if entries.iter().any(|entry| entry.run_hash == new_entry.run_hash) {
+                        log::debug!("> This run has already been submitted, dropping.");
+                        Rejection::Duplicate.send(&mut stream);
+                        return;
+                    }
                     log::debug!("> Writing: {:?}", new_entry);
                     entry_file_append(new_entry.clone());
                     entries.push(new_entry);
@@ -148,17 +388,32 @@ fn handle_upload(mut stream: TcpStream) {
                 }
             }
 
+            UPLOADS_ACCEPTED.fetch_add(1, Ordering::Relaxed);
             let _ = stream.write(b"OK.");
         }
 
         Err(err) => {
             log::debug!("> Deserialization error: {}", err);
-            let _ = stream.write(b"Version too old.");
-            return;
+            Rejection::Version.send(&mut stream);
         }
     }
 }
 
+/// Reads the length-prefixed UTF-8 display name that follows the
+/// tag, returning None if it's malformed or too long.
+fn read_display_name(stream: &mut TcpStream) -> Option<String> {
+    let mut length = [0; 1];
+    stream.read_exact(&mut length).ok()?;
+    let mut display_name_bytes = vec![0; length[0] as usize];
+    stream.read_exact(&mut display_name_bytes).ok()?;
+    let display_name = String::from_utf8(display_name_bytes).ok()?;
+    if leaderboard::valid_display_name(&display_name) {
+        Some(display_name)
+    } else {
+        None
+    }
+}
+
 pub fn entry_file_read() -> Vec<LeaderboardEntry> {
     match OpenOptions::new().read(true).open(ENTRY_FILE) {
         Ok(file) => {
@@ -172,11 +427,19 @@ pub fn entry_file_read() -> Vec<LeaderboardEntry> {
                 let treasure = parts.next().unwrap();
                 let rounds = parts.next().unwrap();
                 let size = parts.next().unwrap();
+                // Entries from before display names, run hashes and
+                // peaceful runs have fewer columns
+                let display_name = parts.next().unwrap_or("");
+                let run_hash = parts.next().and_then(|hash| hash.parse::<u64>().ok()).unwrap_or(0);
+                let peaceful = parts.next() == Some("PEACEFUL");
                 result.push(LeaderboardEntry {
                     name: [name.next().unwrap(), name.next().unwrap(), name.next().unwrap()],
+                    display_name: display_name.to_string(),
                     treasure: treasure.parse::<i32>().unwrap(),
                     rounds: rounds.parse::<u64>().ok(),
                     size: size.parse::<usize>().unwrap(),
+                    run_hash,
+                    peaceful,
                 });
             }
             result
@@ -191,7 +454,7 @@ pub fn entry_file_append(entry: LeaderboardEntry) {
     writer
         .write_all(
             format!(
-                "{}{}{},{},{},{}\n",
+                "{}{}{},{},{},{},{},{},{}\n",
                 entry.name[0],
                 entry.name[1],
                 entry.name[2],
@@ -201,7 +464,10 @@ pub fn entry_file_append(entry: LeaderboardEntry) {
                 } else {
                     String::from("DEAD")
                 },
-                entry.size
+                entry.size,
+                entry.display_name,
+                entry.run_hash,
+                if entry.peaceful { "PEACEFUL" } else { "NORMAL" },
             )
             .as_bytes(),
         )
@@ -1,20 +1,52 @@
-use crate::{enemy_ai, stats, Camera, EnemyAi, Name, Stats, TileGraphic, TileLayer, TilePainter, TILE_STRIDE};
-use rand_core::RngCore;
+use crate::{
+    enemy_ai, stats, Camera, Die, EnemyAi, Fighter, LootTable, Name, Stats, Team, TileGraphic, TileLayer, TilePainter,
+    TILE_STRIDE,
+};
+use rand_core::{RngCore, SeedableRng};
 use rand_pcg::Pcg32;
 use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
 use sdl2::render::{Canvas, RenderTarget};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 const LEVEL_WIDTH: usize = 128;
 const LEVEL_HEIGHT: usize = 128;
 
+/// How far the player can see clearly on darkened levels, in tiles.
+pub const FADE_RADIUS: f32 = 7.0;
+/// Like [FADE_RADIUS], but for the dimmer magma levels.
+pub const MAGMA_FADE_RADIUS: f32 = 5.5;
+
+/// How close (in tiles, along the ray) a line of sight has to pass to a
+/// tile corner to be considered going straight through it.
+const CORNER_EPSILON: f32 = 1e-4;
+
+/// Generated levels with fewer rooms than this (the start room
+/// included) are thrown away and generated again.
+const MIN_ROOM_COUNT: usize = 4;
+/// How many times a level is regenerated before settling for fewer
+/// rooms than [MIN_ROOM_COUNT]. After as many more attempts, the exit
+/// is moved wherever it fits, which can't fail.
+const MAX_GENERATION_ATTEMPTS: u32 = 16;
+
+/// Why a generated level was thrown away.
+#[derive(Debug)]
+enum GenerationError {
+    StartRoomBlocked,
+    TooFewRooms,
+    ExitBlocked,
+}
+
 pub const SPAWN_PLAYER: FighterSpawn = FighterSpawn {
     name: Name::Astronaut,
     tile: TileGraphic::Player,
+    team: Team::Player,
     stats: stats::PLAYER,
     ai: None,
+    scale: 1.0,
+    size: (1, 1),
+    loot: None,
     x: 0,
     y: 0,
 };
@@ -22,8 +54,12 @@ pub const SPAWN_PLAYER: FighterSpawn = FighterSpawn {
 pub const SPAWN_SLIME: FighterSpawn = FighterSpawn {
     name: Name::Slime,
     tile: TileGraphic::Slime,
+    team: Team::Enemy,
     stats: stats::SLIME,
     ai: Some(enemy_ai::SLIME),
+    scale: 1.0,
+    size: (1, 1),
+    loot: None,
     x: 0,
     y: 0,
 };
@@ -31,8 +67,12 @@ pub const SPAWN_SLIME: FighterSpawn = FighterSpawn {
 pub const SPAWN_ROACH: FighterSpawn = FighterSpawn {
     name: Name::Roach,
     tile: TileGraphic::Roach,
+    team: Team::Enemy,
     stats: stats::ROACH,
     ai: Some(enemy_ai::ROACH),
+    scale: 1.0,
+    size: (1, 1),
+    loot: None,
     x: 0,
     y: 0,
 };
@@ -40,8 +80,12 @@ pub const SPAWN_ROACH: FighterSpawn = FighterSpawn {
 pub const SPAWN_ROCKMAN: FighterSpawn = FighterSpawn {
     name: Name::Rockman,
     tile: TileGraphic::Rockman,
+    team: Team::Enemy,
     stats: stats::ROCKMAN,
     ai: Some(enemy_ai::ROCKMAN),
+    scale: 1.0,
+    size: (1, 1),
+    loot: Some(stats::ROCKMAN_LOOT),
     x: 0,
     y: 0,
 };
@@ -49,8 +93,40 @@ pub const SPAWN_ROCKMAN: FighterSpawn = FighterSpawn {
 pub const SPAWN_SENTIENT_METAL: FighterSpawn = FighterSpawn {
     name: Name::SentientMetal,
     tile: TileGraphic::SentientMetal,
+    team: Team::Enemy,
     stats: stats::SENTIENT_METAL,
     ai: Some(enemy_ai::SENTIENT_METAL),
+    scale: 1.0,
+    size: (1, 1),
+    loot: Some(stats::SENTIENT_METAL_LOOT),
+    x: 0,
+    y: 0,
+};
+
+pub const SPAWN_METAL_OVERSEER: FighterSpawn = FighterSpawn {
+    name: Name::MetalOverseer,
+    tile: TileGraphic::SentientMetal,
+    team: Team::Enemy,
+    stats: stats::METAL_OVERSEER,
+    ai: Some(enemy_ai::METAL_OVERSEER),
+    scale: 1.5,
+    size: (1, 1),
+    loot: None,
+    x: 0,
+    y: 0,
+};
+
+/// The player's ally in runs with [crate::RunOptions::companion]. Not
+/// placed by the level generator, but next to the player on each level.
+pub const SPAWN_REPAIR_DRONE: FighterSpawn = FighterSpawn {
+    name: Name::RepairDrone,
+    tile: TileGraphic::SentientMetal,
+    team: Team::Player,
+    stats: stats::REPAIR_DRONE,
+    ai: Some(enemy_ai::COMPANION),
+    scale: 0.6,
+    size: (1, 1),
+    loot: None,
     x: 0,
     y: 0,
 };
@@ -59,8 +135,17 @@ pub const SPAWN_SENTIENT_METAL: FighterSpawn = FighterSpawn {
 pub struct FighterSpawn {
     pub name: Name,
     pub tile: TileGraphic,
+    pub team: Team,
     pub stats: Stats,
     pub ai: Option<EnemyAi>,
+    /// How big the sprite is drawn, relative to the footprint. Purely
+    /// visual, see [FighterSpawn::size] for how much room it takes.
+    pub scale: f32,
+    /// How many tiles wide and tall the fighter is, extending right and
+    /// down from (x, y). The sprite is stretched across all of them.
+    pub size: (u32, u32),
+    /// Treasure dropped when incapacitated, if any, see [LootTable].
+    pub loot: Option<LootTable>,
     pub x: i32,
     pub y: i32,
 }
@@ -92,22 +177,27 @@ pub enum Terrain {
 
 impl Terrain {
     pub const fn unwalkable(self) -> bool {
-        match self {
-            Terrain::Wall | Terrain::Door | Terrain::LockedDoor { .. } => true,
-            _ => false,
-        }
+        matches!(self, Terrain::Wall | Terrain::Door | Terrain::LockedDoor { .. })
+    }
+
+    pub const fn is_locked_door(self) -> bool {
+        matches!(self, Terrain::LockedDoor { .. })
+    }
+
+    pub const fn is_unlocked_door(self) -> bool {
+        matches!(self, Terrain::Door | Terrain::DoorOpen)
     }
 
     pub const fn enemies_avoid(self) -> bool {
-        match self {
+        matches!(
+            self,
             Terrain::Door
-            | Terrain::LockedDoor { .. }
-            | Terrain::DoorOpen
-            | Terrain::Empty
-            | Terrain::Exit
-            | Terrain::FinalTreasure => true,
-            _ => false,
-        }
+                | Terrain::LockedDoor { .. }
+                | Terrain::DoorOpen
+                | Terrain::Empty
+                | Terrain::Exit
+                | Terrain::FinalTreasure
+        )
     }
 }
 
@@ -116,16 +206,105 @@ struct LevelAnimation {
     door_openings: HashMap<(i32, i32), f32>,
 }
 
+/// How many line of sight grids are kept around in a
+/// [LineOfSightCache]. The player usually just moves back and forth
+/// around the last few tiles they stood on.
+const LINE_OF_SIGHT_CACHE_SIZE: usize = 16;
+
+/// The visible tiles of the viewport, keyed by where they were seen
+/// from and the viewport. Only the most recently used grids are kept,
+/// so long levels and window resizes don't pile them up.
+#[derive(Default, Clone, Debug)]
+struct LineOfSightCache {
+    grids: HashMap<(Point, Rect), Vec<bool>>,
+    /// The keys of `grids`, least recently used first.
+    usage_order: VecDeque<(Point, Rect)>,
+}
+
+impl LineOfSightCache {
+    fn get(&mut self, key: &(Point, Rect)) -> Option<&Vec<bool>> {
+        if self.grids.contains_key(key) {
+            self.usage_order.retain(|used| used != key);
+            self.usage_order.push_back(*key);
+        }
+        self.grids.get(key)
+    }
+
+    fn insert(&mut self, key: (Point, Rect), line_of_sight: Vec<bool>) {
+        if self.grids.insert(key, line_of_sight).is_some() {
+            self.usage_order.retain(|used| *used != key);
+        }
+        self.usage_order.push_back(key);
+        while self.usage_order.len() > LINE_OF_SIGHT_CACHE_SIZE {
+            if let Some(oldest) = self.usage_order.pop_front() {
+                self.grids.remove(&oldest);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.grids.clear();
+        self.usage_order.clear();
+    }
+}
+
+/// The enemies that can spawn in the rooms on each difficulty, and
+/// how likely each one is relative to the others on that difficulty.
+/// Each table adds up to 10, and the spawns are in the order of the
+/// old roll ranges, so that the levels of existing saves are generated
+/// the same. That's also why the rockmen of the later difficulties are
+/// split around the sentient metal.
+const ENEMY_SPAWN_WEIGHTS: [&[(FighterSpawn, u32)]; 4] = [
+    &[(SPAWN_SLIME, 8), (SPAWN_ROACH, 2)],
+    &[(SPAWN_SLIME, 5), (SPAWN_ROACH, 5)],
+    &[(SPAWN_SLIME, 2), (SPAWN_ROACH, 5), (SPAWN_ROCKMAN, 3)],
+    &[
+        (SPAWN_ROACH, 4),
+        (SPAWN_ROCKMAN, 3),
+        (SPAWN_SENTIENT_METAL, 2),
+        (SPAWN_ROCKMAN, 1),
+    ],
+];
+
+/// How much the final treasure is worth.
+pub const FINAL_TREASURE_AMOUNT: i32 = 100;
+
+/// The parts of [Level::draw] that are the same for every layer of a
+/// frame.
+#[derive(Clone, Copy)]
+pub struct LevelDrawSettings {
+    pub show_debug: bool,
+    pub fade_radius: Option<f32>,
+    pub magma_level: bool,
+    /// The player's Finger and die, for the odds shown on locked doors.
+    pub finger: i32,
+    pub die: Die,
+}
+
 #[derive(Clone, Debug)]
 pub struct Level {
     pub spawns: Vec<FighterSpawn>,
     pub line_of_sight_x: i32,
     pub line_of_sight_y: i32,
     pub final_treasure_found: bool,
+    /// True for the last level, where the final treasure is instead of
+    /// the exit.
+    has_final_treasure: bool,
     terrain: [Terrain; LEVEL_WIDTH * LEVEL_HEIGHT],
     rooms: Vec<Rect>,
     treasure: [Option<Treasure>; LEVEL_WIDTH * LEVEL_HEIGHT],
-    line_of_sight_cache: RefCell<HashMap<(Point, Rect), Vec<bool>>>,
+    /// Where the tiles with treasure are, in the order they got it, so
+    /// the remaining treasure can be listed without scanning the level.
+    treasure_positions: Vec<Point>,
+    line_of_sight_cache: RefCell<LineOfSightCache>,
+    /// Fighters to add once the current turn is over, drained by the
+    /// dungeon into its fighters. Anything with access to the level
+    /// mid-turn can queue them, like the AI or a slime splitting on
+    /// death, since the fighters can't be added to while they're being
+    /// iterated over. The queue is here rather than in the dungeon's
+    /// state because the level is what combat and the AI already get
+    /// mutable access to.
+    pending_spawns: Vec<FighterSpawn>,
 
     /// Intended to only be used in the drawing functions, mutated by
     /// `.animate()`. In a RefCell, because this is "stateful" per
@@ -140,7 +319,61 @@ impl PartialEq for Level {
 }
 
 impl Level {
+    /// Generates a level with its own rng, for when the level doesn't
+    /// need to share a random stream with the rest of the dungeon.
+    pub fn new_with_seed(seed: u64, difficulty: u32) -> Level {
+        Level::new(&mut Pcg32::seed_from_u64(seed), difficulty)
+    }
+
+    /// Generates a level from the given rng. If the generation fails,
+    /// it's retried with sub-seeds drawn from `rng`, so a successful
+    /// first attempt doesn't change the random stream.
     pub fn new(rng: &mut Pcg32, difficulty: u32) -> Level {
+        // Levels are big, so generate is only called from one place to
+        // keep just one of them on the stack in debug builds
+        let last_attempt = MAX_GENERATION_ATTEMPTS * 2;
+        let mut attempt = 0;
+        loop {
+            let min_room_count = if attempt < MAX_GENERATION_ATTEMPTS {
+                MIN_ROOM_COUNT
+            } else {
+                1
+            };
+            let settle = attempt == last_attempt;
+            let mut sub_rng;
+            let attempt_rng = if attempt == 0 {
+                &mut *rng
+            } else {
+                sub_rng = Pcg32::seed_from_u64(rng.next_u64());
+                &mut sub_rng
+            };
+            match Level::generate(attempt_rng, difficulty, min_room_count, settle) {
+                Ok(level) => return level,
+                Err(err) if settle => panic!("settling for any exit can't fail, but it did ({:?})", err),
+                Err(err) if attempt + 1 == last_attempt => {
+                    log::warn!("Level generation keeps failing ({:?}), settling for any exit", err);
+                }
+                Err(err) => log::debug!(
+                    "Level generation failed ({:?}), retrying (attempt {})",
+                    err,
+                    attempt + 1
+                ),
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Generates a level, or fails if it doesn't have at least
+    /// `min_room_count` rooms or the exit lands somewhere it shouldn't.
+    /// With `settle`, the exit is instead moved to the first free tile
+    /// of its room, or of the start room (unless it's the final level
+    /// and there's another room), so the generation can't fail.
+    fn generate(
+        rng: &mut Pcg32,
+        difficulty: u32,
+        min_room_count: usize,
+        settle: bool,
+    ) -> Result<Level, GenerationError> {
         fn terrain_mut(
             terrain: &mut [Terrain; LEVEL_WIDTH * LEVEL_HEIGHT],
             x: i32,
@@ -318,7 +551,7 @@ impl Level {
             };
 
             let new_room = Rect::new(new_room_x, new_room_y, new_room_width, new_room_height);
-            let door_spots_available = add_doors(rng, terrain, &rooms, new_room, true, door_terrain, max_doors).is_ok();
+            let door_spots_available = add_doors(rng, terrain, rooms, new_room, true, door_terrain, max_doors).is_ok();
             if door_spots_available && put_room(terrain, new_room).is_ok() {
                 let _ = add_doors(rng, terrain, rooms, new_room, false, door_terrain, max_doors);
                 Ok(new_room)
@@ -337,7 +570,7 @@ impl Level {
         let start_room_x = (LEVEL_WIDTH as u32 - start_room_width) as i32 / 2;
         let start_room_y = (LEVEL_HEIGHT as u32 - start_room_height) as i32 / 2;
         let start_room = Rect::new(start_room_x, start_room_y, start_room_width, start_room_height);
-        put_room(&mut terrain, start_room).unwrap();
+        put_room(&mut terrain, start_room).map_err(|_| GenerationError::StartRoomBlocked)?;
         rooms.push(start_room);
 
         // Place normal rooms
@@ -349,6 +582,9 @@ impl Level {
                 rooms.push(new_room);
             }
         }
+        if rooms.len() < min_room_count {
+            return Err(GenerationError::TooFewRooms);
+        }
 
         // Place player
         let mut spawns = Vec::new();
@@ -359,40 +595,32 @@ impl Level {
 
         // Place enemies
         for room in rooms.iter().skip(1) {
-            if rng.next_u32() % 3 == 0 {
+            if rng.next_u32().is_multiple_of(3) {
                 // Leave some rooms non-hostile
                 continue;
             }
 
-            let mut occupied_spots = Vec::new();
             let spawned_enemies = room.width() / 3 + rng.next_u32() % (3 + difficulty / 2);
-            'spawn_loop: for _ in 0..spawned_enemies {
-                let x = (rng.next_u32() % room.width()) as i32 + room.x;
-                let y = (rng.next_u32() % (room.height() - 1)) as i32 + room.y;
+            for _ in 0..spawned_enemies {
+                let x = random_below(rng, room.width()) as i32 + room.x;
+                let y = random_below(rng, room.height().saturating_sub(1)) as i32 + room.y;
 
-                for (x_, y_) in &occupied_spots {
-                    if x == *x_ && y == *y_ {
-                        continue 'spawn_loop;
-                    }
+                // Checked against every spawn, the player's included, in case rooms overlap
+                if spawns.iter().any(|spawn: &FighterSpawn| spawn.x == x && spawn.y == y) {
+                    continue;
                 }
 
-                let spawn = match rng.next_u32() % 10 + difficulty * 3 {
-                    0..=7 => SPAWN_SLIME,
-                    8..=12 => SPAWN_ROACH,
-                    13..=15 => SPAWN_ROCKMAN,
-                    16..=17 => SPAWN_SENTIENT_METAL,
-                    _ => SPAWN_ROCKMAN,
-                };
+                let weights = ENEMY_SPAWN_WEIGHTS[(difficulty as usize).min(ENEMY_SPAWN_WEIGHTS.len() - 1)];
+                let spawn = weighted_choice(rng, weights).clone();
                 spawns.push(spawn.at_position(x, y));
-                occupied_spots.push((x, y));
             }
         }
 
         // Place treasure
         for _ in 0..5 + difficulty * 5 + rng.next_u32() % 5 {
             let room = rooms[rng.next_u32() as usize % rooms.len()];
-            let x = room.x + 1 + (rng.next_u32() % (room.width() - 2)) as i32;
-            let y = room.y + (rng.next_u32() % (room.height() - 1)) as i32;
+            let x = room.x + 1 + random_below(rng, room.width().saturating_sub(2)) as i32;
+            let y = room.y + random_below(rng, room.height().saturating_sub(1)) as i32;
             let index = x as usize + y as usize * LEVEL_WIDTH;
             if terrain[index] == Terrain::Floor {
                 treasure[index] = Some(Treasure {
@@ -409,13 +637,57 @@ impl Level {
             let dy = room.y + room.height() as i32 / 2 - start_room_center_y;
             dx * dx + dy * dy
         });
-        let furthest_room = rooms.iter().nth_back(0).unwrap();
-        let exit_x = furthest_room.x as usize + 1 + (rng.next_u32() % (furthest_room.width() - 2)) as usize;
-        let exit_y = furthest_room.y as usize + 1 + (rng.next_u32() % (furthest_room.height() - 3)) as usize;
-        if difficulty < 3 {
-            terrain[exit_x + exit_y * LEVEL_WIDTH] = Terrain::Exit;
+        let furthest_room = rooms.last().ok_or(GenerationError::TooFewRooms)?;
+        let exit_x = furthest_room.x as usize + 1 + random_below(rng, furthest_room.width().saturating_sub(2)) as usize;
+        let exit_y =
+            furthest_room.y as usize + 1 + random_below(rng, furthest_room.height().saturating_sub(3)) as usize;
+        let final_level = difficulty >= 3;
+        let free_floor = |x: i32, y: i32| {
+            let on_player = spawns[0].x == x && spawns[0].y == y;
+            !on_player && terrain[x as usize + y as usize * LEVEL_WIDTH] == Terrain::Floor
+        };
+        // The final treasure is guarded by the boss, from a free tile right next to it
+        let boss_tile = |x: usize, y: usize| {
+            let (x, y) = (x as i32, y as i32);
+            [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                .iter()
+                .copied()
+                .find(|&(x, y)| free_floor(x, y))
+        };
+        let exit_blocked =
+            |x: usize, y: usize| !free_floor(x as i32, y as i32) || (final_level && boss_tile(x, y).is_none());
+        let (exit_x, exit_y) = if !exit_blocked(exit_x, exit_y) {
+            (exit_x, exit_y)
+        } else if settle {
+            // The same tiles the exit is rolled from, and the start room
+            // always has a free one. The boss shouldn't start next to the
+            // player though, so the final level only falls back to the
+            // start room if there's no other room.
+            let fallback = if final_level && *furthest_room != start_room {
+                None
+            } else {
+                Some(start_room)
+            };
+            std::iter::once(*furthest_room)
+                .chain(fallback)
+                .flat_map(|room| {
+                    let xs = room.x as usize + 1..room.right() as usize - 1;
+                    let ys = room.y as usize + 1..room.bottom() as usize - 2;
+                    ys.flat_map(move |y| xs.clone().map(move |x| (x, y)))
+                })
+                .find(|&(x, y)| !exit_blocked(x, y))
+                .ok_or(GenerationError::ExitBlocked)?
         } else {
+            return Err(GenerationError::ExitBlocked);
+        };
+        if final_level {
+            // The boss' tile was checked to be free along with the exit's
+            let (boss_x, boss_y) = boss_tile(exit_x, exit_y).unwrap();
             terrain[exit_x + exit_y * LEVEL_WIDTH] = Terrain::FinalTreasure;
+            spawns.retain(|spawn| spawn.x != boss_x || spawn.y != boss_y);
+            spawns.push(SPAWN_METAL_OVERSEER.at_position(boss_x, boss_y));
+        } else {
+            terrain[exit_x + exit_y * LEVEL_WIDTH] = Terrain::Exit;
         }
 
         // Place treasure rooms now that there's a way to finish
@@ -442,22 +714,29 @@ impl Level {
                 treasure_rooms.push(treasure_room);
             }
         }
-        rooms.extend(treasure_rooms.into_iter());
+        rooms.extend(treasure_rooms);
 
         let line_of_sight_x = spawns[0].x;
         let line_of_sight_y = spawns[0].y;
+        let treasure_positions = (0..LEVEL_WIDTH * LEVEL_HEIGHT)
+            .filter(|&i| treasure[i].is_some())
+            .map(|i| Point::new((i % LEVEL_WIDTH) as i32, (i / LEVEL_WIDTH) as i32))
+            .collect();
 
-        Level {
+        Ok(Level {
             spawns,
             line_of_sight_x,
             line_of_sight_y,
             final_treasure_found: false,
+            has_final_treasure: difficulty >= 3,
             terrain,
             rooms,
             treasure,
+            treasure_positions,
             animation_state: RefCell::new(LevelAnimation::default()),
-            line_of_sight_cache: RefCell::new(HashMap::new()),
-        }
+            line_of_sight_cache: RefCell::new(LineOfSightCache::default()),
+            pending_spawns: Vec::new(),
+        })
     }
 
     pub fn room_center_in_pixel_space(&self, in_room_point: Point) -> Option<Point> {
@@ -472,14 +751,22 @@ impl Level {
     }
 
     pub fn room_at_position(&self, point: Point) -> Option<Rect> {
-        self.rooms.iter().find(|room| room.contains_point(point)).map(|r| *r)
+        self.rooms.iter().find(|room| room.contains_point(point)).copied()
+    }
+
+    /// Changes the terrain at (x, y), which must be in bounds. All
+    /// terrain changes after generation should go through here, so the
+    /// cached lines of sight don't go stale.
+    pub(crate) fn set_terrain(&mut self, x: i32, y: i32, terrain: Terrain) {
+        self.terrain[x as usize + y as usize * LEVEL_WIDTH] = terrain;
+        self.line_of_sight_cache.borrow_mut().clear();
     }
 
     pub fn open_door(&mut self, x: i32, y: i32) {
         if x >= 0 && x < LEVEL_WIDTH as i32 && y >= 0 && y < LEVEL_HEIGHT as i32 {
             match self.terrain[x as usize + y as usize * LEVEL_WIDTH] {
                 Terrain::Door | Terrain::LockedDoor { .. } => {
-                    self.terrain[x as usize + y as usize * LEVEL_WIDTH] = Terrain::DoorOpen;
+                    self.set_terrain(x, y, Terrain::DoorOpen);
                     self.animation_state.borrow_mut().door_openings.insert((x, y), 0.066);
                 }
                 _ => {}
@@ -495,6 +782,59 @@ impl Level {
         }
     }
 
+    /// Returns true if a fighter could step on (x, y) right now: the
+    /// terrain isn't in the way, and no living fighter is standing there.
+    pub fn is_walkable(&self, x: i32, y: i32, fighters: &[Fighter]) -> bool {
+        !self.get_terrain(x, y).unwalkable() && Fighter::living_at(fighters, x, y).is_none()
+    }
+
+    /// Finds a shortest path from `from` to `to`, only stepping on tiles
+    /// that `can_walk` allows, and giving up on paths longer than
+    /// `max_length` steps. The destination is always considered
+    /// walkable. The path doesn't include `from`, but ends with `to`.
+    pub fn find_path<F: Fn(Point, Terrain) -> bool>(
+        &self,
+        from: Point,
+        to: Point,
+        max_length: usize,
+        can_walk: F,
+    ) -> Option<Vec<Point>> {
+        let mut came_from: HashMap<Point, Point> = HashMap::new();
+        let mut queue = VecDeque::new();
+        came_from.insert(from, from);
+        queue.push_back((from, 0));
+
+        while let Some((point, length)) = queue.pop_front() {
+            if point == to {
+                let mut path = vec![point];
+                let mut current = point;
+                while came_from[&current] != from {
+                    current = came_from[&current];
+                    path.push(current);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if length >= max_length {
+                continue;
+            }
+
+            for (dx, dy) in &[(0, -1), (0, 1), (-1, 0), (1, 0)] {
+                let next = point.offset(*dx, *dy);
+                if came_from.contains_key(&next) {
+                    continue;
+                }
+                if next != to && !can_walk(next, self.get_terrain(next.x, next.y)) {
+                    continue;
+                }
+                came_from.insert(next, point);
+                queue.push_back((next, length + 1));
+            }
+        }
+
+        None
+    }
+
     pub fn get_treasure(&self, x: i32, y: i32) -> Option<Treasure> {
         if x < 0 || y < 0 || x >= LEVEL_WIDTH as i32 || y >= LEVEL_HEIGHT as i32 {
             None
@@ -507,14 +847,15 @@ impl Level {
         if x < 0 || y < 0 || x >= LEVEL_WIDTH as i32 || y >= LEVEL_HEIGHT as i32 {
             0
         } else if self.terrain[x as usize + y as usize * LEVEL_WIDTH] == Terrain::FinalTreasure {
-            self.terrain[x as usize + y as usize * LEVEL_WIDTH] = Terrain::Floor;
+            self.set_terrain(x, y, Terrain::Floor);
             self.final_treasure_found = true;
-            100
+            FINAL_TREASURE_AMOUNT
         } else {
-            self.treasure[x as usize + y as usize * LEVEL_WIDTH]
-                .take()
-                .map(|treasure| treasure.amount)
-                .unwrap_or(0)
+            let treasure = self.treasure[x as usize + y as usize * LEVEL_WIDTH].take();
+            if treasure.is_some() {
+                self.treasure_positions.retain(|&position| position != Point::new(x, y));
+            }
+            treasure.map(|treasure| treasure.amount).unwrap_or(0)
         }
     }
 
@@ -527,6 +868,41 @@ impl Level {
             treasure.amount += amount;
         } else {
             self.treasure[index] = Some(Treasure { amount });
+            self.treasure_positions.push(Point::new(x, y));
+        }
+    }
+
+    /// Queues a fighter to be added after the current turn. The spawn's
+    /// position is where it came from, it gets placed on a free tile
+    /// next to that. Spawns are added in the order they were queued.
+    pub fn queue_spawn(&mut self, spawn: FighterSpawn) {
+        self.pending_spawns.push(spawn);
+    }
+
+    pub fn take_pending_spawns(&mut self) -> Vec<FighterSpawn> {
+        std::mem::take(&mut self.pending_spawns)
+    }
+
+    /// The positions and amounts of all the treasure left lying around,
+    /// not counting the final treasure.
+    pub fn treasure_positions(&self) -> impl Iterator<Item = (Point, i32)> + '_ {
+        self.treasure_positions
+            .iter()
+            .filter_map(move |&position| Some((position, self.get_treasure(position.x, position.y)?.amount)))
+    }
+
+    /// The sum of the treasure left lying around on the level.
+    pub fn remaining_treasure(&self) -> i32 {
+        self.treasure_positions().map(|(_, amount)| amount).sum()
+    }
+
+    /// All the treasure that can still be picked up on the level, the
+    /// final treasure included.
+    pub fn collectable_treasure(&self) -> i32 {
+        if self.has_final_treasure && !self.final_treasure_found {
+            self.remaining_treasure() + FINAL_TREASURE_AMOUNT
+        } else {
+            self.remaining_treasure()
         }
     }
 
@@ -538,58 +914,82 @@ impl Level {
         camera: &Camera,
         show_debug: bool,
     ) -> bool {
-        if x == self.line_of_sight_x && y == self.line_of_sight_y {
-            return true;
+        let from = Point::new(self.line_of_sight_x, self.line_of_sight_y);
+        if !show_debug {
+            return self.trace_line_of_sight(from, x, y, |_, _, _, _| {});
         }
-
-        let (target_x, target_y) = (x as f32 + 0.5, y as f32 + 0.5);
-        let (mut cursor_x, mut cursor_y) = (self.line_of_sight_x as f32 + 0.5, self.line_of_sight_y as f32 + 0.5);
-        let dx = target_x - cursor_x;
-        let dy = target_y - cursor_y;
-        let dl = (dx * dx + dy * dy).sqrt();
-        let dx = dx / dl;
-        let dy = dy / dl;
-
-        if show_debug {
+        self.trace_line_of_sight(from, x, y, |cursor_x, cursor_y, dx, dy| {
             canvas.set_draw_color(Color::RGBA(
                 (0xDD as f32 + 0x11 as f32 * dx) as u8,
                 0xFF,
                 (0xDD as f32 + 0x11 as f32 * dy) as u8,
                 0x88,
             ));
+            let _ = canvas.draw_point(sdl2::rect::Point::new(
+                (cursor_x * TILE_STRIDE as f32) as i32 - camera.x,
+                (cursor_y * TILE_STRIDE as f32) as i32 - camera.y,
+            ));
+        })
+    }
+
+    /// Whether the tile at (x, y) can be seen from `from`, without
+    /// walls in between.
+    pub fn is_visible_from(&self, from: Point, x: i32, y: i32) -> bool {
+        self.trace_line_of_sight(from, x, y, |_, _, _, _| {})
+    }
+
+    /// Walks a ray from the center of `from` towards the center of
+    /// (x, y) one tile at a time, calling `visit` with the ray's
+    /// position and direction in each tile it enters.
+    fn trace_line_of_sight<F: FnMut(f32, f32, f32, f32)>(&self, from: Point, x: i32, y: i32, mut visit: F) -> bool {
+        if x == from.x && y == from.y {
+            return true;
         }
 
-        let mut iterations = 0;
-        while (((target_x - cursor_x) * dx).signum() == 1.0 || ((target_y - cursor_y) * dy).signum() == 1.0)
-            && iterations < 200
-        {
-            iterations += 1;
-            if show_debug {
-                let _ = canvas.draw_point(sdl2::rect::Point::new(
-                    (cursor_x * TILE_STRIDE as f32) as i32 - camera.x,
-                    (cursor_y * TILE_STRIDE as f32) as i32 - camera.y,
-                ));
-            }
+        let (start_x, start_y) = (from.x as f32 + 0.5, from.y as f32 + 0.5);
+        let dx = (x - from.x) as f32;
+        let dy = (y - from.y) as f32;
+        let dl = (dx * dx + dy * dy).sqrt();
+        let dx = dx / dl;
+        let dy = dy / dl;
 
-            let distance_to_next_x = if dx >= 0.0 {
-                1.0 - cursor_x.fract()
-            } else if cursor_x.fract() == 0.0 {
-                1.0
+        // How far along the ray the next vertical and horizontal tile
+        // edges are, and how far apart the edges are along the ray.
+        // The ray starts from a tile's center, so the first edge is
+        // half a tile away.
+        let (step_x, step_y) = ((x - from.x).signum(), (y - from.y).signum());
+        let delta_x = if step_x == 0 { f32::INFINITY } else { 1.0 / dx.abs() };
+        let delta_y = if step_y == 0 { f32::INFINITY } else { 1.0 / dy.abs() };
+        let (mut next_x, mut next_y) = (delta_x / 2.0, delta_y / 2.0);
+
+        // Each step enters a new tile, so the target is at most this
+        // many steps away. The margin is just for peace of mind.
+        let max_steps = (x - from.x).abs() + (y - from.y).abs() + 2;
+        let (mut tile_x, mut tile_y) = (from.x, from.y);
+        for _ in 0..max_steps {
+            // Rays that pass (almost) exactly through a corner go to the
+            // diagonal tile, rounding errors shouldn't decide which of
+            // the two side tiles blocks the view.
+            let t = if (next_x - next_y).abs() < CORNER_EPSILON {
+                let t = next_x;
+                tile_x += step_x;
+                tile_y += step_y;
+                next_x += delta_x;
+                next_y += delta_y;
+                t
+            } else if next_x < next_y {
+                let t = next_x;
+                tile_x += step_x;
+                next_x += delta_x;
+                t
             } else {
-                cursor_x.fract()
+                let t = next_y;
+                tile_y += step_y;
+                next_y += delta_y;
+                t
             };
-            let distance_to_next_y = if dy >= 0.0 {
-                1.0 - cursor_y.fract()
-            } else if cursor_y.fract() == 0.0 {
-                1.0
-            } else {
-                cursor_y.fract()
-            };
-            let d = distance_to_next_x.min(distance_to_next_y) + 0.1;
-            cursor_x += dx * d;
-            cursor_y += dy * d;
-            let tile_x = cursor_x as i32;
-            let tile_y = cursor_y as i32;
+            visit(start_x + dx * t, start_y + dy * t, dx, dy);
+
             if self.get_terrain(tile_x, tile_y).unwalkable() {
                 return false;
             } else if tile_x == x && tile_y == y {
@@ -614,26 +1014,33 @@ impl Level {
         tile_painter: &mut TilePainter,
         camera: &Camera,
         layer: TileLayer,
-        show_debug: bool,
         dark_fade: bool,
-        magma_level: bool,
+        settings: LevelDrawSettings,
     ) {
+        let LevelDrawSettings {
+            show_debug,
+            fade_radius,
+            magma_level,
+            finger,
+            die,
+        } = settings;
         let offset_x = camera.x / TILE_STRIDE;
         let offset_y = camera.y / TILE_STRIDE;
         let (screen_width, screen_height) = canvas.output_size().unwrap();
         let tiles_x = screen_width as i32 / TILE_STRIDE + 2;
         let tiles_y = screen_height as i32 / TILE_STRIDE + 2;
 
+        // Once the final treasure is found, the whole level is revealed
+        let reveal_all = self.final_treasure_found;
+
         // Precalculate line of sight (if needed)
         let mut los_cache = self.line_of_sight_cache.borrow_mut();
-        let line_of_sight: &[bool] = if layer == TileLayer::AboveAll {
+        let line_of_sight: &[bool] = if layer == TileLayer::AboveAll && !reveal_all {
             let key = (
                 Point::new(self.line_of_sight_x, self.line_of_sight_y),
                 Rect::new(offset_x, offset_y, tiles_x as u32, tiles_y as u32),
             );
-            if let Some(cached_los) = los_cache.get(&key) {
-                cached_los
-            } else {
+            if los_cache.get(&key).is_none() {
                 let mut line_of_sight = Vec::with_capacity((tiles_x * tiles_y) as usize);
                 for y in 0..tiles_y {
                     let tile_y = y + offset_y;
@@ -643,8 +1050,8 @@ impl Level {
                     }
                 }
                 los_cache.insert(key, line_of_sight);
-                los_cache.get(&key).as_ref().unwrap()
             }
+            los_cache.get(&key).unwrap()
         } else {
             &[]
         };
@@ -770,8 +1177,8 @@ impl Level {
                     }
 
                     // Draw the tile
-                    let x = tile_x as i32 * TILE_STRIDE + x_offset - camera.x;
-                    let y = tile_y as i32 * TILE_STRIDE + y_offset - camera.y;
+                    let x = tile_x * TILE_STRIDE + x_offset - camera.x;
+                    let y = tile_y * TILE_STRIDE + y_offset - camera.y;
                     let flip_h = (flags & FLAG_FLIP_H) != 0;
                     let flip_v = (flags & FLAG_FLIP_V) != 0;
                     if (flags & FLAG_SHDW) != 0 {
@@ -782,7 +1189,17 @@ impl Level {
                 }
 
                 // Line of sight stuff
-                if layer == TileLayer::AboveAll {
+                if layer == TileLayer::AboveAll && reveal_all {
+                    if terrain == Terrain::Empty && dark_fade {
+                        canvas.set_draw_color(Color::RGB(0x1A, 0x1A, 0x22));
+                        let _ = canvas.fill_rect(Rect::new(
+                            tile_x * TILE_STRIDE - camera.x,
+                            tile_y * TILE_STRIDE - camera.y,
+                            TILE_STRIDE as u32,
+                            TILE_STRIDE as u32,
+                        ));
+                    }
+                } else if layer == TileLayer::AboveAll {
                     let mut current_tile_is_in_los = false;
                     'los_check: for y_ in 0..=2 {
                         for x_ in -1..=1 {
@@ -799,14 +1216,13 @@ impl Level {
                         } else {
                             canvas.set_draw_color(Color::RGB(0x44, 0x44, 0x44));
                         }
-                    } else if dark_fade {
+                    } else if let (true, Some(range)) = (dark_fade, fade_radius) {
                         let dx = (tile_x - self.line_of_sight_x) as f32;
                         let dy = (tile_y - self.line_of_sight_y) as f32;
-                        let range = if magma_level { 5.5 } else { 7.0 };
                         let alpha = (0xFF as f32 * ((dx * dx + dy * dy).sqrt() / range).min(1.0).powf(2.0)) as u8;
                         canvas.set_draw_color(Color::RGBA(0x1A, 0x1A, 0x22, alpha));
                     }
-                    if !current_tile_is_in_los || dark_fade {
+                    if !current_tile_is_in_los || (dark_fade && fade_radius.is_some()) {
                         let _ = canvas.fill_rect(Rect::new(
                             tile_x * TILE_STRIDE - camera.x,
                             tile_y * TILE_STRIDE - camera.y,
@@ -816,6 +1232,20 @@ impl Level {
                     }
                 }
 
+                // Lock difficulty indicators
+                if let (TileLayer::AboveAll, Terrain::LockedDoor { roll_threshold }) = (layer, terrain) {
+                    let visible = reveal_all || (0..=2).any(|y_| (-1..=1).any(|x_| in_line_of_sight(x + x_, y + y_)));
+                    if visible {
+                        draw_lock_difficulty(
+                            canvas,
+                            camera,
+                            tile_x,
+                            tile_y,
+                            die.success_chance(roll_threshold - finger),
+                        );
+                    }
+                }
+
                 // Debug rectangles
                 if show_debug && terrain.unwalkable() {
                     canvas.set_draw_color(Color::RGB(0xCC, 0x44, 0x11));
@@ -847,8 +1277,8 @@ impl Level {
             for x in 0..tiles_x {
                 let tile_x = x + offset_x;
                 if self.get_treasure(tile_x, tile_y).is_some() {
-                    let x = tile_x as i32 * TILE_STRIDE - camera.x;
-                    let y = tile_y as i32 * TILE_STRIDE - camera.y;
+                    let x = tile_x * TILE_STRIDE - camera.x;
+                    let y = tile_y * TILE_STRIDE - camera.y;
                     tile_painter.draw_tile_shadowed(
                         canvas,
                         TileGraphic::MineralsScattered,
@@ -895,11 +1325,350 @@ impl Level {
                 };
 
                 for tile in tiles {
-                    let x = tile_x as i32 * TILE_STRIDE - camera.x;
-                    let y = tile_y as i32 * TILE_STRIDE - camera.y;
+                    let x = tile_x * TILE_STRIDE - camera.x;
+                    let y = tile_y * TILE_STRIDE - camera.y;
                     tile_painter.draw_tile(canvas, *tile, x, y, false, false);
                 }
             }
         }
     }
 }
+
+/// Draws a small light over a locked door: green if the player
+/// unlocks it at least every other try, yellow if it takes more tries,
+/// red if no roll is enough.
+fn draw_lock_difficulty<RT: RenderTarget>(
+    canvas: &mut Canvas<RT>,
+    camera: &Camera,
+    tile_x: i32,
+    tile_y: i32,
+    unlock_chance: f32,
+) {
+    let color = if unlock_chance >= 0.5 {
+        Color::RGB(0x55, 0xCC, 0x44)
+    } else if unlock_chance > 0.0 {
+        Color::RGB(0xDD, 0xBB, 0x33)
+    } else {
+        Color::RGB(0xCC, 0x33, 0x22)
+    };
+    let size = 12;
+    let x = tile_x * TILE_STRIDE + (TILE_STRIDE - size) / 2 - camera.x;
+    let y = tile_y * TILE_STRIDE - TILE_STRIDE / 2 - camera.y;
+    canvas.set_draw_color(Color::RGB(0x22, 0x22, 0x22));
+    let _ = canvas.fill_rect(Rect::new(x - 2, y - 2, size as u32 + 4, size as u32 + 4));
+    canvas.set_draw_color(color);
+    let _ = canvas.fill_rect(Rect::new(x, y, size as u32, size as u32));
+}
+
+/// A random number in `0..bound`, or 0 if the bound is 0, so that
+/// shrinking the rooms can't cause a division by zero. Always takes a
+/// number from the rng, to keep the rest of the generation the same.
+fn random_below(rng: &mut Pcg32, bound: u32) -> u32 {
+    rng.next_u32() % bound.max(1)
+}
+
+/// Picks one of the choices, each with a chance proportional to its
+/// weight. Always takes exactly one number from the rng.
+fn weighted_choice<'a, T>(rng: &mut Pcg32, choices: &'a [(T, u32)]) -> &'a T {
+    let total_weight = choices.iter().map(|(_, weight)| weight).sum();
+    let mut roll = random_below(rng, total_weight);
+    for (choice, weight) in choices {
+        if roll < *weight {
+            return choice;
+        }
+        roll -= weight;
+    }
+    &choices.last().expect("there should be something to choose from").0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: std::ops::Range<u64> = 0..64;
+
+    fn for_each_level<F: FnMut(u64, u32, &Level)>(mut f: F) {
+        for seed in SEEDS {
+            for difficulty in 0..4 {
+                f(seed, difficulty, &Level::new_with_seed(seed, difficulty));
+            }
+        }
+    }
+
+    #[test]
+    fn random_below_handles_empty_ranges() {
+        let mut rng = Pcg32::seed_from_u64(0);
+        for bound in 0..4 {
+            assert!(random_below(&mut rng, bound) <= bound.saturating_sub(1));
+        }
+    }
+
+    #[test]
+    fn enemy_spawn_weights_pick_the_same_enemies_as_the_old_roll_ranges() {
+        for difficulty in 0..4u32 {
+            let mut old_rng = Pcg32::seed_from_u64(difficulty as u64);
+            let mut new_rng = Pcg32::seed_from_u64(difficulty as u64);
+            for _ in 0..100 {
+                let old_spawn = match old_rng.next_u32() % 10 + difficulty * 3 {
+                    0..=7 => SPAWN_SLIME,
+                    8..=12 => SPAWN_ROACH,
+                    13..=15 => SPAWN_ROCKMAN,
+                    16..=17 => SPAWN_SENTIENT_METAL,
+                    _ => SPAWN_ROCKMAN,
+                };
+                let new_spawn = weighted_choice(&mut new_rng, ENEMY_SPAWN_WEIGHTS[difficulty as usize]);
+                assert_eq!(old_spawn.name, new_spawn.name);
+            }
+        }
+    }
+
+    #[test]
+    fn settling_generation_never_fails() {
+        for seed in SEEDS {
+            for difficulty in 0..4 {
+                let mut rng = Pcg32::seed_from_u64(seed);
+                let level = Level::generate(&mut rng, difficulty, 1, true).unwrap();
+                assert_eq!(level.spawns[0].name, Name::Astronaut);
+            }
+        }
+    }
+
+    #[test]
+    fn rooms_are_in_bounds() {
+        let bounds = Rect::new(0, 0, LEVEL_WIDTH as u32, LEVEL_HEIGHT as u32);
+        for_each_level(|seed, difficulty, level| {
+            for room in &level.rooms {
+                let with_walls = Rect::new(room.x - 1, room.y - 1, room.width() + 2, room.height() + 2);
+                assert!(
+                    bounds.contains_rect(with_walls),
+                    "seed {}, difficulty {}: room {:?} out of bounds",
+                    seed,
+                    difficulty,
+                    room
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn no_double_wide_walls() {
+        for_each_level(|seed, difficulty, level| {
+            for y in 0..LEVEL_HEIGHT as i32 - 1 {
+                for x in 0..LEVEL_WIDTH as i32 - 1 {
+                    let wall_block = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .all(|(dx, dy)| level.get_terrain(x + dx, y + dy) == Terrain::Wall);
+                    assert!(
+                        !wall_block,
+                        "seed {}, difficulty {}: double-wide wall at ({}, {})",
+                        seed, difficulty, x, y
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn player_spawns_on_floor() {
+        for_each_level(|seed, difficulty, level| {
+            let player = &level.spawns[0];
+            assert_eq!(player.name, Name::Astronaut);
+            assert_eq!(
+                level.get_terrain(player.x, player.y),
+                Terrain::Floor,
+                "seed {}, difficulty {}",
+                seed,
+                difficulty
+            );
+        });
+    }
+
+    #[test]
+    fn levels_have_enough_rooms() {
+        for_each_level(|seed, difficulty, level| {
+            assert!(
+                level.rooms.len() >= MIN_ROOM_COUNT,
+                "seed {}, difficulty {}: only {} rooms",
+                seed,
+                difficulty,
+                level.rooms.len()
+            );
+        });
+    }
+
+    #[test]
+    fn exactly_one_way_to_finish() {
+        for_each_level(|seed, difficulty, level| {
+            let exits = level.terrain.iter().filter(|t| **t == Terrain::Exit).count();
+            let final_treasures = level.terrain.iter().filter(|t| **t == Terrain::FinalTreasure).count();
+            assert_eq!(exits + final_treasures, 1, "seed {}, difficulty {}", seed, difficulty);
+            if difficulty < 3 {
+                assert_eq!(exits, 1, "seed {}, difficulty {}", seed, difficulty);
+            } else {
+                assert_eq!(final_treasures, 1, "seed {}, difficulty {}", seed, difficulty);
+            }
+        });
+    }
+
+    #[test]
+    fn treasure_positions_stay_in_sync() {
+        for_each_level(|seed, difficulty, level| {
+            let mut level = level.clone();
+            let scanned = level.treasure.iter().flatten().map(|t| t.amount).sum::<i32>();
+            assert_eq!(
+                level.remaining_treasure(),
+                scanned,
+                "seed {}, difficulty {}",
+                seed,
+                difficulty
+            );
+
+            let first = level.treasure_positions().next();
+            if let Some((position, amount)) = first {
+                assert_eq!(level.take_treasure(position.x, position.y), amount);
+                assert_eq!(level.remaining_treasure(), scanned - amount);
+                level.put_treasure(position.x, position.y, 3);
+                level.put_treasure(position.x, position.y, 2);
+                assert_eq!(level.remaining_treasure(), scanned - amount + 5);
+                assert_eq!(level.treasure_positions().filter(|(p, _)| *p == position).count(), 1);
+            }
+        });
+    }
+
+    #[test]
+    fn treasure_only_on_floor() {
+        for_each_level(|seed, difficulty, level| {
+            for (i, treasure) in level.treasure.iter().enumerate() {
+                if treasure.is_some() {
+                    let (x, y) = (i % LEVEL_WIDTH, i / LEVEL_WIDTH);
+                    // The exit goes on a floor tile after the treasure is
+                    // scattered, so it can end up on top of some
+                    assert!(
+                        matches!(
+                            level.terrain[i],
+                            Terrain::Floor | Terrain::Exit | Terrain::FinalTreasure
+                        ),
+                        "seed {}, difficulty {}: treasure at ({}, {})",
+                        seed,
+                        difficulty,
+                        x,
+                        y
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn paths_are_walkable_steps() {
+        for_each_level(|seed, difficulty, level| {
+            let player = &level.spawns[0];
+            let from = Point::new(player.x, player.y);
+            let to = level
+                .terrain
+                .iter()
+                .position(|t| *t == Terrain::Exit || *t == Terrain::FinalTreasure)
+                .map(|i| Point::new((i % LEVEL_WIDTH) as i32, (i / LEVEL_WIDTH) as i32))
+                .unwrap();
+            let path = level
+                .find_path(from, to, usize::MAX, |_, terrain| {
+                    terrain != Terrain::Wall && terrain != Terrain::Empty
+                })
+                .unwrap_or_else(|| panic!("seed {}, difficulty {}: exit is unreachable", seed, difficulty));
+            assert_eq!(path.last(), Some(&to));
+            let mut previous = from;
+            for point in path {
+                assert_eq!((point.x - previous.x).abs() + (point.y - previous.y).abs(), 1);
+                let terrain = level.get_terrain(point.x, point.y);
+                assert!(terrain != Terrain::Wall && terrain != Terrain::Empty);
+                previous = point;
+            }
+
+            let nowhere_to_walk = level.find_path(from, to, usize::MAX, |_, _| false);
+            assert_eq!(nowhere_to_walk, None, "seed {}, difficulty {}", seed, difficulty);
+        });
+    }
+
+    #[test]
+    fn long_sightlines_reach_their_target() {
+        let mut level = Level::new_with_seed(0, 0);
+        for terrain in level.terrain.iter_mut() {
+            *terrain = Terrain::Floor;
+        }
+        let from = Point::new(1, 1);
+        for &(x, y) in &[(120, 1), (1, 120), (120, 120), (120, 37), (37, 120), (90, 31)] {
+            assert!(level.is_visible_from(from, x, y), "({}, {}) not visible", x, y);
+            assert!(level.is_visible_from(Point::new(x, y), from.x, from.y));
+        }
+
+        // A single wall tile on the way blocks the view
+        level.terrain[61 + 61 * LEVEL_WIDTH] = Terrain::Wall;
+        assert!(!level.is_visible_from(from, 120, 120));
+        // Rays passing through a corner continue in the diagonal tile
+        level.terrain[61 + 61 * LEVEL_WIDTH] = Terrain::Floor;
+        assert!(level.is_visible_from(from, 7, 3));
+        level.terrain[3 + 2 * LEVEL_WIDTH] = Terrain::Wall;
+        assert!(!level.is_visible_from(from, 7, 3));
+    }
+
+    #[test]
+    fn opening_a_door_clears_the_line_of_sight_cache() {
+        let mut level = Level::new_with_seed(0, 0);
+        let door = level
+            .terrain
+            .iter()
+            .position(|t| *t == Terrain::Door)
+            .map(|i| Point::new((i % LEVEL_WIDTH) as i32, (i / LEVEL_WIDTH) as i32))
+            .unwrap();
+        let key = (door, Rect::new(0, 0, 1, 1));
+        level.line_of_sight_cache.borrow_mut().insert(key, vec![false]);
+        level.open_door(door.x, door.y);
+        assert_eq!(level.get_terrain(door.x, door.y), Terrain::DoorOpen);
+        assert!(level.line_of_sight_cache.borrow().grids.is_empty());
+    }
+
+    #[test]
+    fn line_of_sight_cache_keeps_the_recently_used_grids() {
+        let key = |i: i32| (Point::new(i, 0), Rect::new(0, 0, 1, 1));
+        let mut cache = LineOfSightCache::default();
+        for i in 0..LINE_OF_SIGHT_CACHE_SIZE as i32 {
+            cache.insert(key(i), vec![true]);
+        }
+        // Using the oldest grid saves it from the next eviction
+        assert!(cache.get(&key(0)).is_some());
+        cache.insert(key(100), vec![true]);
+        assert_eq!(cache.grids.len(), LINE_OF_SIGHT_CACHE_SIZE);
+        assert!(cache.get(&key(0)).is_some());
+        assert!(cache.get(&key(1)).is_none());
+        assert!(cache.get(&key(100)).is_some());
+    }
+
+    #[test]
+    fn boss_guards_the_final_treasure() {
+        for_each_level(|seed, difficulty, level| {
+            let bosses: Vec<&FighterSpawn> = level
+                .spawns
+                .iter()
+                .filter(|spawn| spawn.name == Name::MetalOverseer)
+                .collect();
+            if difficulty < 3 {
+                assert!(bosses.is_empty(), "seed {}, difficulty {}", seed, difficulty);
+            } else {
+                assert_eq!(bosses.len(), 1, "seed {}, difficulty {}", seed, difficulty);
+                let boss = bosses[0];
+                assert_eq!(level.get_terrain(boss.x, boss.y), Terrain::Floor);
+                let guarded = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .iter()
+                    .any(|&(dx, dy)| level.get_terrain(boss.x + dx, boss.y + dy) == Terrain::FinalTreasure);
+                assert!(guarded, "seed {}, difficulty {}", seed, difficulty);
+            }
+            assert_eq!(
+                level.spawns[0].name,
+                Name::Astronaut,
+                "seed {}, difficulty {}",
+                seed,
+                difficulty
+            );
+        });
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{interface, Font, StatIncrease, Text};
+use crate::{
+    interface, stats, AnimationSpeed, Die, Font, Hint, RunStats, StatIncrease, Stats, StatusKind, Terrain, Text,
+    CARRY_CAP, MAX_UNDO_DEPTH,
+};
 use sdl2::pixels::Color;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -16,6 +19,8 @@ pub enum Name {
     Roach,
     Rockman,
     SentientMetal,
+    MetalOverseer,
+    RepairDrone,
 }
 
 impl Name {
@@ -50,6 +55,14 @@ impl Name {
                 Language::Debug => unreachable!(),
                 Language::English => String::from("Superior Metal Being"),
             },
+            Name::MetalOverseer => match language {
+                Language::Debug => unreachable!(),
+                Language::English => String::from("Superior Metal Overseer"),
+            },
+            Name::RepairDrone => match language {
+                Language::Debug => unreachable!(),
+                Language::English => String::from("Repair Drone"),
+            },
         }
     }
 }
@@ -74,6 +87,20 @@ pub enum LocalizableString {
         defender_leg: i32,
     },
     SomeoneWasIncapacitated(Name),
+    SomeoneWasPushed(Name),
+    SomeoneWasSlammed(Name),
+    SomeoneGotStatus {
+        name: Name,
+        status: StatusKind,
+    },
+    SomeoneHurtByStatus {
+        name: Name,
+        status: StatusKind,
+        damage: i32,
+    },
+    TurnLostToStun(Name),
+    TreasureDropped(i32),
+    TreasurePickedUp(i32),
 
     DoorUnlocked {
         roll_threshold: i32,
@@ -84,6 +111,7 @@ pub enum LocalizableString {
         roll_threshold: i32,
         roll: i32,
         finger: i32,
+        die: Die,
     },
 
     FighterDescription {
@@ -91,25 +119,103 @@ pub enum LocalizableString {
         name: Name,
         max_health: i32,
         health: i32,
-        arm: i32,
-        leg: i32,
+    },
+
+    FighterStat {
+        stat: StatIncrease,
+        value: i32,
+    },
+
+    /// What the examine cursor is pointing at. Everything is None if
+    /// the tile can't be seen.
+    TileDescription {
+        terrain: Option<Terrain>,
+        treasure: Option<i32>,
+        fighter: Option<Name>,
         finger: i32,
+        die: Die,
+    },
+    /// What the terrain is and does, `finger` being the player's and
+    /// `die` the run's, for explaining locks.
+    TerrainDescription {
+        terrain: Terrain,
+        finger: i32,
+        die: Die,
+    },
+
+    CombatOdds {
+        hit_chance: f32,
+        expected_damage: f32,
+        hit_back_chance: f32,
+    },
+
+    ActionsUntilTrained {
+        stat: StatIncrease,
+        actions: u32,
     },
 
     GameOver {
         name: Name,
+        run_stats: RunStats,
+    },
+    Victory {
+        run_stats: RunStats,
+    },
+    RunAbandoned {
+        run_stats: RunStats,
+    },
+    PauseMenu,
+    CopyDiagnosticsButton,
+    Diagnostics(String),
+    ResumeButton,
+    SaveButton,
+    SettingsButton,
+    AbandonRunButton,
+    SettingsMenu,
+    BorderlessWindowButton(bool),
+    /// The multiplier for the dark fade's radius, None if it's off.
+    DarkFadeButton(Option<f32>),
+    ThreatHighlightsButton(bool),
+    DamagedHealthBarsButton(bool),
+    AnimationSpeedButton(AnimationSpeed),
+    BackButton,
+    RunOptionsButton,
+    RunOptionsMenu,
+    OxygenModeButton(bool),
+    DieButton(Die),
+    CarryCapButton(bool),
+    CompanionButton(bool),
+    PeacefulModeButton(bool),
+    UndoButton(bool),
+    ManualPickupButton(bool),
+    TreasureMagnetButton(bool),
+    PersonalBest {
+        treasure: i32,
+        fastest_finish: Option<u64>,
+        new_record: bool,
     },
-    Victory,
 
     BigConfirmButton,
     EraseButton,
     NameInputInfo,
+    DisplayNameInput {
+        display_name: String,
+        active: bool,
+    },
     RestartButton,
     QuitButton,
     SubmitToLeaderboardsButton,
     LevelUpMessage(u32),
-    StatInfo(StatIncrease),
+    StatInfo {
+        stat: StatIncrease,
+        current: i32,
+        die: Die,
+    },
     IncreaseStatButton(StatIncrease),
+    IncreaseStatByOneButton(StatIncrease),
+    StatPointsRemaining {
+        first_pick: Option<StatIncrease>,
+    },
 
     StatIncreaseByTraining {
         stat: StatIncrease,
@@ -117,17 +223,116 @@ pub enum LocalizableString {
     },
 
     LeaderboardsHeader,
+    PeacefulLeaderboardsHeader,
     LeaderboardsEmpty,
+    LeaderboardsOffline {
+        seconds_ago: u64,
+    },
     LeaderboardsTitleName,
     LeaderboardsTitleTreasure,
     LeaderboardsTitleRounds,
-    LeaderboardsName([char; 3]),
+    LeaderboardsName([char; 3], String),
     LeaderboardsTreasure(i32),
     LeaderboardsRounds(Option<u64>),
-    LeaderboardsSortByButton,
+    /// The parameter is the sort direction if the column is the
+    /// one the leaderboards are currently sorted by.
+    LeaderboardsSortByButton(Option<bool>),
+
+    InWorldClock {
+        round: u64,
+    },
+    TreasureRemaining(i32),
+    ToastLevelCleared,
+    ToastLockedRoomOpened,
+    ToastNewEnemy(Name),
+    ToastCriticalHealth,
+
+    TreasureMagnetActivated,
+    OxygenRanOut,
+    Suffocating,
+    RunOptionsChanged,
+    SeededRun(u64),
+
+    Hint(Hint),
+    DismissHintButton,
+
+    RecoveryPrompt,
+    RecoverSessionButton,
+    StartFreshButton,
+
+    QuitPrompt,
+    SaveAndQuitButton,
+    QuitAnywayButton,
+    CancelButton,
+
+    BestiaryHeader {
+        encountered: usize,
+        total: usize,
+    },
+    /// The base stats and a description of the enemy.
+    BestiaryEntry {
+        name: Name,
+        stats: Stats,
+    },
+    BestiaryUnknown,
+
+    AutosavesHeader(usize),
+    LoadAutosaveButton {
+        level: usize,
+        round: u64,
+        treasure: i32,
+        seconds_ago: u64,
+    },
+}
+
+/// The explanation of what it takes to open a lock, shared by the
+/// failed unlocking message and the locked door description so the
+/// numbers match.
+fn unlock_requirement(roll_threshold: i32, finger: i32, die: Die) -> String {
+    format!(
+        "Unlocking{} would require a roll of {} (Lock {} - Finger {}).",
+        if roll_threshold - finger > die.sides() {
+            " is impossible with current Finger, as it"
+        } else {
+            ""
+        },
+        roll_threshold - finger,
+        roll_threshold,
+        finger,
+    )
+}
+
+/// The in-world date and time at the start of the given round, a
+/// round being one second.
+pub fn in_world_timestamp(round: u64) -> String {
+    format!(
+        "21XX-03-{d:x} T {h:02}:{m:02}:{s:02}",
+        d = 0x14 + round / 60 / 60 / 24,
+        h = (5 + round / 60 / 60) % 24,
+        m = (31 + round / 60) % 60,
+        s = round % 60
+    )
 }
 
 impl LocalizableString {
+    fn run_stats_summary(run_stats: &RunStats, language: Language) -> Text {
+        match language {
+            Language::Debug => unreachable!(),
+            Language::English => Text(
+                Font::RegularUi,
+                14.0,
+                Color::RGB(0x99, 0x99, 0x99),
+                format!(
+                    "\nIncapacitated {} enemies, walked {} tiles, opened {} locked doors, took {} damage.\n",
+                    run_stats.enemies_incapacitated,
+                    run_stats.tiles_walked,
+                    run_stats.locked_doors_opened,
+                    run_stats.damage_taken,
+                ),
+            ),
+        }
+    }
+
     pub fn localize(&self, language: Language) -> Vec<Text> {
         if let LocalizableString::Character(c, size, color) = self {
             return vec![Text(Font::RegularUi, *size, *color, format!("{}", c))];
@@ -139,6 +344,8 @@ impl LocalizableString {
         const SMALLER_FONT_SIZE: f32 = 14.0;
         const BIGGER_FONT_SIZE: f32 = 18.0;
         const COMMENT_COLOR: Color = Color::RGB(0x99, 0x99, 0x99);
+        // A typical lock on the first levels, for the lockpicking odds
+        const REFERENCE_LOCK: i32 = 16;
         match self {
             LocalizableString::Character(_, _, _) => unreachable!(),
 
@@ -213,6 +420,79 @@ impl LocalizableString {
                 )],
             },
 
+            LocalizableString::SomeoneWasPushed(name) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                    format!("{} is pushed back by the blow.\n", name.translated_to(language)),
+                )],
+            },
+
+            LocalizableString::SomeoneWasSlammed(name) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                    format!("{} is slammed against something, taking 1 extra damage.\n", name.translated_to(language)),
+                )],
+            },
+
+            LocalizableString::SomeoneGotStatus { name, status } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                    format!(
+                        "{} {}.\n",
+                        name.translated_to(language),
+                        match status {
+                            StatusKind::Poison => "is poisoned",
+                            StatusKind::Stun => "is stunned",
+                            StatusKind::Burning => "catches fire",
+                        },
+                    ),
+                )],
+            },
+
+            LocalizableString::SomeoneHurtByStatus { name, status, damage } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                    format!(
+                        "{} takes {} damage from {}.\n",
+                        name.translated_to(language),
+                        damage,
+                        match status {
+                            StatusKind::Poison => "the poison",
+                            StatusKind::Stun => "being stunned",
+                            StatusKind::Burning => "the flames",
+                        },
+                    ),
+                )],
+            },
+
+            LocalizableString::TurnLostToStun(name) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                    format!("{} is stunned and can't act.\n", name.translated_to(language)),
+                )],
+            },
+
+            LocalizableString::TreasureDropped(amount) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                    format!("You leave {} minerals on the ground.\n", amount),
+                )],
+            },
+
+            LocalizableString::TreasurePickedUp(amount) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                    format!("You pick up {} minerals.\n", amount),
+                )],
+            },
+
             LocalizableString::DoorUnlocked {
                 roll_threshold,
                 roll,
@@ -240,6 +520,7 @@ impl LocalizableString {
                 roll_threshold,
                 roll,
                 finger,
+                die,
             } => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
@@ -249,13 +530,7 @@ impl LocalizableString {
                     ),
                     Text(
                         Font::RegularUi, NORMAL_FONT_SIZE, COMMENT_COLOR,
-                        format!(
-                            "Unlocking{} would require a roll of {} (Lock {} - Finger {}).\n",
-                            if roll_threshold - finger > 6 { " is impossible with current Finger, as it" } else { "" },
-                            roll_threshold - finger,
-                            roll_threshold,
-                            finger,
-                        ),
+                        format!("{}\n", unlock_requirement(*roll_threshold, *finger, *die)),
                     ),
                 ],
             },
@@ -265,9 +540,6 @@ impl LocalizableString {
                 name,
                 max_health,
                 health,
-                arm,
-                leg,
-                finger,
             } => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
@@ -278,11 +550,11 @@ impl LocalizableString {
                         format!(
                             "{}{}{}\n",
                             name.translated_to(language),
-                            if *id <= 0 { " (that's you)" } else { "" },
+                            if *id == 0 { " (that's you)" } else { "" },
                             if *health <= 0 { " (dead)" } else { "" },
                         ),
                     ),
-                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, format!("Health: ")),
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Health: ")),
                     Text(
                         Font::RegularUi,
                         20.0,
@@ -296,19 +568,64 @@ impl LocalizableString {
                         format!("{}", health),
                     ),
                     Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE, format!("/{}", max_health)),
-                    Text(
-                        Font::RegularUi,
-                        NORMAL_FONT_SIZE,
-                        Color::WHITE,
-                        format!(
-                            "\nArm: {}\nLeg: {}\nFinger: {}\n",
-                            arm, leg, finger
-                        ),
-                    ),
                 ],
             },
 
-            LocalizableString::GameOver { name } => match language {
+            LocalizableString::FighterStat { stat, value } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi,
+                    NORMAL_FONT_SIZE,
+                    Color::WHITE,
+                    match stat {
+                        StatIncrease::Arm => format!("Arm: {}", value),
+                        StatIncrease::Leg => format!("Leg: {}", value),
+                        StatIncrease::Finger => format!("Finger: {}", value),
+                    },
+                )],
+            },
+
+            LocalizableString::ActionsUntilTrained { stat, actions } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi,
+                    SMALLER_FONT_SIZE,
+                    Color::RGB(0xAA, 0xAA, 0xAA),
+                    match (stat, actions) {
+                        (StatIncrease::Arm, 1) => String::from("1 hit"),
+                        (StatIncrease::Arm, n) => format!("{} hits", n),
+                        (StatIncrease::Leg, 1) => String::from("1 step"),
+                        (StatIncrease::Leg, n) => format!("{} steps", n),
+                        (StatIncrease::Finger, 1) => String::from("1 lock"),
+                        (StatIncrease::Finger, n) => format!("{} locks", n),
+                    },
+                )],
+            },
+
+            LocalizableString::PersonalBest {
+                treasure,
+                fastest_finish,
+                new_record,
+            } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => {
+                    let mut texts = Vec::new();
+                    if *new_record {
+                        texts.push(Text(
+                            Font::RegularUi, NORMAL_FONT_SIZE, interface::HEALTH_HIGH,
+                            String::from("New personal best! "),
+                        ));
+                    }
+                    let best = match fastest_finish {
+                        Some(rounds) => format!("Best: {} treasure, finished in {} rounds", treasure, rounds),
+                        None => format!("Best: {} treasure", treasure),
+                    };
+                    texts.push(Text(Font::RegularUi, NORMAL_FONT_SIZE, COMMENT_COLOR, best));
+                    texts
+                }
+            },
+
+            LocalizableString::GameOver { name, run_stats } => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
                     Text(
@@ -317,12 +634,13 @@ impl LocalizableString {
                     ),
                     Text(
                         Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
-                        format!("\nBetter luck next time!\n"),
+                        String::from("\nBetter luck next time!\n"),
                     ),
+                    LocalizableString::run_stats_summary(run_stats, language),
                 ],
             },
 
-            LocalizableString::Victory => match language {
+            LocalizableString::Victory { run_stats } => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
                     Text(
@@ -330,9 +648,236 @@ impl LocalizableString {
                     ),
                     Text(
                         Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
-                        format!("\nYou have delved a deep as it gets, congratulations!\n\
-                                 Finish the run by selecting either button below.\n"),
+                        String::from("\nYou have delved a deep as it gets, congratulations!\n\
+                                      Finish the run by selecting either button below.\n"),
                     ),
+                    LocalizableString::run_stats_summary(run_stats, language),
+                ],
+            },
+
+            LocalizableString::RunAbandoned { run_stats } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(
+                        Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE, String::from("Run abandoned.\n"),
+                    ),
+                    Text(
+                        Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                        String::from("\nThe treasure counts, but the run isn't ranked by time.\n"),
+                    ),
+                    LocalizableString::run_stats_summary(run_stats, language),
+                ],
+            },
+
+            LocalizableString::PauseMenu => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE, String::from("Paused\n")),
+                    Text(
+                        Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                        String::from("\nAbandoning ends the run here. It can still be submitted to the \
+                                      leaderboards, but only for treasure: abandoned runs forfeit the \
+                                      time ranking."),
+                    ),
+                ],
+            },
+            LocalizableString::CopyDiagnosticsButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Copy diagnostics"))
+                ],
+            },
+            LocalizableString::Diagnostics(diagnostics) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR, diagnostics.clone())
+                ],
+            },
+            LocalizableString::ResumeButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Resume"))
+                ],
+            },
+            LocalizableString::SaveButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Save"))
+                ],
+            },
+            LocalizableString::SettingsButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Settings"))
+                ],
+            },
+            LocalizableString::AbandonRunButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Abandon run"))
+                ],
+            },
+
+            LocalizableString::SettingsMenu => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE, String::from("Settings\n")),
+                    Text(
+                        Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                        String::from("\nThese also have hotkeys while playing: F11 for the borderless \
+                                      window, F for the dark fade, T for threats and B for health bars."),
+                    ),
+                ],
+            },
+            LocalizableString::BorderlessWindowButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, if *enabled {
+                        String::from("Borderless window: on")
+                    } else {
+                        String::from("Borderless window: off")
+                    }),
+                ],
+            },
+            LocalizableString::DarkFadeButton(scale) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, match scale {
+                        Some(scale) if *scale < 2.0 => String::from("Dark fade: normal"),
+                        Some(_) => String::from("Dark fade: wide"),
+                        None => String::from("Dark fade: off"),
+                    }),
+                ],
+            },
+            LocalizableString::ThreatHighlightsButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, if *enabled {
+                        String::from("Threat highlights: on")
+                    } else {
+                        String::from("Threat highlights: off")
+                    }),
+                ],
+            },
+            LocalizableString::DamagedHealthBarsButton(only_damaged) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, if *only_damaged {
+                        String::from("Health bars: only when damaged")
+                    } else {
+                        String::from("Health bars: always")
+                    }),
+                ],
+            },
+            LocalizableString::AnimationSpeedButton(speed) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from(match speed {
+                        AnimationSpeed::Normal => "Animations: normal",
+                        AnimationSpeed::Fast => "Animations: fast",
+                        AnimationSpeed::ReduceMotion => "Animations: reduced motion",
+                    })),
+                ],
+            },
+            LocalizableString::BackButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Back"))
+                ],
+            },
+            LocalizableString::RunOptionsButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Run options"))
+                ],
+            },
+
+            LocalizableString::RunOptionsMenu => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE, String::from("Run options\n")),
+                    Text(
+                        Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                        format!("\nThese apply from the next run. Undo takes back up to {} turns with \
+                                 backspace, and manual pickup is the comma key. Runs with oxygen, undo, \
+                                 the companion or other dice aren't ranked.", MAX_UNDO_DEPTH),
+                    ),
+                ],
+            },
+            LocalizableString::OxygenModeButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from(if *enabled {
+                        "Oxygen: on"
+                    } else {
+                        "Oxygen: off"
+                    })),
+                ],
+            },
+            LocalizableString::DieButton(die) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, format!("Dice: d{}", die.sides())),
+                ],
+            },
+            LocalizableString::CarryCapButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, if *enabled {
+                        format!("Carry limit: {}", CARRY_CAP)
+                    } else {
+                        String::from("Carry limit: off")
+                    }),
+                ],
+            },
+            LocalizableString::CompanionButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from(if *enabled {
+                        "Repair drone: on"
+                    } else {
+                        "Repair drone: off"
+                    })),
+                ],
+            },
+            LocalizableString::PeacefulModeButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from(if *enabled {
+                        "Peaceful: on"
+                    } else {
+                        "Peaceful: off"
+                    })),
+                ],
+            },
+            LocalizableString::UndoButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from(if *enabled {
+                        "Undo: on"
+                    } else {
+                        "Undo: off"
+                    })),
+                ],
+            },
+            LocalizableString::ManualPickupButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from(if *enabled {
+                        "Pickup: manual"
+                    } else {
+                        "Pickup: automatic"
+                    })),
+                ],
+            },
+            LocalizableString::TreasureMagnetButton(enabled) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from(if *enabled {
+                        "Treasure magnet: on"
+                    } else {
+                        "Treasure magnet: off"
+                    })),
                 ],
             },
 
@@ -353,10 +898,19 @@ impl LocalizableString {
                 Language::English => vec![
                     Text(Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE, String::from(
                         "Enter a name or tag to represent you on the leaderboards. \
-                         Only ASCII characters (A-Z) and digits (0-9) are accepted, sorry about that.\n"
+                         Only ASCII characters (A-Z) and digits (0-9) are accepted, sorry about that. \
+                         After the tag, you can also type a longer display name.\n"
                     ))
                 ],
             },
+            LocalizableString::DisplayNameInput { display_name, active } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, COMMENT_COLOR, String::from("Display name: ")),
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                         format!("{}{}", display_name, if *active { "_" } else { "" })),
+                ],
+            },
             LocalizableString::RestartButton => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
@@ -394,31 +948,46 @@ impl LocalizableString {
                 ],
             },
 
-            LocalizableString::StatInfo(stat) => match language {
+            LocalizableString::StatInfo { stat, current, die } => match language {
                 Language::Debug => unreachable!(),
-                Language::English =>match stat {
-                    StatIncrease::Arm => vec![
-                        Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
-                             String::from("Arm\n")),
-                        Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE,
-                             String::from("\nReflects your ability to smash heads in. \
-                                           Each +1 is equivalent to rolling 1 better.\n"))
-                    ],
-                    StatIncrease::Leg => vec![
-                        Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
-                             String::from("Leg\n")),
-                        Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE,
-                             String::from("\nMakes you harder to hit. Each +1 is equivalent \
-                                           to enemies rolling 1 worse.\n"))
-                    ],
-                    StatIncrease::Finger => vec![
+                Language::English => {
+                    let (name, description) = match stat {
+                        StatIncrease::Arm => ("Arm", "Reflects your ability to smash heads in. \
+                                                      Each +1 is equivalent to rolling 1 better."),
+                        StatIncrease::Leg => ("Leg", "Makes you harder to hit. Each +1 is equivalent \
+                                                      to enemies rolling 1 worse."),
+                        StatIncrease::Finger => ("Finger", "Allows you to open locked doors. \
+                                                            Each +1 is equivalent to rolling 1 better when \
+                                                            opening locked doors."),
+                    };
+                    let percent = |min_roll: i32| (die.success_chance(min_roll) * 100.0).round();
+                    let (current, increased) = (*current, *current + 2);
+                    let effect = match stat {
+                        StatIncrease::Arm => format!(
+                            "Chance to hit a Rockman: {}% -> {}%",
+                            percent(stats::ROCKMAN.leg - current),
+                            percent(stats::ROCKMAN.leg - increased),
+                        ),
+                        StatIncrease::Leg => format!(
+                            "Chance for a Rockman to hit you: {}% -> {}%",
+                            percent(current - stats::ROCKMAN.arm),
+                            percent(increased - stats::ROCKMAN.arm),
+                        ),
+                        StatIncrease::Finger => format!(
+                            "Chance to open a {}-lock: {}% -> {}%",
+                            REFERENCE_LOCK,
+                            percent(REFERENCE_LOCK - current),
+                            percent(REFERENCE_LOCK - increased),
+                        ),
+                    };
+                    vec![
                         Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
-                             String::from("Finger\n")),
+                             format!("{}: {} -> {}\n", name, current, increased)),
                         Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE,
-                             String::from("\nAllows you to open locked doors. \
-                                           Each +1 is equivalent to rolling 1 better when \
-                                           opening locked doors.\n"))
-                    ],
+                             format!("\n{}\n", description)),
+                        Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR,
+                             format!("\n{}\n", effect)),
+                    ]
                 }
             },
 
@@ -433,6 +1002,29 @@ impl LocalizableString {
                 ],
             },
 
+            LocalizableString::IncreaseStatByOneButton(stat) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, match stat {
+                        StatIncrease::Arm => String::from("+1 to Arm"),
+                        StatIncrease::Leg => String::from("+1 to Leg"),
+                        StatIncrease::Finger => String::from("+1 to Finger"),
+                    })
+                ],
+            },
+
+            LocalizableString::StatPointsRemaining { first_pick } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, match first_pick {
+                        None => String::from("Points to spend: 2"),
+                        Some(StatIncrease::Arm) => String::from("Points to spend: 1 (+1 to Arm picked)"),
+                        Some(StatIncrease::Leg) => String::from("Points to spend: 1 (+1 to Leg picked)"),
+                        Some(StatIncrease::Finger) => String::from("Points to spend: 1 (+1 to Finger picked)"),
+                    })
+                ],
+            },
+
             LocalizableString::StatIncreaseByTraining { stat, name } => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
@@ -460,6 +1052,13 @@ impl LocalizableString {
                 ],
             },
 
+            LocalizableString::PeacefulLeaderboardsHeader => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::BoldUi, 24.0, Color::WHITE, String::from("Leaderboards (peaceful runs)"))
+                ],
+            },
+
             LocalizableString::LeaderboardsEmpty => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
@@ -467,6 +1066,19 @@ impl LocalizableString {
                 ],
             },
 
+            LocalizableString::LeaderboardsOffline { seconds_ago } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, interface::HEALTH_MEDIUM, String::from("Offline, ")),
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, COMMENT_COLOR, match seconds_ago {
+                        0..=119 => String::from("last seen just now."),
+                        120..=7199 => format!("last seen {} minutes ago.", seconds_ago / 60),
+                        7200..=172_799 => format!("last seen {} hours ago.", seconds_ago / 60 / 60),
+                        _ => format!("last seen {} days ago.", seconds_ago / 60 / 60 / 24),
+                    }),
+                ],
+            },
+
             LocalizableString::LeaderboardsTitleName => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
@@ -486,12 +1098,13 @@ impl LocalizableString {
                 ],
             },
 
-            LocalizableString::LeaderboardsName(chars) => match language {
-                _ => vec![Text(Font::RegularUi, 18.0, Color::WHITE, format!("{}{}{}", chars[0], chars[1], chars[2]))],
-            },
-            LocalizableString::LeaderboardsTreasure(amount) => match language {
-                _ => vec![Text(Font::RegularUi, 18.0, Color::WHITE, format!("{}", amount))],
-            },
+            LocalizableString::LeaderboardsName(chars, display_name) => vec![
+                Text(Font::RegularUi, 18.0, Color::WHITE, format!("{}{}{} ", chars[0], chars[1], chars[2])),
+                Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR, display_name.clone()),
+            ],
+            LocalizableString::LeaderboardsTreasure(amount) => {
+                vec![Text(Font::RegularUi, 18.0, Color::WHITE, format!("{}", amount))]
+            }
             LocalizableString::LeaderboardsRounds(rounds) => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
@@ -505,10 +1118,298 @@ impl LocalizableString {
                 ],
             },
 
-            LocalizableString::LeaderboardsSortByButton => match language {
+            LocalizableString::LeaderboardsSortByButton(ascending) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE, match ascending {
+                        Some(true) => String::from("Sort by ^"),
+                        Some(false) => String::from("Sort by v"),
+                        None => String::from("Sort by"),
+                    })
+                ],
+            },
+
+            LocalizableString::TreasureMagnetActivated => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::RGB(0x44, 0xDD, 0x44),
+                         String::from("The mineral magnet in your suit hums to life. \
+                                       Treasure next to you is now picked up as you walk by.")),
+                ],
+            },
+
+            LocalizableString::OxygenRanOut => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, interface::HEALTH_LOW,
+                         String::from("Your oxygen tank is empty! Find the way down before you suffocate.")),
+                ],
+            },
+
+            LocalizableString::Suffocating => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, interface::HEALTH_LOW,
+                         String::from("You're suffocating, and lose 1 health.")),
+                ],
+            },
+
+            LocalizableString::RunOptionsChanged => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                         String::from("The run options change from the next run.")),
+                ],
+            },
+
+            LocalizableString::Hint(hint) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::BoldUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Tip: ")),
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from(match hint {
+                        Hint::Combat => "Attacks land when a d6 roll plus your Arm equals or exceeds the \
+                                         target's Leg. Walk into an enemy to attack it.",
+                        Hint::LockedDoor => "Locked doors open when your Finger plus a d6 roll reaches \
+                                             the lock's difficulty. Walk into the door to try.",
+                        Hint::Training => "Stats train with use: attacking trains Arm, walking Leg, \
+                                           and lockpicking Finger. See the bars when you click yourself.",
+                        Hint::Exit => "The exit leads deeper into the mine. Treasure left behind \
+                                       stays behind.",
+                    })),
+                ],
+            },
+            LocalizableString::RecoveryPrompt => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE,
+                         String::from("The game didn't close properly last time.\n")),
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, COMMENT_COLOR,
+                         String::from("Continue from the latest autosave?")),
+                ],
+            },
+            LocalizableString::RecoverSessionButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Recover last session"))
+                ],
+            },
+            LocalizableString::StartFreshButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Start fresh"))
+                ],
+            },
+            LocalizableString::QuitPrompt => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE,
+                         String::from("Quit without saving?\n")),
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, COMMENT_COLOR,
+                         String::from("The run has progressed since it was last saved.")),
+                ],
+            },
+            LocalizableString::SaveAndQuitButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Save & quit"))
+                ],
+            },
+            LocalizableString::QuitAnywayButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Quit anyway"))
+                ],
+            },
+            LocalizableString::CancelButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Cancel"))
+                ],
+            },
+
+            LocalizableString::BestiaryHeader { encountered, total } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::BoldUi, 24.0, Color::WHITE, String::from("Bestiary")),
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, COMMENT_COLOR,
+                         format!("   {}/{} encountered", encountered, total)),
+                ],
+            },
+            LocalizableString::BestiaryEntry { name, stats } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::BoldUi, NORMAL_FONT_SIZE, Color::WHITE,
+                         format!("{}\n", name.translated_to(language))),
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE,
+                         format!("Health {}, Arm {}, Leg {}{}\n",
+                                 stats.max_health, stats.arm, stats.leg, if stats.flying { ", flying" } else { "" })),
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR, String::from(match name {
+                        Name::Slime => "A sluggish blob. It only moves when attacked, and then towards the attacker.",
+                        Name::Roach => "An insect grown huge in the low gravity. Wanders around, backs off when hit.",
+                        Name::Rockman => "Hunts down anyone it sees, until it's hurt enough to retreat to a corner.",
+                        Name::SentientMetal => "Flying bits of metal that keep their distance, hitting in a + shape.",
+                        Name::MetalOverseer => "The largest of the metal beings, guarding the final treasure.",
+                        _ => "",
+                    })),
+                ],
+            },
+            LocalizableString::BestiaryUnknown => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::BoldUi, NORMAL_FONT_SIZE, COMMENT_COLOR, String::from("???\n")),
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR,
+                         String::from("Not encountered yet.")),
+                ],
+            },
+
+            LocalizableString::AutosavesHeader(count) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE, String::from("Autosaves ")),
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR, if *count == 0 {
+                        String::from("(none yet, press F8 to close)")
+                    } else {
+                        String::from("(press F8 to close)")
+                    }),
+                ],
+            },
+            LocalizableString::LoadAutosaveButton { level, round, treasure, seconds_ago } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                         format!("Level {}, round {}, {} treasure ", level + 1, round, treasure)),
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR, if *seconds_ago < 120 {
+                        String::from("(just now)")
+                    } else {
+                        format!("({} min ago)", seconds_ago / 60)
+                    }),
+                ],
+            },
+            LocalizableString::DismissHintButton => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, String::from("Got it"))
+                ],
+            },
+
+            LocalizableString::TileDescription { terrain, treasure, fighter, finger, die } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => {
+                    let mut texts = match terrain {
+                        Some(terrain) => LocalizableString::TerrainDescription { terrain: *terrain, finger: *finger, die: *die }
+                            .localize(language),
+                        None => vec![Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE,
+                                          String::from("You can't see there.\n"))],
+                    };
+                    if let Some(name) = fighter {
+                        texts.push(Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE,
+                                        format!("{} is here.\n", name.translated_to(language))));
+                    }
+                    if let Some(amount) = treasure {
+                        texts.push(Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE,
+                                        format!("{} treasure on the ground.\n", amount)));
+                    }
+                    texts
+                }
+            },
+
+            LocalizableString::TerrainDescription { terrain, finger, die } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => {
+                    let (name, description) = match terrain {
+                        Terrain::Empty => ("Solid rock", String::from("Not even worth mining.")),
+                        Terrain::Floor => ("Floor", String::from("Safe to walk on.")),
+                        Terrain::Wall => ("Wall", String::from("Blocks movement and sight.")),
+                        Terrain::Door => ("Door", String::from("Opens when walked into.")),
+                        Terrain::LockedDoor { roll_threshold } => {
+                            let min_roll = roll_threshold - finger;
+                            let odds = match die.expected_tries(min_roll) {
+                                Some(tries) => format!(
+                                    " Needs a roll of {} or more: {:.0}% per try, {:.1} tries on average.",
+                                    min_roll.max(1),
+                                    die.success_chance(min_roll) * 100.0,
+                                    tries,
+                                ),
+                                None => String::new(),
+                            };
+                            ("Locked door", format!("{}{}", unlock_requirement(*roll_threshold, *finger, *die), odds))
+                        }
+                        Terrain::DoorOpen => ("Open door", String::from("Doesn't block anything anymore.")),
+                        Terrain::Exit => ("Exit", String::from("Leads deeper into the mine. \
+                                                               There's no coming back up.")),
+                        Terrain::FinalTreasure => ("The motherlode", String::from("What you came here for. \
+                                                                                    Grab it to win.")),
+                    };
+                    vec![
+                        Text(Font::RegularUi, NORMAL_FONT_SIZE, Color::WHITE, format!("{}\n", name)),
+                        Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR, format!("{}\n", description)),
+                    ]
+                }
+            },
+
+            LocalizableString::CombatOdds { hit_chance, expected_damage, hit_back_chance } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE,
+                         format!("You hit {:.0}%, ~{:.1} dmg. ", hit_chance * 100.0, expected_damage)),
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR,
+                         format!("It hits {:.0}%.", hit_back_chance * 100.0)),
+                ],
+            },
+
+            LocalizableString::SeededRun(seed) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR,
+                         format!("Seeded run ({}), not eligible for the leaderboards.", seed)),
+                ],
+            },
+
+            LocalizableString::InWorldClock { round } => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE, format!("Round {}\n", round)),
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR, in_world_timestamp(*round)),
+                ],
+            },
+
+            LocalizableString::ToastLevelCleared => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE,
+                    String::from("Level cleared!"),
+                )],
+            },
+
+            LocalizableString::ToastLockedRoomOpened => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE,
+                    String::from("Locked room opened!"),
+                )],
+            },
+
+            LocalizableString::ToastNewEnemy(name) => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, BIGGER_FONT_SIZE, Color::WHITE,
+                    format!("New enemy: {}", name.translated_to(language)),
+                )],
+            },
+
+            LocalizableString::ToastCriticalHealth => match language {
+                Language::Debug => unreachable!(),
+                Language::English => vec![Text(
+                    Font::RegularUi, BIGGER_FONT_SIZE, interface::HEALTH_LOW,
+                    String::from("Critical health!"),
+                )],
+            },
+
+            LocalizableString::TreasureRemaining(amount) => match language {
                 Language::Debug => unreachable!(),
                 Language::English => vec![
-                    Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE, String::from("Sort by"))
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, COMMENT_COLOR, String::from("Left\n")),
+                    Text(Font::RegularUi, SMALLER_FONT_SIZE, Color::WHITE, format!("{}", amount)),
                 ],
             },
         }
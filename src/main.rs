@@ -70,10 +70,12 @@
 #![windows_subsystem = "windows"]
 
 use fontdue::layout::LayoutSettings;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::mouse::{Cursor, MouseButton, MouseWheelDirection, SystemCursor};
-use sdl2::rect::Rect;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::BlendMode;
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
 mod text_painter;
@@ -81,15 +83,17 @@ pub use text_painter::{Font, Text, TextPainter};
 mod tile_painter;
 pub use tile_painter::{TileGraphic, TileLayer, TilePainter, TILE_STRIDE};
 mod level;
-pub use level::{FighterSpawn, Level, Terrain};
+pub use level::{FighterSpawn, Level, LevelDrawSettings, Terrain};
 mod dungeon;
-pub use dungeon::{Dungeon, DungeonEvent};
+pub use dungeon::{Dungeon, DungeonEvent, DungeonSave, RunOptions, RunOutcome, SimResult, CARRY_CAP, MAX_UNDO_DEPTH};
 mod fighter;
-pub use fighter::Fighter;
+pub use fighter::{Fighter, Team};
+mod animation_speed;
+pub use animation_speed::AnimationSpeed;
 mod camera;
 pub use camera::Camera;
 pub mod stats;
-pub use stats::{StatIncrease, Stats};
+pub use stats::{AttackOutcome, Die, LootTable, RunStats, StatIncrease, Stats, StatusEffect, StatusKind};
 mod game_log;
 pub use game_log::GameLog;
 mod localization;
@@ -100,14 +104,100 @@ pub mod interface;
 pub use interface::UserInterface;
 mod leaderboard;
 pub use leaderboard::{Leaderboard, LeaderboardEntry};
+mod auto_explore;
+mod autosave;
+mod bestiary;
+mod hints;
+pub use auto_explore::AutoExplore;
+pub use autosave::{AutosaveInfo, Autosaves};
+pub use bestiary::Bestiary;
+mod bench;
 mod leaderboard_server;
+mod personal_best;
+mod toasts;
+mod window_settings;
+pub use hints::{Hint, Hints};
+pub use personal_best::PersonalBest;
+pub use toasts::Toasts;
+pub use window_settings::WindowSettings;
 
 static QUICK_SAVE_FILE: &str = "excavation-site-mercury-quicksave.bin";
+/// Exists while the game is running, so if it's there at launch, the
+/// previous session didn't exit cleanly.
+static RUNNING_SENTINEL_FILE: &str = "excavation-site-mercury-running";
+/// Tilesets and fonts in this directory replace the embedded ones.
+pub static ASSETS_DIRECTORY: &str = "assets";
+/// The longest frame the game will simulate, in seconds. Longer frames
+/// are slowed down to this.
+const MAX_DELTA_SECONDS: f32 = 0.1;
+/// How much of each frame's duration is mixed into the smoothed delta.
+const DELTA_SMOOTHING: f32 = 0.25;
+/// How many of the latest frames are shown in the debug frame time graph.
+const FRAME_GRAPH_LENGTH: usize = 120;
+/// The frame time that fills the debug frame time graph, in milliseconds.
+const FRAME_GRAPH_MAX_MS: f32 = 50.0;
+/// How much treasure one press of the drop key leaves behind.
+const TREASURE_DROP_AMOUNT: i32 = 10;
+
+/// Returns the forced seed if there is one, otherwise a fresh seed
+/// from the randomly keyed hasher the standard library provides.
+fn new_seed(forced_seed: Option<u64>) -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    if let Some(seed) = forced_seed {
+        return seed;
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(since_epoch) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        hasher.write_u128(since_epoch.as_nanos());
+    }
+    hasher.finish()
+}
+
+/// Saves the dungeon to [QUICK_SAVE_FILE], for F5 and the pause menu.
+/// Returns false if saving failed.
+fn quicksave(dungeon: &Dungeon) -> bool {
+    log::info!("Quicksaving game to {}...", QUICK_SAVE_FILE);
+    match dungeon
+        .to_bytes()
+        .ok()
+        .and_then(|bytes| std::fs::write(QUICK_SAVE_FILE, bytes).ok())
+    {
+        Some(_) => {
+            log::info!("Game quicksaved to {}!", QUICK_SAVE_FILE);
+            true
+        }
+        None => {
+            log::error!("Failed quicksaving to {}.", QUICK_SAVE_FILE);
+            false
+        }
+    }
+}
+
+/// Whether quitting now would lose progress: the run is still going,
+/// something has happened in it, and it hasn't been quicksaved since.
+/// `quicksaved` is the seed and round of the latest quicksave.
+fn has_unsaved_progress(dungeon: &Dungeon, quicksaved: Option<(u64, u64)>) -> bool {
+    let run_over = dungeon.is_game_over() || dungeon.is_abandoned() || dungeon.final_treasure_found();
+    !run_over && dungeon.round() > 0 && quicksaved != Some((dungeon.seed(), dungeon.round()))
+}
 
 #[derive(PartialEq)]
 enum Screen {
     InGame,
+    /// The pause menu, drawn over the game.
+    Paused,
     Leaderboard,
+    Bestiary,
+}
+
+/// The buttons shown in the pause menu.
+#[derive(PartialEq)]
+enum PauseMenu {
+    Main,
+    Settings,
+    /// The [RunOptions] for the next run, opened from the settings.
+    RunOptions,
 }
 
 // TODO: Catch panics and show a message box before crashing?
@@ -120,33 +210,87 @@ pub fn main() {
         return;
     }
 
+    if std::env::args().find(|s| s == "--bench").is_some() {
+        bench::run();
+        return;
+    }
+
+    // A forced seed makes every run in this session replay the same
+    // dungeon, which is handy for reproducing bugs.
+    let forced_seed = std::env::args()
+        .skip_while(|s| s != "--seed")
+        .nth(1)
+        .map(|s| s.parse::<u64>().expect("--seed expects an unsigned 64-bit integer"));
+
+    // The debug overlay, and the shortcuts that only work with it on,
+    // are for development. Debug builds always have them, release
+    // builds only with --dev.
+    let dev_tools = cfg!(debug_assertions) || std::env::args().any(|s| s == "--dev");
+
     let initialization_start = Instant::now();
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
+    let mut window = video_subsystem
         .window("Excavation Site Mercury", 800, 600)
         .position_centered()
         .resizable()
         .allow_highdpi()
         .build()
         .unwrap();
+    let _ = window.set_minimum_size(interface::MIN_WINDOW_WIDTH, interface::MIN_WINDOW_HEIGHT);
+    let mut window_settings = WindowSettings::load();
+    window_settings.apply(&video_subsystem, &mut window);
 
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     let texture_creator = canvas.texture_creator();
     let mut text_painter = TextPainter::new(&texture_creator).unwrap();
     let mut tile_painter = TilePainter::new(&texture_creator).unwrap();
 
-    let mut dungeon = Dungeon::new((Instant::now() - initialization_start).subsec_nanos() as u64);
+    let mut run_options = RunOptions::default();
+    let mut dungeon = Dungeon::with_options(new_seed(forced_seed), run_options);
     let mut camera = Camera::new();
-    let mut camera_position = dungeon
-        .level()
-        .room_center_in_pixel_space(dungeon.player().position())
-        .unwrap();
 
     let mut show_debug = false;
-    let mut selected_fighter: Option<usize> = None;
+    let mut show_debug_grid = true;
+    let mut only_damaged_health_bars = false;
+    let mut show_threats = true;
+    // The tile being examined, while in examine mode
+    let mut examine_cursor: Option<Point> = None;
+    // The first +1 picked on the stat increase screen, if splitting the points
+    let mut first_stat_pick: Option<StatIncrease> = None;
+    // Multiplier for the dark fade's radius, None to disable the fade
+    let mut fade_radius_scale = Some(1.0);
+    let mut animation_speed = AnimationSpeed::Normal;
+    let mut selected_fighter: Option<usize> = Some(dungeon.player_id());
     let mut ui = UserInterface::new();
     let mut leaderboard = Leaderboard::new();
+    let mut hints = Hints::load();
+    let mut bestiary = Bestiary::load();
+    let mut autosaves = Autosaves::new();
+    let mut auto_explore = AutoExplore::new();
+    let mut toasts = Toasts::new();
+    let mut personal_best = PersonalBest::load();
+    // The seed of the last run compared to the personal best, and whether it beat it
+    let mut recorded_run: Option<(u64, bool)> = None;
+    // The seed and round of the latest quicksave made or loaded
+    let mut quicksaved: Option<(u64, u64)> = None;
+    // Set when the window is closed with unsaved progress, to ask first
+    let mut quit_requested = false;
+    let mut pause_menu = PauseMenu::Main;
+    // The autosaves listed for loading, while the list is open
+    let mut autosave_list: Option<Vec<AutosaveInfo>> = None;
+
+    let previous_session_crashed = std::path::Path::new(RUNNING_SENTINEL_FILE).exists();
+    if let Err(err) = std::fs::write(RUNNING_SENTINEL_FILE, b"") {
+        log::error!("Failed to create {}: {}", RUNNING_SENTINEL_FILE, err);
+    }
+    // The newest autosave, offered for loading if the game crashed last time
+    let mut recovery_slot = if previous_session_crashed {
+        log::info!("The previous session didn't exit cleanly.");
+        Autosaves::newest_slot()
+    } else {
+        None
+    };
 
     let mut screen = Screen::InGame;
 
@@ -157,17 +301,49 @@ pub fn main() {
     log::info!("Game startup took {:?}.", Instant::now() - initialization_start);
 
     let mut frame_times = Vec::new();
+    // Durations of the latest frames, for the debug frame time graph
+    let mut frame_durations: VecDeque<Duration> = VecDeque::with_capacity(FRAME_GRAPH_LENGTH);
+    let mut delta_seconds = 0.01667;
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut window_focused = true;
     'running: loop {
+        if !window_focused {
+            // Nothing needs to move while the player is away, so just
+            // sleep until the window gets focus again.
+            match event_pump.wait_event_timeout(500) {
+                Some(Event::Quit { .. }) if has_unsaved_progress(&dungeon, quicksaved) => {
+                    quit_requested = true;
+                    window_focused = true;
+                }
+                Some(Event::Quit { .. }) => break 'running,
+                Some(Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                }) => {
+                    window_focused = true;
+                    frame_times.clear();
+                }
+                _ => continue 'running,
+            }
+        }
+
         let mut fts = frame_times.iter();
-        let delta_seconds = if let (Some(latest), Some(previous)) = (fts.nth_back(0), fts.nth_back(0)) {
+        if let (Some(latest), Some(previous)) = (fts.nth_back(0), fts.nth_back(0)) {
             let frame_duration: Duration = *latest - *previous;
-            frame_duration.as_secs_f32()
-        } else {
-            0.01667
-        };
+            // Hitches (window drags, saving, etc.) shouldn't make the
+            // camera and animations jump, so clamp and smooth the delta.
+            let frame_seconds = frame_duration.as_secs_f32().min(MAX_DELTA_SECONDS);
+            delta_seconds += (frame_seconds - delta_seconds) * DELTA_SMOOTHING;
+        }
 
         let (width, height) = canvas.output_size().unwrap();
+        // On HiDPI screens, mouse events are in window coordinates,
+        // which can be smaller than the pixels the canvas draws in.
+        let (window_width, window_height) = canvas.window().size();
+        let mouse_scale_x = width as f32 / window_width.max(1) as f32;
+        let mouse_scale_y = height as f32 / window_height.max(1) as f32;
+        let window_to_pixels =
+            |x: i32, y: i32| Point::new((x as f32 * mouse_scale_x) as i32, (y as f32 * mouse_scale_y) as i32);
         let on_screen_fighters = dungeon.get_selectable_fighter_ids();
         if let Some(currently_selected) = selected_fighter {
             if !on_screen_fighters.contains(&currently_selected) {
@@ -179,13 +355,22 @@ pub fn main() {
 
         for event in event_pump.poll_iter() {
             match event {
+                Event::Quit { .. } if has_unsaved_progress(&dungeon, quicksaved) => quit_requested = true,
                 Event::Quit { .. } => break 'running,
 
-                Event::MouseButtonDown { mouse_btn, .. } => match mouse_btn {
-                    MouseButton::Left => ui.mouse_left_pressed = true,
-                    MouseButton::Right => ui.mouse_right_pressed = true,
-                    _ => {}
-                },
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } => window_focused = false,
+
+                Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                    ui.mouse_position = window_to_pixels(x, y);
+                    match mouse_btn {
+                        MouseButton::Left => ui.mouse_left_pressed = true,
+                        MouseButton::Right => ui.mouse_right_pressed = true,
+                        _ => {}
+                    }
+                }
 
                 Event::MouseButtonUp { mouse_btn, .. } => match mouse_btn {
                     MouseButton::Left => {
@@ -200,8 +385,7 @@ pub fn main() {
                 },
 
                 Event::MouseMotion { x, y, .. } => {
-                    ui.mouse_position.x = x;
-                    ui.mouse_position.y = y;
+                    ui.mouse_position = window_to_pixels(x, y);
                 }
 
                 Event::MouseWheel { y, direction, .. } => {
@@ -216,19 +400,19 @@ pub fn main() {
                     ui.text_input = Some(text);
                 }
 
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if quit_requested => quit_requested = false,
+
+                // The quit dialog is only controlled with its hotkeys, which are key releases
+                Event::KeyDown { .. } if quit_requested => {}
+
                 Event::KeyDown {
                     keycode: Some(Keycode::F5),
                     ..
-                } if screen == Screen::InGame => {
-                    log::info!("Quicksaving game to {}...", QUICK_SAVE_FILE);
-                    match dungeon
-                        .to_bytes()
-                        .ok()
-                        .and_then(|bytes| std::fs::write(QUICK_SAVE_FILE, bytes).ok())
-                    {
-                        Some(_) => log::info!("Game quicksaved to {}!", QUICK_SAVE_FILE),
-                        None => log::error!("Failed quicksaving to {}.", QUICK_SAVE_FILE),
-                    }
+                } if screen == Screen::InGame && quicksave(&dungeon) => {
+                    quicksaved = Some((dungeon.seed(), dungeon.round()));
                 }
 
                 Event::KeyDown {
@@ -242,6 +426,10 @@ pub fn main() {
                     {
                         Some(loaded_dungeon) => {
                             dungeon = loaded_dungeon;
+                            camera.reset();
+                            first_stat_pick = None;
+                            toasts.reset(&dungeon);
+                            quicksaved = Some((dungeon.seed(), dungeon.round()));
                             log::info!("Quicksave loaded from {}!", QUICK_SAVE_FILE);
                         }
                         None => {
@@ -250,20 +438,186 @@ pub fn main() {
                     }
                 }
 
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if screen == Screen::InGame && dungeon.undo() => {
+                    log::info!("Undid the last turn, {} more can be undone.", dungeon.undoable_events());
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } if screen == Screen::InGame => {
+                    autosave_list = match autosave_list {
+                        Some(_) => None,
+                        None => Some(Autosaves::list()),
+                    };
+                }
+
                 Event::KeyDown {
                     keycode: Some(Keycode::F3),
                     ..
-                } => show_debug = !show_debug,
+                } if dev_tools => show_debug = !show_debug,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    ..
+                } => {
+                    window_settings.next_display(&video_subsystem);
+                    window_settings.apply(&video_subsystem, canvas.window_mut());
+                    window_settings.save();
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => {
+                    window_settings.borderless = !window_settings.borderless;
+                    window_settings.apply(&video_subsystem, canvas.window_mut());
+                    window_settings.save();
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::G),
+                    ..
+                } if show_debug => show_debug_grid = !show_debug_grid,
 
                 Event::KeyDown {
                     keycode: Some(Keycode::R),
                     ..
-                } if screen == Screen::InGame => {
-                    if show_debug {
-                        dungeon = Dungeon::new((delta_seconds * 1_000_000_000.0) as u64)
+                } if screen == Screen::InGame && show_debug => {
+                    dungeon = Dungeon::with_options(new_seed(forced_seed), run_options);
+                    camera.reset();
+                    first_stat_pick = None;
+                    toasts.reset(&dungeon);
+                    selected_fighter = Some(dungeon.player_id());
+                }
+
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } if screen == Screen::InGame && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    // Ctrl + direction attacks without moving, YUBN for the diagonals
+                    let direction = match keycode {
+                        Keycode::W | Keycode::K | Keycode::Up => Some((0, -1)),
+                        Keycode::S | Keycode::J | Keycode::Down => Some((0, 1)),
+                        Keycode::A | Keycode::H | Keycode::Left => Some((-1, 0)),
+                        Keycode::D | Keycode::L | Keycode::Right => Some((1, 0)),
+                        Keycode::Y => Some((-1, -1)),
+                        Keycode::U => Some((1, -1)),
+                        Keycode::B => Some((-1, 1)),
+                        Keycode::N => Some((1, 1)),
+                        _ => None,
+                    };
+                    if let Some((dx, dy)) = direction {
+                        if dungeon.can_run_events() {
+                            dungeon.run_event(DungeonEvent::Attack(dx, dy));
+                        }
                     }
                 }
 
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    ..
+                } if screen == Screen::InGame => only_damaged_health_bars = !only_damaged_health_bars,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::T),
+                    ..
+                } if screen == Screen::InGame => show_threats = !show_threats,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } if screen == Screen::InGame => {
+                    fade_radius_scale = match fade_radius_scale {
+                        Some(scale) if scale < 2.0 => Some(2.0),
+                        Some(_) => None,
+                        None => Some(1.0),
+                    };
+                    log::info!("Dark fade radius multiplier set to {:?}.", fade_radius_scale);
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    ..
+                } if screen == Screen::InGame && dungeon.carry_load().is_some() && dungeon.can_run_events() => {
+                    dungeon.run_event(DungeonEvent::DropTreasure(TREASURE_DROP_AMOUNT));
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::Comma),
+                    ..
+                } if screen == Screen::InGame && dungeon.options().manual_pickup && dungeon.can_run_events() => {
+                    dungeon.run_event(DungeonEvent::PickUp);
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    ..
+                } if screen == Screen::InGame => selected_fighter = Some(dungeon.player_id()),
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::X),
+                    ..
+                } if screen == Screen::InGame => {
+                    examine_cursor = match examine_cursor {
+                        Some(_) => None,
+                        None => Some(dungeon.player().position()),
+                    };
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if examine_cursor.is_some() => examine_cursor = None,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if autosave_list.is_some() => autosave_list = None,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if screen == Screen::InGame && dungeon.can_run_events() && recovery_slot.is_none() => {
+                    auto_explore.active = false;
+                    pause_menu = PauseMenu::Main;
+                    screen = Screen::Paused;
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if screen == Screen::Paused => match pause_menu {
+                    PauseMenu::Main => screen = Screen::InGame,
+                    PauseMenu::Settings => pause_menu = PauseMenu::Main,
+                    PauseMenu::RunOptions => pause_menu = PauseMenu::Settings,
+                },
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    ..
+                } if screen == Screen::InGame => screen = Screen::Bestiary,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if screen == Screen::Bestiary => screen = Screen::InGame,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } if screen == Screen::InGame && examine_cursor.is_none() => {
+                    auto_explore.active = !auto_explore.active;
+                }
+
                 Event::KeyDown {
                     keycode: Some(Keycode::Tab),
                     ..
@@ -285,6 +639,8 @@ pub fn main() {
                 Event::KeyDown {
                     keycode: Some(keycode), ..
                 } if screen == Screen::InGame => {
+                    // Any other key interrupts exploring
+                    auto_explore.active = false;
                     let event = match keycode {
                         Keycode::W | Keycode::K | Keycode::Up => Some(DungeonEvent::MoveUp),
                         Keycode::S | Keycode::J | Keycode::Down => Some(DungeonEvent::MoveDown),
@@ -292,7 +648,19 @@ pub fn main() {
                         Keycode::D | Keycode::L | Keycode::Right => Some(DungeonEvent::MoveRight),
                         _ => None,
                     };
-                    if let Some(event) = event {
+                    if let (Some(event), Some(cursor)) = (&event, &mut examine_cursor) {
+                        // In examine mode, the directions move the cursor instead
+                        match event {
+                            DungeonEvent::MoveUp => cursor.y -= 1,
+                            DungeonEvent::MoveDown => cursor.y += 1,
+                            DungeonEvent::MoveLeft => cursor.x -= 1,
+                            DungeonEvent::MoveRight => cursor.x += 1,
+                            _ => {}
+                        }
+                        if let Some(fighter) = dungeon.fighter_at(cursor.x, cursor.y) {
+                            selected_fighter = Some(fighter.id);
+                        }
+                    } else if let Some(event) = event {
                         if dungeon.can_run_events() {
                             dungeon.run_event(event);
 
@@ -323,6 +691,9 @@ pub fn main() {
             }
         }
 
+        // While asking about quitting, only the quit dialog gets the input
+        let dialog_ui = if quit_requested { Some(ui.take_input()) } else { None };
+
         canvas.set_draw_color(interface::WINDOW_BACKGROUND);
         canvas.clear();
 
@@ -331,100 +702,192 @@ pub fn main() {
                 leaderboard.run(delta_seconds, &mut canvas, &mut text_painter, &mut ui);
                 if leaderboard.should_restart {
                     screen = Screen::InGame;
-                    dungeon = Dungeon::new((delta_seconds * 1_000_000_000.0) as u64);
+                    dungeon = Dungeon::with_options(new_seed(forced_seed), run_options);
+                    camera.reset();
+                    first_stat_pick = None;
+                    toasts.reset(&dungeon);
+                    selected_fighter = Some(dungeon.player_id());
                     leaderboard.should_restart = false;
                 } else if leaderboard.should_quit {
                     break 'running;
                 }
             }
 
-            Screen::InGame => {
+            Screen::Bestiary => {
+                bestiary.run(&mut canvas, &mut text_painter, &mut tile_painter, &mut ui);
+                if bestiary.should_close {
+                    screen = Screen::InGame;
+                    bestiary.should_close = false;
+                }
+            }
+
+            Screen::InGame | Screen::Paused => {
                 // TODO: All this in-game-specific stuff should be in its own module
                 // Just lacking the time right now.
 
                 dungeon.try_load_next_level(false);
+                auto_explore.update(&mut dungeon);
+                bestiary.update(&dungeon, &on_screen_fighters);
+
+                let fade_radius = fade_radius_scale.map(|scale| {
+                    scale
+                        * if dungeon.level_nth() >= 3 {
+                            level::MAGMA_FADE_RADIUS
+                        } else {
+                            level::FADE_RADIUS
+                        }
+                });
 
                 if ui.mouse_right_released {
                     log::info!("TODO: Player should pathfind to mouse now");
                 }
 
-                if ui.mouse_left_released {
+                if ui.mouse_left_released && screen == Screen::InGame {
                     selected_fighter = dungeon
                         .fighters()
                         .iter()
+                        .filter(|fighter| on_screen_fighters.contains(&fighter.id))
                         .filter(|fighter| fighter.mouse_over(&camera, ui.mouse_position))
                         .map(|fighter| fighter.id)
                         .next();
                 }
 
                 // Animate
-                dungeon.level().animate(delta_seconds);
+                dungeon.level().animate(delta_seconds * animation_speed.multiplier());
                 for fighter in dungeon.fighters() {
-                    fighter.animate(delta_seconds, dungeon.level());
+                    fighter.animate(delta_seconds, dungeon.level(), animation_speed);
                 }
 
                 // Update camera
-                if let Some(new_position) = dungeon.level().room_center_in_pixel_space(dungeon.player().position()) {
-                    camera_position = new_position;
-                }
-                let camera_target_x = camera_position.x - width as i32 / 2;
-                let camera_target_y = camera_position.y - (height as i32 - 150) / 2;
-                if dungeon.level_changed() {
-                    camera.x = camera_target_x;
-                    camera.y = camera_target_y;
-                } else {
-                    camera.update(delta_seconds, camera_target_x, camera_target_y);
-                }
+                let player_position = dungeon.player().position();
+                let focus = Point::new(
+                    player_position.x * TILE_STRIDE + TILE_STRIDE / 2,
+                    player_position.y * TILE_STRIDE + TILE_STRIDE / 2,
+                );
+                let viewport = Rect::new(0, 0, width, height.saturating_sub(interface::HUD_BOTTOM_HEIGHT).max(1));
+                camera.enter_level(dungeon.level_nth(), focus, viewport);
+                camera.frame(focus, viewport);
+                camera.update(delta_seconds, animation_speed);
 
                 // Draw the world
+                let level_draw_settings = LevelDrawSettings {
+                    show_debug,
+                    fade_radius,
+                    magma_level: dungeon.level_nth() >= 3,
+                    finger: dungeon.player().stats.finger,
+                    die: dungeon.options().die,
+                };
                 dungeon.level().draw(
                     &mut canvas,
                     &mut tile_painter,
                     &camera,
                     TileLayer::BelowFighters,
-                    show_debug,
                     false,
-                    dungeon.level_nth() >= 3,
+                    level_draw_settings,
                 );
                 dungeon.level().draw_treasure(&mut canvas, &mut tile_painter, &camera);
                 if dungeon.is_first_level() {
                     dungeon.level().draw_shadows(&mut canvas, &mut tile_painter, &camera);
                 }
+                if let Some(fighter) = selected_fighter.and_then(|id| dungeon.get_fighter(id)) {
+                    fighter.draw_selection_highlight(&mut canvas, &mut tile_painter, &camera);
+                }
                 for fighter in dungeon.fighters() {
-                    let selected = Some(fighter.id) == selected_fighter;
-                    fighter.draw(&mut canvas, &mut tile_painter, &camera, true, show_debug, selected);
+                    fighter.draw(&mut canvas, &mut tile_painter, &camera, true, show_debug);
+                }
+                if show_threats && !dungeon.is_game_over() {
+                    for id in dungeon.threatening_fighter_ids() {
+                        if let Some(fighter) = dungeon.get_fighter(id) {
+                            fighter.draw_threat_highlight(&mut canvas, &mut tile_painter, &camera);
+                        }
+                    }
                 }
                 for fighter in dungeon.fighters() {
-                    let selected = Some(fighter.id) == selected_fighter;
-                    fighter.draw(&mut canvas, &mut tile_painter, &camera, false, show_debug, selected);
+                    fighter.draw(&mut canvas, &mut tile_painter, &camera, false, show_debug);
                 }
                 for fighter in dungeon.fighters() {
                     fighter.draw_particles(&mut canvas, &mut tile_painter, &camera);
                 }
+                if let Some(cursor) = examine_cursor {
+                    tile_painter.draw_tile(
+                        &mut canvas,
+                        TileGraphic::TileHighlight,
+                        cursor.x * TILE_STRIDE - camera.x,
+                        cursor.y * TILE_STRIDE - camera.y,
+                        false,
+                        false,
+                    );
+                }
+
+                // Highlight the tile under the mouse (if it's somewhere to go or something to interact with)
+                let (hover_x, hover_y) = camera.screen_to_tile(ui.mouse_position);
+                let hovered_terrain = dungeon.level().get_terrain(hover_x, hover_y);
+                let interactable = hovered_terrain == Terrain::Door
+                    || hovered_terrain.is_locked_door()
+                    || dungeon
+                        .fighter_at(hover_x, hover_y)
+                        .is_some_and(|fighter| fighter.id != dungeon.player_id());
+                if screen == Screen::InGame
+                    && examine_cursor.is_none()
+                    && dungeon.can_run_events()
+                    && viewport.contains_point(ui.mouse_position)
+                    && hovered_terrain != Terrain::Empty
+                    && (dungeon.is_walkable(hover_x, hover_y) || interactable)
+                    && (auto_explore.has_seen(&dungeon, hover_x, hover_y)
+                        || dungeon
+                            .level()
+                            .in_line_of_sight(hover_x, hover_y, &mut canvas, &camera, false))
+                {
+                    tile_painter.draw_tile(
+                        &mut canvas,
+                        TileGraphic::TileHighlight,
+                        hover_x * TILE_STRIDE - camera.x,
+                        hover_y * TILE_STRIDE - camera.y,
+                        false,
+                        false,
+                    );
+                }
                 dungeon.level().draw(
                     &mut canvas,
                     &mut tile_painter,
                     &camera,
                     TileLayer::AboveFighters,
-                    show_debug,
                     false,
-                    dungeon.level_nth() >= 3,
+                    level_draw_settings,
                 );
                 for fighter in dungeon.fighters() {
-                    fighter.draw_health(&mut canvas, &camera);
+                    // The player's health bar is always shown
+                    let highlighted = Some(fighter.id) == selected_fighter
+                        || fighter.id == dungeon.player_id()
+                        || fighter.mouse_over(&camera, ui.mouse_position);
+                    fighter.draw_health(&mut canvas, &camera, only_damaged_health_bars, highlighted);
+                    fighter.draw_status_effects(&mut canvas, &camera);
                 }
                 dungeon.level().draw(
                     &mut canvas,
                     &mut tile_painter,
                     &camera,
                     TileLayer::AboveAll,
-                    show_debug,
                     !dungeon.is_first_level(),
-                    dungeon.level_nth() >= 3,
+                    level_draw_settings,
                 );
 
+                // Draw the tile grid (if debugging)
+                if show_debug && show_debug_grid {
+                    canvas.set_blend_mode(BlendMode::Blend);
+                    canvas.set_draw_color(interface::DEBUG_GRID);
+                    let first_x = -camera.x.rem_euclid(TILE_STRIDE);
+                    let first_y = -camera.y.rem_euclid(TILE_STRIDE);
+                    for x in (first_x..width as i32).step_by(TILE_STRIDE as usize) {
+                        let _ = canvas.draw_line(Point::new(x, 0), Point::new(x, height as i32));
+                    }
+                    for y in (first_y..height as i32).step_by(TILE_STRIDE as usize) {
+                        let _ = canvas.draw_line(Point::new(0, y), Point::new(width as i32, y));
+                    }
+                }
+
                 // Draw the treasure counter
-                {
+                if width >= 160 && height >= 66 {
                     let mineral_counter_bg = Rect::new(10, 10, 140, 46);
                     canvas.set_draw_color(interface::HUD_BACKGROUND_TRANSPARENT);
                     let _ = canvas.fill_rect(mineral_counter_bg);
@@ -448,23 +911,136 @@ pub fn main() {
                         horizontal_align: HorizontalAlign::Right,
                         ..LayoutSettings::default()
                     };
-                    let text = &[Text(
-                        Font::RegularUi,
-                        26.0,
-                        Color::WHITE,
-                        format!("{}", dungeon.treasure()),
-                    )];
+                    let text = &[match dungeon.carry_load() {
+                        Some((carried, cap)) => Text(
+                            Font::RegularUi,
+                            26.0,
+                            if carried > cap {
+                                interface::HEALTH_LOW
+                            } else {
+                                Color::WHITE
+                            },
+                            format!("{}/{}", carried, cap),
+                        ),
+                        None => Text(Font::RegularUi, 26.0, Color::WHITE, format!("{}", dungeon.treasure())),
+                    }];
                     canvas.set_clip_rect(mineral_counter_bg);
                     text_painter.draw_text(&mut canvas, &layout, text);
                     canvas.set_clip_rect(None);
                 }
 
+                // Draw the treasure left on the level
+                if width >= 210 && height >= 66 {
+                    let remaining_rect = Rect::new(154, 10, 46, 46);
+                    canvas.set_draw_color(interface::HUD_BACKGROUND_TRANSPARENT);
+                    let _ = canvas.fill_rect(remaining_rect);
+                    canvas.set_draw_color(interface::HUD_BORDER);
+                    let _ = canvas.draw_rect(remaining_rect);
+                    let remaining = LocalizableString::TreasureRemaining(dungeon.level().collectable_treasure());
+                    ui.text(
+                        &mut canvas,
+                        &mut text_painter,
+                        &remaining,
+                        remaining_rect.x + 6,
+                        remaining_rect.y + 4,
+                    );
+                }
+
+                // Draw the in-world clock
+                if width >= 210 && height >= 110 {
+                    let clock_rect = Rect::new(10, 62, 190, 42);
+                    canvas.set_draw_color(interface::HUD_BACKGROUND_TRANSPARENT);
+                    let _ = canvas.fill_rect(clock_rect);
+                    canvas.set_draw_color(interface::HUD_BORDER);
+                    let _ = canvas.draw_rect(clock_rect);
+                    let clock = LocalizableString::InWorldClock { round: dungeon.round() };
+                    ui.text(
+                        &mut canvas,
+                        &mut text_painter,
+                        &clock,
+                        clock_rect.x + 8,
+                        clock_rect.y + 4,
+                    );
+                }
+
+                // Draw the oxygen gauge (if oxygen is limited)
+                if let (Some((oxygen, max_oxygen)), true) = (dungeon.oxygen(), width >= 210 && height >= 130) {
+                    let gauge_rect = Rect::new(10, 108, 190, 12);
+                    canvas.set_draw_color(interface::HUD_BACKGROUND_TRANSPARENT);
+                    let _ = canvas.fill_rect(gauge_rect);
+                    let filled_width = (gauge_rect.width() as i32 - 4) * oxygen / max_oxygen;
+                    if filled_width > 0 {
+                        canvas.set_draw_color(if oxygen <= max_oxygen / 4 {
+                            interface::HEALTH_LOW
+                        } else {
+                            interface::OXYGEN_FILL
+                        });
+                        let _ = canvas.fill_rect(Rect::new(
+                            gauge_rect.x + 2,
+                            gauge_rect.y + 2,
+                            filled_width as u32,
+                            gauge_rect.height() - 4,
+                        ));
+                    }
+                    canvas.set_draw_color(interface::HUD_BORDER);
+                    let _ = canvas.draw_rect(gauge_rect);
+                }
+                if run_options != dungeon.options() {
+                    let changed = LocalizableString::RunOptionsChanged;
+                    ui.text(&mut canvas, &mut text_painter, &changed, 10, 124);
+                }
+                if forced_seed.is_some() && width >= 210 && height >= 160 {
+                    let seeded = LocalizableString::SeededRun(dungeon.seed());
+                    ui.text(&mut canvas, &mut text_painter, &seeded, 10, 140);
+                }
+
+                // Draw the description of the examined tile
+                if let (Some(cursor), true) = (examine_cursor, width >= 530 && height >= 130) {
+                    let level = dungeon.level();
+                    let terrain = level.get_terrain(cursor.x, cursor.y);
+                    let visible = level.in_line_of_sight(cursor.x, cursor.y, &mut canvas, &camera, false);
+                    // Tiles seen earlier stay described, even once they're out of sight
+                    let seen = visible || auto_explore.has_seen(&dungeon, cursor.x, cursor.y);
+                    let tile_description = LocalizableString::TileDescription {
+                        terrain: Some(terrain).filter(|_| seen),
+                        treasure: level
+                            .get_treasure(cursor.x, cursor.y)
+                            .filter(|_| visible)
+                            .map(|treasure| treasure.amount),
+                        fighter: dungeon
+                            .fighter_at(cursor.x, cursor.y)
+                            .filter(|_| visible)
+                            .map(|fighter| fighter.name.clone()),
+                        finger: dungeon.player().stats.finger,
+                        die: dungeon.options().die,
+                    };
+                    let description_rect = Rect::new(width as i32 - 310, 10, 300, 140);
+                    ui.text_box(
+                        &mut canvas,
+                        &mut text_painter,
+                        &tile_description,
+                        description_rect,
+                        false,
+                    );
+                }
+
                 // Draw the combat log
                 dungeon.log().draw_messages(&mut canvas, &mut text_painter);
 
                 // Draw the fighter selection HUD
-                if let Some(selected_fighter) = selected_fighter.and_then(|id| dungeon.get_fighter(id)) {
-                    let background_rect = Rect::new(width as i32 - 310, height as i32 - 20 - 16 * 12 - 135, 300, 125);
+                let inspection_panel_fits = width >= 320 && height >= 20 + 16 * 12 + 155 + 66;
+                if let Some(selected_fighter) = selected_fighter
+                    .filter(|_| inspection_panel_fits)
+                    .and_then(|id| dungeon.get_fighter(id))
+                {
+                    let show_odds = selected_fighter.id != dungeon.player_id() && selected_fighter.stats.health > 0;
+                    let panel_height = if show_odds { 145 } else { 125 };
+                    let background_rect = Rect::new(
+                        width as i32 - 310,
+                        height as i32 - 20 - 16 * 12 - 10 - panel_height as i32,
+                        300,
+                        panel_height,
+                    );
                     canvas.set_draw_color(interface::HUD_BACKGROUND_TRANSPARENT);
                     let _ = canvas.fill_rect(background_rect);
 
@@ -475,38 +1051,130 @@ pub fn main() {
                         max_height: Some((background_rect.height() - 16) as f32),
                         ..LayoutSettings::default()
                     };
-                    let fighter_description = LocalizableString::FighterDescription {
+                    let mut fighter_description = LocalizableString::FighterDescription {
                         id: selected_fighter.id,
                         name: selected_fighter.name.clone(),
                         max_health: selected_fighter.stats.max_health,
                         health: selected_fighter.stats.health,
-                        arm: selected_fighter.stats.arm,
-                        leg: selected_fighter.stats.leg,
-                        finger: selected_fighter.stats.finger,
                     }
                     .localize(Language::English);
+                    // The name line would otherwise get clipped at the edge of the panel
+                    if let Some(name_line) = fighter_description.first_mut() {
+                        *name_line = text_painter.ellipsize(name_line, layout.max_width.unwrap());
+                    }
                     canvas.set_clip_rect(background_rect);
                     text_painter.draw_text(&mut canvas, &layout, &fighter_description);
+
+                    let stats = [StatIncrease::Arm, StatIncrease::Leg, StatIncrease::Finger];
+                    let experience = selected_fighter
+                        .experience_ratios()
+                        .map(|(arm, leg, finger)| [arm, leg, finger]);
+                    for (i, stat) in stats.iter().enumerate() {
+                        let y = background_rect.y + 58 + i as i32 * 20;
+                        let stat_text = LocalizableString::FighterStat {
+                            stat: *stat,
+                            value: selected_fighter.stats.get(*stat),
+                        };
+                        ui.text(&mut canvas, &mut text_painter, &stat_text, background_rect.x + 8, y);
+
+                        // Training progress towards the next +1
+                        if let (Some(experience), Some(actions_left)) =
+                            (experience, selected_fighter.actions_until_trained(*stat))
+                        {
+                            let bar_rect = Rect::new(background_rect.x + 100, y + 7, 100, 6);
+                            canvas.set_draw_color(interface::EXPERIENCE_EMPTY);
+                            let _ = canvas.fill_rect(bar_rect);
+                            let filled_width = (experience[i].clamp(0.0, 1.0) * bar_rect.width() as f32) as u32;
+                            if filled_width > 0 {
+                                canvas.set_draw_color(interface::EXPERIENCE_FILL);
+                                let _ = canvas.fill_rect(Rect::new(
+                                    bar_rect.x,
+                                    bar_rect.y,
+                                    filled_width,
+                                    bar_rect.height(),
+                                ));
+                            }
+
+                            let actions_left_text = LocalizableString::ActionsUntilTrained {
+                                stat: *stat,
+                                actions: actions_left,
+                            };
+                            ui.text(
+                                &mut canvas,
+                                &mut text_painter,
+                                &actions_left_text,
+                                bar_rect.right() + 8,
+                                y + 2,
+                            );
+                        }
+                    }
+
+                    // The player's odds in a fight against the selected enemy
+                    if show_odds {
+                        let player_stats = &dungeon.player().stats;
+                        let die = dungeon.options().die;
+                        let odds_text = LocalizableString::CombatOdds {
+                            hit_chance: player_stats.hit_chance_against(&selected_fighter.stats, die),
+                            expected_damage: player_stats.expected_damage_against(&selected_fighter.stats, die),
+                            hit_back_chance: selected_fighter.stats.hit_chance_against(player_stats, die),
+                        };
+                        ui.text(
+                            &mut canvas,
+                            &mut text_painter,
+                            &odds_text,
+                            background_rect.x + 8,
+                            background_rect.y + 120,
+                        );
+                    }
                     canvas.set_clip_rect(None);
 
                     canvas.set_draw_color(interface::HUD_BORDER);
                     let _ = canvas.draw_rect(background_rect);
                 }
 
+                // Compare finished runs to the personal best. Seeded, peaceful
+                // and unranked runs don't count, as they're easier.
+                let run_over = dungeon.is_game_over() || dungeon.is_abandoned() || dungeon.final_treasure_found();
+                let counts_for_best =
+                    forced_seed.is_none() && dungeon.options().ranked() && !dungeon.options().peaceful;
+                if run_over && counts_for_best && recorded_run.is_none_or(|(seed, _)| seed != dungeon.seed()) {
+                    let new_best = personal_best.record(&dungeon.sim_result());
+                    recorded_run = Some((dungeon.seed(), new_best));
+                }
+                let personal_best_text = LocalizableString::PersonalBest {
+                    treasure: personal_best.treasure,
+                    fastest_finish: personal_best.fastest_finish,
+                    new_record: recorded_run == Some((dungeon.seed(), true)),
+                };
+
                 // Draw the game over screen (if needed)
-                if dungeon.is_game_over() {
+                if dungeon.is_game_over() || dungeon.is_abandoned() {
                     let bg_width = 400;
-                    let bg_height = 140;
+                    let bg_height = 224;
                     let background_rect = Rect::new(
                         (width as i32 - bg_width as i32) / 2,
                         (height as i32 - bg_height as i32) / 2,
                         bg_width,
                         bg_height,
                     );
-                    let game_over_string = LocalizableString::GameOver {
-                        name: dungeon.player().name.clone(),
+                    let game_over_string = if dungeon.is_game_over() {
+                        LocalizableString::GameOver {
+                            name: dungeon.player().name.clone(),
+                            run_stats: dungeon.run_stats(),
+                        }
+                    } else {
+                        LocalizableString::RunAbandoned {
+                            run_stats: dungeon.run_stats(),
+                        }
                     };
                     ui.text_box(&mut canvas, &mut text_painter, &game_over_string, background_rect, true);
+                    ui.text(
+                        &mut canvas,
+                        &mut text_painter,
+                        &personal_best_text,
+                        background_rect.x + 10,
+                        background_rect.bottom() - 72,
+                    );
 
                     let restart_button = Rect::new(
                         background_rect.x + 10,
@@ -521,7 +1189,11 @@ pub fn main() {
                         restart_button,
                         true,
                     ) {
-                        dungeon = Dungeon::new((delta_seconds * 1_000_000_000.0) as u64)
+                        dungeon = Dungeon::with_options(new_seed(forced_seed), run_options);
+                        camera.reset();
+                        first_stat_pick = None;
+                        toasts.reset(&dungeon);
+                        selected_fighter = Some(dungeon.player_id());
                     }
 
                     let submit_button = Rect::new(
@@ -535,7 +1207,7 @@ pub fn main() {
                         &mut text_painter,
                         &LocalizableString::SubmitToLeaderboardsButton,
                         submit_button,
-                        true,
+                        forced_seed.is_none() && dungeon.options().ranked(),
                     ) {
                         screen = Screen::Leaderboard;
                         leaderboard.submit_run(&dungeon);
@@ -544,16 +1216,26 @@ pub fn main() {
 
                 // Draw the victory screen (if the final treasure has been found)
                 if dungeon.final_treasure_found() && !dungeon.is_game_over() || show_debug {
-                    let bg_width = 450;
-                    let bg_height = 160;
-                    let background_rect = Rect::new((width - 10 - bg_width) as i32, 10, bg_width, bg_height);
+                    let bg_width = 450.min(width.saturating_sub(20));
+                    let bg_height = 234;
+                    let background_rect =
+                        Rect::new(width.saturating_sub(10 + bg_width) as i32, 10, bg_width, bg_height);
                     ui.text_box(
                         &mut canvas,
                         &mut text_painter,
-                        &LocalizableString::Victory,
+                        &LocalizableString::Victory {
+                            run_stats: dungeon.run_stats(),
+                        },
                         background_rect,
                         false,
                     );
+                    ui.text(
+                        &mut canvas,
+                        &mut text_painter,
+                        &personal_best_text,
+                        background_rect.x + 10,
+                        background_rect.bottom() - 72,
+                    );
 
                     let restart_button = Rect::new(
                         background_rect.x + 10,
@@ -568,7 +1250,11 @@ pub fn main() {
                         restart_button,
                         true,
                     ) {
-                        dungeon = Dungeon::new((delta_seconds * 1_000_000_000.0) as u64)
+                        dungeon = Dungeon::with_options(new_seed(forced_seed), run_options);
+                        camera.reset();
+                        first_stat_pick = None;
+                        toasts.reset(&dungeon);
+                        selected_fighter = Some(dungeon.player_id());
                     }
 
                     let submit_button = Rect::new(
@@ -582,7 +1268,7 @@ pub fn main() {
                         &mut text_painter,
                         &LocalizableString::SubmitToLeaderboardsButton,
                         submit_button,
-                        true,
+                        forced_seed.is_none() && dungeon.options().ranked(),
                     ) {
                         screen = Screen::Leaderboard;
                         leaderboard.submit_run(&dungeon);
@@ -594,8 +1280,8 @@ pub fn main() {
                     canvas.set_draw_color(interface::SCREEN_FADE_COLOR);
                     let _ = canvas.fill_rect(Rect::new(0, 0, width, height));
 
-                    let bg_width = 900.min(width - 20);
-                    let bg_height = (380 + 2 * (600 - bg_width as i32).max(0) as u32).min(height - 10);
+                    let bg_width = 900.min(width.saturating_sub(20));
+                    let bg_height = (380 + 2 * (600 - bg_width as i32).max(0) as u32).min(height.saturating_sub(10));
                     let background_rect = Rect::new(
                         (width - bg_width) as i32 / 2,
                         (height - bg_height) as i32 / 2,
@@ -610,20 +1296,36 @@ pub fn main() {
                         true,
                     );
 
+                    let points_remaining = LocalizableString::StatPointsRemaining {
+                        first_pick: first_stat_pick,
+                    };
+                    ui.text(
+                        &mut canvas,
+                        &mut text_painter,
+                        &points_remaining,
+                        background_rect.x + 10,
+                        background_rect.y + 136,
+                    );
+
                     use StatIncrease::*;
+                    let mut single_point_buttons = Vec::with_capacity(3);
                     for (i, inc) in [Arm, Leg, Finger].iter().enumerate() {
                         let padding = 10;
-                        let section_width = (background_rect.width() - padding as u32) / 3;
+                        let section_width = background_rect.width().saturating_sub(padding as u32) / 3;
                         let section_rect = Rect::new(
                             background_rect.x + padding + (section_width as i32) * i as i32,
                             background_rect.y + 160,
-                            section_width - padding as u32,
-                            background_rect.height() - 170,
+                            section_width.saturating_sub(padding as u32),
+                            background_rect.height().saturating_sub(170),
                         );
                         ui.text_box(
                             &mut canvas,
                             &mut text_painter,
-                            &LocalizableString::StatInfo(*inc),
+                            &LocalizableString::StatInfo {
+                                stat: *inc,
+                                current: dungeon.player().stats.get(*inc),
+                                die: dungeon.options().die,
+                            },
                             section_rect,
                             true,
                         );
@@ -631,7 +1333,7 @@ pub fn main() {
                         let button_rect = Rect::new(
                             section_rect.x + 10,
                             section_rect.y + section_rect.height() as i32 - 46,
-                            section_rect.width() - 20,
+                            section_rect.width().saturating_sub(20),
                             36,
                         );
                         if ui.button(
@@ -639,12 +1341,340 @@ pub fn main() {
                             &mut text_painter,
                             &LocalizableString::IncreaseStatButton(*inc),
                             button_rect,
-                            true,
+                            first_stat_pick.is_none(),
                         ) {
                             dungeon.run_event(DungeonEvent::LevelUp(*inc));
                         }
+
+                        let mut single_point_button = button_rect;
+                        single_point_button.offset(0, -42);
+                        single_point_buttons.push((*inc, single_point_button));
+                    }
+
+                    // The +1 buttons go after the +2 ones, to keep the +2 hotkeys as 1-3
+                    for (inc, button_rect) in single_point_buttons {
+                        if ui.button(
+                            &mut canvas,
+                            &mut text_painter,
+                            &LocalizableString::IncreaseStatByOneButton(inc),
+                            button_rect,
+                            true,
+                        ) {
+                            if let Some(first) = first_stat_pick.take() {
+                                dungeon.run_event(DungeonEvent::LevelUpSplit(first, inc));
+                            } else {
+                                first_stat_pick = Some(inc);
+                            }
+                        }
+                    }
+
+                    // Taking back the first +1, to pick another one or a +2 instead
+                    if first_stat_pick.is_some() {
+                        let cancel_button = Rect::new(background_rect.right() - 130, background_rect.y + 128, 120, 28);
+                        if ui.button(
+                            &mut canvas,
+                            &mut text_painter,
+                            &LocalizableString::CancelButton,
+                            cancel_button,
+                            true,
+                        ) {
+                            first_stat_pick = None;
+                        }
+                    }
+                }
+
+                // Draw the current hint (if there's something to explain)
+                hints.update(&dungeon);
+                let hint_hidden = dungeon.stat_increase_pending() || screen == Screen::Paused;
+                if let (Some(hint), false, true) = (hints.current, hint_hidden, width >= 580) {
+                    let hint_rect = Rect::new((width as i32 - 360) / 2 + 100, 10, 360, 110);
+                    ui.text_box(
+                        &mut canvas,
+                        &mut text_painter,
+                        &LocalizableString::Hint(hint),
+                        hint_rect,
+                        false,
+                    );
+                    let dismiss_button = Rect::new(hint_rect.right() - 100, hint_rect.bottom() - 36, 90, 28);
+                    if ui.button(
+                        &mut canvas,
+                        &mut text_painter,
+                        &LocalizableString::DismissHintButton,
+                        dismiss_button,
+                        true,
+                    ) {
+                        hints.dismiss();
                     }
                 }
+
+                // Draw the announcements of big events, below the hint if there is one
+                toasts.update(&dungeon, delta_seconds);
+                if width >= 320 {
+                    let top = if hints.current.is_some() && width >= 580 {
+                        130
+                    } else {
+                        10
+                    };
+                    canvas.set_blend_mode(BlendMode::Blend);
+                    toasts.draw(&mut canvas, &mut text_painter, width as i32 / 2, top);
+                }
+
+                // Offer to recover the last session (if it crashed)
+                if let (Some(slot), true) = (recovery_slot, width >= 420 && height >= 200) {
+                    let prompt_rect = Rect::new((width as i32 - 400) / 2, (height as i32 - 140) / 2, 400, 140);
+                    ui.text_box(
+                        &mut canvas,
+                        &mut text_painter,
+                        &LocalizableString::RecoveryPrompt,
+                        prompt_rect,
+                        true,
+                    );
+                    let recover_button = Rect::new(prompt_rect.x + 10, prompt_rect.bottom() - 46, 185, 36);
+                    let dismiss_button = Rect::new(prompt_rect.x + 205, prompt_rect.bottom() - 46, 185, 36);
+                    if ui.button(
+                        &mut canvas,
+                        &mut text_painter,
+                        &LocalizableString::RecoverSessionButton,
+                        recover_button,
+                        true,
+                    ) {
+                        if let Some(loaded_dungeon) = Autosaves::load(slot) {
+                            dungeon = loaded_dungeon;
+                            camera.reset();
+                            first_stat_pick = None;
+                            toasts.reset(&dungeon);
+                            autosaves.reset(&dungeon);
+                            selected_fighter = Some(dungeon.player_id());
+                        }
+                        recovery_slot = None;
+                    }
+                    if ui.button(
+                        &mut canvas,
+                        &mut text_painter,
+                        &LocalizableString::StartFreshButton,
+                        dismiss_button,
+                        true,
+                    ) {
+                        recovery_slot = None;
+                    }
+                }
+
+                // Autosave, and draw the list of autosaves (if opened)
+                autosaves.update(&dungeon);
+                if let (Some(autosave_infos), true) = (&autosave_list, width >= 420 && height >= 300) {
+                    let list_height = 60 + 46 * autosave_infos.len() as u32;
+                    let list_rect = Rect::new(
+                        (width as i32 - 400) / 2,
+                        (height as i32 - list_height as i32) / 2,
+                        400,
+                        list_height,
+                    );
+                    let header = LocalizableString::AutosavesHeader(autosave_infos.len());
+                    ui.text_box(&mut canvas, &mut text_painter, &header, list_rect, true);
+                    let mut loaded_slot = None;
+                    for (i, info) in autosave_infos.iter().enumerate() {
+                        let button_rect = Rect::new(list_rect.x + 10, list_rect.y + 40 + 46 * i as i32, 380, 36);
+                        let button_text = LocalizableString::LoadAutosaveButton {
+                            level: info.level,
+                            round: info.round,
+                            treasure: info.treasure,
+                            seconds_ago: info.saved_at.elapsed().map(|elapsed| elapsed.as_secs()).unwrap_or(0),
+                        };
+                        if ui.button(&mut canvas, &mut text_painter, &button_text, button_rect, true) {
+                            loaded_slot = Some(info.slot);
+                        }
+                    }
+                    if let Some(loaded_dungeon) = loaded_slot.and_then(Autosaves::load) {
+                        dungeon = loaded_dungeon;
+                        camera.reset();
+                        first_stat_pick = None;
+                        toasts.reset(&dungeon);
+                        autosaves.reset(&dungeon);
+                        selected_fighter = Some(dungeon.player_id());
+                        autosave_list = None;
+                    }
+                }
+
+                // Draw the pause menu (if paused)
+                if screen == Screen::Paused {
+                    canvas.set_draw_color(interface::SCREEN_FADE_COLOR);
+                    let _ = canvas.fill_rect(Rect::new(0, 0, width, height));
+
+                    let bg_width = 400;
+                    let bg_height = 386;
+                    let background_rect = Rect::new(
+                        (width as i32 - bg_width as i32) / 2,
+                        (height as i32 - bg_height as i32) / 2,
+                        bg_width,
+                        bg_height,
+                    );
+                    let menu_text = match pause_menu {
+                        PauseMenu::Main => LocalizableString::PauseMenu,
+                        PauseMenu::Settings => LocalizableString::SettingsMenu,
+                        PauseMenu::RunOptions => LocalizableString::RunOptionsMenu,
+                    };
+                    ui.text_box(&mut canvas, &mut text_painter, &menu_text, background_rect, true);
+
+                    // The buttons are stacked at the bottom of the menu
+                    let button_rect =
+                        |i: i32| Rect::new(background_rect.x + 10, background_rect.y + 110 + 46 * i, 380, 36);
+                    // Half-width buttons, two to a row, left to right
+                    let half_button_rect = |i: i32| {
+                        let row = button_rect(i / 2);
+                        Rect::new(row.x + 195 * (i % 2), row.y, 185, 36)
+                    };
+                    if pause_menu == PauseMenu::RunOptions {
+                        let oxygen = LocalizableString::OxygenModeButton(run_options.oxygen);
+                        if ui.button(&mut canvas, &mut text_painter, &oxygen, half_button_rect(0), true) {
+                            run_options.oxygen = !run_options.oxygen;
+                        }
+                        let die = LocalizableString::DieButton(run_options.die);
+                        if ui.button(&mut canvas, &mut text_painter, &die, half_button_rect(1), true) {
+                            run_options.die = match run_options.die {
+                                Die::D6 => Die::D4,
+                                Die::D4 => Die::D8,
+                                Die::D8 => Die::D6,
+                            };
+                        }
+                        let carry_cap = LocalizableString::CarryCapButton(run_options.carry_cap);
+                        if ui.button(&mut canvas, &mut text_painter, &carry_cap, half_button_rect(2), true) {
+                            run_options.carry_cap = !run_options.carry_cap;
+                        }
+                        let companion = LocalizableString::CompanionButton(run_options.companion);
+                        if ui.button(&mut canvas, &mut text_painter, &companion, half_button_rect(3), true) {
+                            run_options.companion = !run_options.companion;
+                        }
+                        let peaceful = LocalizableString::PeacefulModeButton(run_options.peaceful);
+                        if ui.button(&mut canvas, &mut text_painter, &peaceful, half_button_rect(4), true) {
+                            run_options.peaceful = !run_options.peaceful;
+                        }
+                        let undo = LocalizableString::UndoButton(run_options.undo);
+                        if ui.button(&mut canvas, &mut text_painter, &undo, half_button_rect(5), true) {
+                            run_options.undo = !run_options.undo;
+                        }
+                        let manual_pickup = LocalizableString::ManualPickupButton(run_options.manual_pickup);
+                        if ui.button(
+                            &mut canvas,
+                            &mut text_painter,
+                            &manual_pickup,
+                            half_button_rect(6),
+                            true,
+                        ) {
+                            run_options.manual_pickup = !run_options.manual_pickup;
+                        }
+                        let magnet = LocalizableString::TreasureMagnetButton(run_options.treasure_magnet);
+                        if ui.button(&mut canvas, &mut text_painter, &magnet, half_button_rect(7), true) {
+                            run_options.treasure_magnet = !run_options.treasure_magnet;
+                        }
+                        let back = LocalizableString::BackButton;
+                        if ui.button(&mut canvas, &mut text_painter, &back, button_rect(4), true) {
+                            pause_menu = PauseMenu::Settings;
+                        }
+                    } else if pause_menu == PauseMenu::Settings {
+                        let borderless = LocalizableString::BorderlessWindowButton(window_settings.borderless);
+                        if ui.button(&mut canvas, &mut text_painter, &borderless, button_rect(0), true) {
+                            window_settings.borderless = !window_settings.borderless;
+                            window_settings.apply(&video_subsystem, canvas.window_mut());
+                            window_settings.save();
+                        }
+                        let dark_fade = LocalizableString::DarkFadeButton(fade_radius_scale);
+                        if ui.button(&mut canvas, &mut text_painter, &dark_fade, button_rect(1), true) {
+                            fade_radius_scale = match fade_radius_scale {
+                                Some(scale) if scale < 2.0 => Some(2.0),
+                                Some(_) => None,
+                                None => Some(1.0),
+                            };
+                        }
+                        let threats = LocalizableString::ThreatHighlightsButton(show_threats);
+                        if ui.button(&mut canvas, &mut text_painter, &threats, button_rect(2), true) {
+                            show_threats = !show_threats;
+                        }
+                        let health_bars = LocalizableString::DamagedHealthBarsButton(only_damaged_health_bars);
+                        if ui.button(&mut canvas, &mut text_painter, &health_bars, button_rect(3), true) {
+                            only_damaged_health_bars = !only_damaged_health_bars;
+                        }
+                        let speed = LocalizableString::AnimationSpeedButton(animation_speed);
+                        if ui.button(&mut canvas, &mut text_painter, &speed, button_rect(4), true) {
+                            animation_speed = animation_speed.next();
+                        }
+                        let run_options_button = LocalizableString::RunOptionsButton;
+                        if ui.button(
+                            &mut canvas,
+                            &mut text_painter,
+                            &run_options_button,
+                            half_button_rect(10),
+                            true,
+                        ) {
+                            pause_menu = PauseMenu::RunOptions;
+                        }
+                        let back = LocalizableString::BackButton;
+                        if ui.button(&mut canvas, &mut text_painter, &back, half_button_rect(11), true) {
+                            pause_menu = PauseMenu::Main;
+                        }
+                    } else {
+                        let resume = LocalizableString::ResumeButton;
+                        if ui.button(&mut canvas, &mut text_painter, &resume, button_rect(0), true) {
+                            screen = Screen::InGame;
+                        }
+                        let save = LocalizableString::SaveButton;
+                        if ui.button(&mut canvas, &mut text_painter, &save, button_rect(1), true) {
+                            if quicksave(&dungeon) {
+                                quicksaved = Some((dungeon.seed(), dungeon.round()));
+                            }
+                            screen = Screen::InGame;
+                        }
+                        let settings = LocalizableString::SettingsButton;
+                        if ui.button(&mut canvas, &mut text_painter, &settings, button_rect(2), true) {
+                            pause_menu = PauseMenu::Settings;
+                        }
+                        let abandon = LocalizableString::AbandonRunButton;
+                        if ui.button(&mut canvas, &mut text_painter, &abandon, button_rect(3), true) {
+                            dungeon.run_event(DungeonEvent::Abandon);
+                            screen = Screen::InGame;
+                        }
+                        let copy_diagnostics = LocalizableString::CopyDiagnosticsButton;
+                        if ui.button(&mut canvas, &mut text_painter, &copy_diagnostics, button_rect(4), true) {
+                            match video_subsystem.clipboard().set_clipboard_text(&dungeon.diagnostics()) {
+                                Ok(_) => log::info!("Copied the diagnostics to the clipboard."),
+                                Err(err) => log::error!("Failed to copy the diagnostics to the clipboard: {}", err),
+                            }
+                        }
+                        let quit = LocalizableString::QuitButton;
+                        if ui.button(&mut canvas, &mut text_painter, &quit, button_rect(5), true) {
+                            if has_unsaved_progress(&dungeon, quicksaved) {
+                                quit_requested = true;
+                            } else {
+                                break 'running;
+                            }
+                        }
+                    }
+
+                    // The details for bug reports, under the menu
+                    let diagnostics = LocalizableString::Diagnostics(dungeon.diagnostics());
+                    ui.text(
+                        &mut canvas,
+                        &mut text_painter,
+                        &diagnostics,
+                        background_rect.x,
+                        background_rect.bottom() + 10,
+                    );
+                }
+            }
+        }
+
+        // Ask before quitting (if the window was closed with unsaved progress)
+        if let Some(dialog_ui) = dialog_ui {
+            ui = dialog_ui;
+            let buttons = [
+                LocalizableString::SaveAndQuitButton,
+                LocalizableString::QuitAnywayButton,
+                LocalizableString::CancelButton,
+            ];
+            match ui.dialog(&mut canvas, &mut text_painter, &LocalizableString::QuitPrompt, &buttons) {
+                Some(0) if quicksave(&dungeon) => break 'running,
+                Some(1) => break 'running,
+                Some(2) => quit_requested = false,
+                _ => {}
             }
         }
 
@@ -654,9 +1684,66 @@ pub fn main() {
             let title = Text(Font::RegularUi, 28.0, color, String::from("Excavation Site Mercury\n"));
             let info = Text(Font::RegularUi, 18.0, color, String::from("R to regenerate dungeon\nF5 to quicksave in working directory\nF9 to load quicksave from working directory\n"));
             let fps = frame_times.len();
-            let fps = Text(Font::RegularUi, 18.0, color, format!("FPS: {}", fps));
+            let frame_ms = frame_durations
+                .iter()
+                .map(|d| d.as_secs_f32() * 1000.0)
+                .collect::<Vec<f32>>();
+            let min_ms = frame_ms.iter().copied().fold(f32::INFINITY, f32::min);
+            let max_ms = frame_ms.iter().copied().fold(0.0, f32::max);
+            let avg_ms = frame_ms.iter().sum::<f32>() / frame_ms.len().max(1) as f32;
+            let fps = Text(
+                Font::RegularUi,
+                18.0,
+                color,
+                format!(
+                    "FPS: {}\nFrame time: {:.1} / {:.1} / {:.1} ms (min / avg / max)\n",
+                    fps,
+                    if frame_ms.is_empty() { 0.0 } else { min_ms },
+                    avg_ms,
+                    max_ms,
+                ),
+            );
+            let (tile_x, tile_y) = camera.screen_to_tile(ui.mouse_position);
+            let dungeon_info = Text(
+                Font::RegularUi,
+                18.0,
+                color,
+                format!(
+                    "Version: {}\nSeed: {}\nLevel: {}\nRound: {}\nHovered tile: ({}, {}) {:?}\nG to toggle the tile grid\n",
+                    env!("CARGO_PKG_VERSION"),
+                    dungeon.seed(),
+                    dungeon.level_nth(),
+                    dungeon.round(),
+                    tile_x,
+                    tile_y,
+                    dungeon.level().get_terrain(tile_x, tile_y),
+                ),
+            );
             let layout = LayoutSettings::default();
-            text_painter.draw_text(&mut canvas, &layout, &[title, info, fps]);
+            text_painter.draw_text(&mut canvas, &layout, &[title, info, fps, dungeon_info]);
+
+            // The frame time graph, one bar per frame, with a line at 60 FPS
+            let graph_rect = Rect::new(10, 295, FRAME_GRAPH_LENGTH as u32 * 2, 60);
+            canvas.set_blend_mode(BlendMode::Blend);
+            canvas.set_draw_color(interface::HUD_BACKGROUND_TRANSPARENT);
+            let _ = canvas.fill_rect(graph_rect);
+            canvas.set_draw_color(color);
+            for (i, ms) in frame_ms.iter().enumerate() {
+                let bar_height = ((ms / FRAME_GRAPH_MAX_MS).min(1.0) * graph_rect.height() as f32).max(1.0) as u32;
+                let _ = canvas.fill_rect(Rect::new(
+                    graph_rect.x() + i as i32 * 2,
+                    graph_rect.bottom() - bar_height as i32,
+                    2,
+                    bar_height,
+                ));
+            }
+            let target_y =
+                graph_rect.bottom() - (1000.0 / 60.0 / FRAME_GRAPH_MAX_MS * graph_rect.height() as f32) as i32;
+            canvas.set_draw_color(interface::DEBUG_GRID);
+            let _ = canvas.draw_line(
+                Point::new(graph_rect.x(), target_y),
+                Point::new(graph_rect.right(), target_y),
+            );
         }
 
         // Update cursor
@@ -667,12 +1754,29 @@ pub fn main() {
         }
 
         // Whew, done with this frame.
+        text_painter.end_frame();
         canvas.present();
 
         let now = Instant::now();
+        if let Some(previous) = frame_times.last() {
+            if frame_durations.len() == FRAME_GRAPH_LENGTH {
+                frame_durations.pop_front();
+            }
+            frame_durations.push_back(now - *previous);
+        }
         frame_times.push(now);
         frame_times.retain(|i| now - *i <= Duration::from_secs(1));
     }
+
+    // Remember the display the window was on, in case it was dragged over
+    if let Ok(display) = canvas.window().display_index() {
+        window_settings.display = display;
+    }
+    window_settings.save();
+
+    if let Err(err) = std::fs::remove_file(RUNNING_SENTINEL_FILE) {
+        log::error!("Failed to remove {}: {}", RUNNING_SENTINEL_FILE, err);
+    }
 }
 
 pub fn move_towards(value: i32, target: i32, speed: i32) -> i32 {
@@ -0,0 +1,56 @@
+use crate::{RunOutcome, SimResult};
+
+/// The best results are stored here between sessions.
+static PERSONAL_BEST_FILE: &str = "excavation-site-mercury-best.txt";
+
+/// The player's best runs on this computer, kept separate from the
+/// online leaderboard so there's something to beat offline too.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PersonalBest {
+    pub treasure: i32,
+    /// The fewest rounds a run has been finished in.
+    pub fastest_finish: Option<u64>,
+}
+
+impl PersonalBest {
+    pub fn load() -> PersonalBest {
+        let mut best = PersonalBest::default();
+        let contents = std::fs::read_to_string(PERSONAL_BEST_FILE).unwrap_or_default();
+        for line in contents.lines() {
+            match line.split_once('=') {
+                Some(("treasure", value)) => best.treasure = value.trim().parse().unwrap_or(0),
+                Some(("fastest_finish", value)) => best.fastest_finish = value.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        best
+    }
+
+    fn save(&self) {
+        let mut contents = format!("treasure={}\n", self.treasure);
+        if let Some(rounds) = self.fastest_finish {
+            contents += &format!("fastest_finish={}\n", rounds);
+        }
+        if let Err(err) = std::fs::write(PERSONAL_BEST_FILE, contents) {
+            log::error!("Failed to save the personal best to {}: {}", PERSONAL_BEST_FILE, err);
+        }
+    }
+
+    /// Compares the finished run to the best ones so far, and saves it
+    /// if it beat them. Returns true if it did.
+    pub fn record(&mut self, result: &SimResult) -> bool {
+        let mut improved = false;
+        if result.treasure > self.treasure {
+            self.treasure = result.treasure;
+            improved = true;
+        }
+        if result.outcome == RunOutcome::Finished && self.fastest_finish.is_none_or(|rounds| result.rounds < rounds) {
+            self.fastest_finish = Some(result.rounds);
+            improved = true;
+        }
+        if improved {
+            self.save();
+        }
+        improved
+    }
+}
@@ -1,3 +1,5 @@
+use rand_core::RngCore;
+use rand_pcg::Pcg32;
 use serde::{Deserialize, Serialize};
 
 pub const DUMMY: Stats = Stats {
@@ -20,6 +22,16 @@ pub const PLAYER: Stats = Stats {
     treasure: 0,
 };
 
+pub const REPAIR_DRONE: Stats = Stats {
+    max_health: 6,
+    health: 6,
+    arm: 10,
+    leg: 12,
+    finger: 1,
+    flying: true,
+    treasure: 0,
+};
+
 pub const SLIME: Stats = Stats {
     max_health: 4,
     health: 4,
@@ -60,6 +72,16 @@ pub const SENTIENT_METAL: Stats = Stats {
     treasure: 6,
 };
 
+pub const METAL_OVERSEER: Stats = Stats {
+    max_health: 15,
+    health: 15,
+    arm: 17,
+    leg: 14,
+    finger: 1,
+    flying: true,
+    treasure: 40,
+};
+
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
 pub enum StatIncrease {
     Arm,
@@ -96,4 +118,258 @@ impl Stats {
             StatIncrease::Finger => self.finger += 2,
         }
     }
+
+    pub fn get(&self, stat: StatIncrease) -> i32 {
+        match stat {
+            StatIncrease::Arm => self.arm,
+            StatIncrease::Leg => self.leg,
+            StatIncrease::Finger => self.finger,
+        }
+    }
+
+    pub fn get_mut(&mut self, stat: StatIncrease) -> &mut i32 {
+        match stat {
+            StatIncrease::Arm => &mut self.arm,
+            StatIncrease::Leg => &mut self.leg,
+            StatIncrease::Finger => &mut self.finger,
+        }
+    }
+
+    /// The chance of landing an attack on `defender`.
+    pub fn hit_chance_against(&self, defender: &Stats, die: Die) -> f32 {
+        die.success_chance(defender.leg - self.arm)
+    }
+
+    /// The average damage dealt by attacking `defender`, misses
+    /// included. Hits deal `1 + (roll + arm - leg) / 6`.
+    pub fn expected_damage_against(&self, defender: &Stats, die: Die) -> f32 {
+        let total_damage = (1..=die.sides())
+            .map(|roll| resolve_attack(self, defender, roll).damage())
+            .sum::<i32>();
+        total_damage as f32 / die.sides() as f32
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AttackOutcome {
+    Miss,
+    Hit { damage: i32 },
+}
+
+impl AttackOutcome {
+    pub fn damage(self) -> i32 {
+        match self {
+            AttackOutcome::Miss => 0,
+            AttackOutcome::Hit { damage } => damage,
+        }
+    }
+}
+
+/// Hits dealing at least this much damage push the defender back a
+/// tile, which takes rolling 6 over the hit threshold.
+pub const PUSHING_DAMAGE: i32 = 2;
+
+/// Resolves an attack with the given die roll. The attack hits if the
+/// roll is at least the defender's leg minus the attacker's arm, and
+/// every 6 points the roll goes over that adds a point of damage.
+pub fn resolve_attack(attacker: &Stats, defender: &Stats, roll: i32) -> AttackOutcome {
+    let modifier = attacker.arm - defender.leg;
+    if roll >= -modifier {
+        AttackOutcome::Hit {
+            damage: 1 + (roll + modifier) / 6,
+        }
+    } else {
+        AttackOutcome::Miss
+    }
+}
+
+/// Treasure an enemy might drop when incapacitated, on top of the
+/// [Stats::treasure] it carries.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LootTable {
+    /// The chance of dropping anything, in percent.
+    pub chance: u32,
+    pub min_amount: i32,
+    pub max_amount: i32,
+}
+
+impl LootTable {
+    /// How much treasure drops, often nothing.
+    pub fn roll(self, rng: &mut Pcg32) -> i32 {
+        let dropped = rng.next_u32() % 100 < self.chance;
+        let range = (self.max_amount - self.min_amount + 1).max(1) as u32;
+        let amount = self.min_amount + (rng.next_u32() % range) as i32;
+        if dropped {
+            amount
+        } else {
+            0
+        }
+    }
+}
+
+/// Rock men are mostly rock, but there's some ore in there.
+pub const ROCKMAN_LOOT: LootTable = LootTable {
+    chance: 50,
+    min_amount: 2,
+    max_amount: 4,
+};
+
+/// Sometimes a valuable component survives the metal being's demise.
+pub const SENTIENT_METAL_LOOT: LootTable = LootTable {
+    chance: 25,
+    min_amount: 6,
+    max_amount: 10,
+};
+
+/// The die thrown for attacks and lockpicking, picked per run in
+/// [RunOptions](crate::RunOptions). Smaller dice mean less swing, so
+/// the stats matter more.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Debug)]
+pub enum Die {
+    D4,
+    #[default]
+    D6,
+    D8,
+}
+
+impl Die {
+    pub const fn sides(self) -> i32 {
+        match self {
+            Die::D4 => 4,
+            Die::D6 => 6,
+            Die::D8 => 8,
+        }
+    }
+
+    pub fn roll_die(self, rng: &mut Pcg32) -> i32 {
+        1 + (rng.next_u32() % self.sides() as u32) as i32
+    }
+
+    /// The chance of rolling at least `min_roll`, used for hits
+    /// (`min_roll` being the defender's leg minus the attacker's arm)
+    /// and lockpicking (the lock's threshold minus finger).
+    pub fn success_chance(self, min_roll: i32) -> f32 {
+        (self.sides() + 1 - min_roll.max(1)).max(0) as f32 / self.sides() as f32
+    }
+
+    /// How many tries it takes on average to roll at least `min_roll`,
+    /// e.g. bumping into a locked door until it opens. None if it's
+    /// impossible.
+    pub fn expected_tries(self, min_roll: i32) -> Option<f32> {
+        let chance = self.success_chance(min_roll);
+        if chance > 0.0 {
+            Some(1.0 / chance)
+        } else {
+            None
+        }
+    }
+}
+
+/// The kinds of lingering effects attacks can leave on a fighter.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub enum StatusKind {
+    /// Deals damage every round.
+    Poison,
+    /// Makes the fighter lose its turns.
+    Stun,
+    /// Deals damage every round, and catches on to fighters standing
+    /// next to the burning one.
+    Burning,
+}
+
+impl StatusKind {
+    pub const fn damage_per_round(self) -> i32 {
+        match self {
+            StatusKind::Poison | StatusKind::Burning => 1,
+            StatusKind::Stun => 0,
+        }
+    }
+}
+
+/// A status effect on a fighter, ticked at the end of every round
+/// until there are no rounds left. The round it's applied on counts,
+/// so a 2 round stun makes the fighter lose one turn.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub rounds_left: u32,
+}
+
+/// Tallies of what happened during a run, shown at the end of it.
+/// Calculated from the events, so replays arrive at the same numbers.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct RunStats {
+    pub enemies_incapacitated: u32,
+    pub tiles_walked: u32,
+    pub locked_doors_opened: u32,
+    pub damage_taken: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loot_stays_in_range() {
+        use rand_core::SeedableRng;
+        let mut drops = 0;
+        for seed in 0..1000 {
+            let amount = ROCKMAN_LOOT.roll(&mut Pcg32::seed_from_u64(seed));
+            if amount > 0 {
+                drops += 1;
+                assert!((ROCKMAN_LOOT.min_amount..=ROCKMAN_LOOT.max_amount).contains(&amount));
+            }
+        }
+        // 50% drop chance, give or take
+        assert!(drops > 400 && drops < 600, "{} drops", drops);
+    }
+
+    #[test]
+    fn even_stats_always_hit() {
+        for roll in 1..=5 {
+            assert_eq!(resolve_attack(&PLAYER, &PLAYER, roll), AttackOutcome::Hit { damage: 1 });
+        }
+        assert_eq!(resolve_attack(&PLAYER, &PLAYER, 6), AttackOutcome::Hit { damage: 2 });
+    }
+
+    #[test]
+    fn dodgy_defenders_need_high_rolls() {
+        // Rockman's leg is 14, so the player's 10 arm needs a 4
+        assert_eq!(resolve_attack(&PLAYER, &ROCKMAN, 3), AttackOutcome::Miss);
+        assert_eq!(resolve_attack(&PLAYER, &ROCKMAN, 4), AttackOutcome::Hit { damage: 1 });
+        assert_eq!(resolve_attack(&PLAYER, &ROCKMAN, 6), AttackOutcome::Hit { damage: 1 });
+        let untouchable = Stats { leg: 17, ..PLAYER };
+        assert_eq!(resolve_attack(&PLAYER, &untouchable, 6), AttackOutcome::Miss);
+    }
+
+    #[test]
+    fn strong_attackers_deal_more_damage() {
+        // Overseer's 17 arm against the player's 10 leg: +7
+        assert_eq!(
+            resolve_attack(&METAL_OVERSEER, &PLAYER, 1),
+            AttackOutcome::Hit { damage: 2 }
+        );
+        assert_eq!(
+            resolve_attack(&METAL_OVERSEER, &PLAYER, 5),
+            AttackOutcome::Hit { damage: 3 }
+        );
+    }
+
+    #[test]
+    fn odds_match_the_resolution() {
+        assert_eq!(PLAYER.hit_chance_against(&ROCKMAN, Die::D6), 0.5);
+        assert_eq!(PLAYER.expected_damage_against(&ROCKMAN, Die::D6), 0.5);
+        assert_eq!(PLAYER.expected_damage_against(&PLAYER, Die::D6), 7.0 / 6.0);
+        assert_eq!(PLAYER.hit_chance_against(&ROCKMAN, Die::D4), 0.25);
+        assert_eq!(PLAYER.hit_chance_against(&ROCKMAN, Die::D8), 0.625);
+    }
+
+    #[test]
+    fn lock_odds_cover_the_whole_die() {
+        assert_eq!(Die::D6.expected_tries(-3), Some(1.0));
+        assert_eq!(Die::D6.expected_tries(4), Some(2.0));
+        assert_eq!(Die::D6.expected_tries(6), Some(6.0));
+        assert_eq!(Die::D6.expected_tries(7), None);
+        assert_eq!(Die::D8.expected_tries(7), Some(4.0));
+    }
 }
@@ -1,13 +1,20 @@
-use fontdue::layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle};
+use fontdue::layout::{
+    CoordinateSystem, GlyphPosition, GlyphRasterConfig, HorizontalAlign, Layout, LayoutSettings, TextStyle,
+    VerticalAlign, WrapStyle,
+};
 use fontdue::{Font as FontdueFont, FontSettings};
 use fontdue_sdl2::FontTexture;
 use sdl2::pixels::Color;
 use sdl2::render::{Canvas, RenderTarget, TextureCreator};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Text(pub Font, pub f32, pub Color, pub String);
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Font {
     RegularUi,
     BoldUi,
@@ -15,26 +22,67 @@ pub enum Font {
     Count,
 }
 
+/// The width and height of the glyph cache texture created by
+/// [FontTexture::new].
+const GLYPH_ATLAS_SIZE: usize = 1024;
+/// How much of the glyph cache can be filled before starting over with
+/// an empty one. The glyphs aren't packed perfectly, so this leaves
+/// some slack to avoid running out of space before it's noticed.
+const GLYPH_ATLAS_MAX_FILL: f32 = 0.6;
+
 pub struct TextPainter<'r> {
-    font_texture: FontTexture<'r>,
+    glyph_atlas: GlyphAtlas<'r>,
     fonts: [FontdueFont; Font::Count as usize],
     layout: Layout<Color>,
+    /// Glyph layouts of the texts drawn recently, so that labels
+    /// which stay the same between frames don't need to be laid out
+    /// again. Keyed by [layout_hash].
+    layout_cache: HashMap<u64, CachedLayout>,
+}
+
+/// The [FontTexture] the glyphs are rasterized into, along with enough
+/// bookkeeping to tell when it's getting full. A full FontTexture
+/// draws rectangles in place of the glyphs that don't fit, so it's
+/// replaced with an empty one before that happens.
+struct GlyphAtlas<'r> {
+    font_texture: FontTexture<'r>,
+    create_font_texture: Box<dyn Fn() -> Result<FontTexture<'r>, String> + 'r>,
+    rasterized: HashSet<(GlyphRasterConfig, Color)>,
+    used_area: usize,
+}
+
+struct CachedLayout {
+    settings: LayoutSettings,
+    text_parts: Vec<Text>,
+    glyphs: Vec<GlyphPosition<Color>>,
+    used_this_frame: bool,
 }
 
 impl TextPainter<'_> {
     pub fn new<'r, T>(texture_creator: &'r TextureCreator<T>) -> Result<TextPainter<'r>, String> {
-        let font_texture = FontTexture::new(&texture_creator)?;
-        let font = include_bytes!("fonts/recursive/Recursive-Regular-stripped.ttf") as &[u8];
-        let regular_ui = FontdueFont::from_bytes(font, FontSettings::default()).unwrap();
-        let font = include_bytes!("fonts/recursive/Recursive-Bold-stripped.ttf") as &[u8];
-        let bold_ui = FontdueFont::from_bytes(font, FontSettings::default()).unwrap();
+        let create_font_texture = Box::new(move || FontTexture::new(texture_creator));
+        let glyph_atlas = GlyphAtlas {
+            font_texture: create_font_texture()?,
+            create_font_texture,
+            rasterized: HashSet::new(),
+            used_area: 0,
+        };
+        let regular_ui = load_font(
+            "regular.ttf",
+            include_bytes!("fonts/recursive/Recursive-Regular-stripped.ttf"),
+        );
+        let bold_ui = load_font(
+            "bold.ttf",
+            include_bytes!("fonts/recursive/Recursive-Bold-stripped.ttf"),
+        );
         let fonts = [regular_ui, bold_ui];
         let layout = Layout::new(CoordinateSystem::PositiveYDown);
 
         Ok(TextPainter {
-            font_texture,
+            glyph_atlas,
             fonts,
             layout,
+            layout_cache: HashMap::new(),
         })
     }
 
@@ -44,13 +92,159 @@ impl TextPainter<'_> {
         layout: &LayoutSettings,
         text_parts: &[Text],
     ) {
-        self.layout.reset(layout);
-        for Text(font_enum, font_size, color, text) in text_parts {
-            self.layout.append(
-                &self.fonts,
-                &TextStyle::with_user_data(text, *font_size, *font_enum as usize, *color),
-            );
+        let hash = layout_hash(layout, text_parts);
+        match self.layout_cache.get_mut(&hash) {
+            Some(cached) if cached.settings == *layout && cached.text_parts == text_parts => {
+                cached.used_this_frame = true;
+                self.glyph_atlas.draw(canvas, &self.fonts, &cached.glyphs);
+            }
+            _ => {
+                self.layout.reset(layout);
+                for Text(font_enum, font_size, color, text) in text_parts {
+                    self.layout.append(
+                        &self.fonts,
+                        &TextStyle::with_user_data(text, *font_size, *font_enum as usize, *color),
+                    );
+                }
+                let glyphs = self.layout.glyphs().clone();
+                self.glyph_atlas.draw(canvas, &self.fonts, &glyphs);
+                let cached = CachedLayout {
+                    settings: *layout,
+                    text_parts: text_parts.to_vec(),
+                    glyphs,
+                    used_this_frame: true,
+                };
+                self.layout_cache.insert(hash, cached);
+            }
+        }
+    }
+
+    /// Returns a copy of the text where each line that's wider than
+    /// `max_width` pixels is cut short and ends in an ellipsis.
+    pub fn ellipsize(&self, text: &Text, max_width: f32) -> Text {
+        let Text(font_enum, font_size, color, string) = text;
+        let font = &self.fonts[*font_enum as usize];
+        let advance = |c: char| font.metrics(c, *font_size).advance_width;
+        let ellipsis_width = 3.0 * advance('.');
+
+        let mut lines = Vec::new();
+        for line in string.split('\n') {
+            if line.chars().map(advance).sum::<f32>() <= max_width {
+                lines.push(line.to_string());
+                continue;
+            }
+            let mut width = ellipsis_width;
+            let mut truncated = String::new();
+            for c in line.chars() {
+                width += advance(c);
+                if width > max_width {
+                    break;
+                }
+                truncated.push(c);
+            }
+            truncated.truncate(truncated.trim_end().len());
+            truncated.push_str("...");
+            lines.push(truncated);
+        }
+        Text(*font_enum, *font_size, *color, lines.join("\n"))
+    }
+
+    /// Forgets the layouts of texts that weren't drawn since the
+    /// previous call. Should be called once per frame.
+    pub fn end_frame(&mut self) {
+        self.layout_cache.retain(|_, cached| cached.used_this_frame);
+        for cached in self.layout_cache.values_mut() {
+            cached.used_this_frame = false;
+        }
+    }
+}
+
+impl GlyphAtlas<'_> {
+    fn draw<RT: RenderTarget>(
+        &mut self,
+        canvas: &mut Canvas<RT>,
+        fonts: &[FontdueFont],
+        glyphs: &[GlyphPosition<Color>],
+    ) {
+        let new_area = glyphs
+            .iter()
+            .filter(|glyph| !self.rasterized.contains(&(glyph.key, glyph.user_data)))
+            .map(|glyph| glyph.width * glyph.height)
+            .sum::<usize>();
+        if (self.used_area + new_area) as f32 > (GLYPH_ATLAS_SIZE * GLYPH_ATLAS_SIZE) as f32 * GLYPH_ATLAS_MAX_FILL {
+            log::warn!("The glyph cache is getting full, starting over with an empty one.");
+            self.recreate();
+        }
+        for glyph in glyphs {
+            if self.rasterized.insert((glyph.key, glyph.user_data)) {
+                self.used_area += glyph.width * glyph.height;
+            }
         }
-        let _ = self.font_texture.draw_text(canvas, &self.fonts, self.layout.glyphs());
+
+        if let Err(err) = self.font_texture.draw_text(canvas, fonts, glyphs) {
+            log::warn!("Drawing text failed ({}), retrying with a new glyph cache.", err);
+            self.recreate();
+            if let Err(err) = self.font_texture.draw_text(canvas, fonts, glyphs) {
+                log::error!("Drawing text failed again: {}", err);
+            }
+        }
+    }
+
+    fn recreate(&mut self) {
+        match (self.create_font_texture)() {
+            Ok(font_texture) => self.font_texture = font_texture,
+            Err(err) => log::error!("Could not create a new glyph cache: {}", err),
+        }
+        self.rasterized.clear();
+        self.used_area = 0;
+    }
+}
+
+/// Loads the font from [crate::ASSETS_DIRECTORY] if it's there and
+/// valid, otherwise from the embedded bytes.
+fn load_font(file_name: &str, embedded: &[u8]) -> FontdueFont {
+    let path = Path::new(crate::ASSETS_DIRECTORY).join(file_name);
+    if let Ok(bytes) = std::fs::read(&path) {
+        match FontdueFont::from_bytes(bytes, FontSettings::default()) {
+            Ok(font) => {
+                log::info!("Using the font from {}.", path.display());
+                return font;
+            }
+            Err(err) => log::error!("Could not use the font from {}: {}", path.display(), err),
+        }
+    }
+    FontdueFont::from_bytes(embedded, FontSettings::default()).unwrap()
+}
+
+fn layout_hash(layout: &LayoutSettings, text_parts: &[Text]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    layout.x.to_bits().hash(&mut hasher);
+    layout.y.to_bits().hash(&mut hasher);
+    layout.max_width.map(f32::to_bits).hash(&mut hasher);
+    layout.max_height.map(f32::to_bits).hash(&mut hasher);
+    (match layout.horizontal_align {
+        HorizontalAlign::Left => 0u8,
+        HorizontalAlign::Center => 1,
+        HorizontalAlign::Right => 2,
+    })
+    .hash(&mut hasher);
+    (match layout.vertical_align {
+        VerticalAlign::Top => 0u8,
+        VerticalAlign::Middle => 1,
+        VerticalAlign::Bottom => 2,
+    })
+    .hash(&mut hasher);
+    (match layout.wrap_style {
+        WrapStyle::Word => 0u8,
+        WrapStyle::Letter => 1,
+    })
+    .hash(&mut hasher);
+    layout.wrap_hard_breaks.hash(&mut hasher);
+    for Text(font, font_size, color, text) in text_parts {
+        (*font as usize).hash(&mut hasher);
+        font_size.to_bits().hash(&mut hasher);
+        color.hash(&mut hasher);
+        text.hash(&mut hasher);
     }
+    hasher.finish()
 }
@@ -2,11 +2,24 @@ use png::{BitDepth, ColorType};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::{Point, Rect};
 use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture, TextureCreator, TextureValueError, UpdateTextureError};
+use std::path::Path;
 
+/// The size of a tile in the world, regardless of the resolution of
+/// the tileset.
 pub const TILE_STRIDE: i32 = 64;
-const TILE_COLUMNS: i32 = 512 / TILE_STRIDE;
 const TILE_WIDTH: u32 = TILE_STRIDE as u32;
 const TILE_HEIGHT: u32 = TILE_STRIDE as u32;
+/// Tilesets are always this many tiles wide, so the size of the tiles
+/// in the tileset is derived from its width. E.g. a 1024px wide
+/// tileset has 128px tiles.
+const TILESET_COLUMNS: u32 = 8;
+/// Tilesets need to be at least this many tiles tall to contain every
+/// [TileGraphic].
+const MIN_TILESET_ROWS: u32 = 5;
+/// Replaces the embedded tileset if it exists in [crate::ASSETS_DIRECTORY].
+const EXTERNAL_TILESET_FILE: &str = "tileset.png";
+/// The gray that tile shadows are tinted with.
+const SHADOW_GRAY: u8 = 0x44;
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum TileLayer {
@@ -63,6 +76,54 @@ impl TileGraphic {
         }
     }
 
+    /// The column and row of the graphic in the tileset. Explicit, so
+    /// that the order of the variants doesn't matter.
+    pub const fn atlas_position(self) -> (i32, i32) {
+        match self {
+            TileGraphic::Ground => (0, 0),
+            TileGraphic::WallTop => (1, 0),
+            TileGraphic::WallSide => (2, 0),
+            TileGraphic::Player => (3, 0),
+            TileGraphic::ShadowLeft => (4, 0),
+            TileGraphic::ShadowBottom => (5, 0),
+            TileGraphic::ShadowBottomLeft => (6, 0),
+            TileGraphic::ShadowTopLeft => (7, 0),
+            TileGraphic::CornerShadowTopLeft => (0, 1),
+            TileGraphic::TileHighlight => (1, 1),
+            TileGraphic::DoorClosed => (2, 1),
+            TileGraphic::DoorOpening => (3, 1),
+            TileGraphic::DoorOpen => (4, 1),
+            TileGraphic::SideDoorClosed => (5, 1),
+            TileGraphic::SideDoorOpening => (6, 1),
+            TileGraphic::SideDoorOpen => (7, 1),
+            TileGraphic::Slime => (0, 2),
+            TileGraphic::DeadSlime => (1, 2),
+            TileGraphic::Roach => (2, 2),
+            TileGraphic::DeadRoach => (3, 2),
+            TileGraphic::Rockman => (4, 2),
+            TileGraphic::DeadRockman => (5, 2),
+            TileGraphic::SentientMetal => (6, 2),
+            TileGraphic::DeadSentientMetal => (7, 2),
+            TileGraphic::LevelExit => (0, 3),
+            TileGraphic::MineralCounter => (1, 3),
+            TileGraphic::MineralsScattered => (2, 3),
+            TileGraphic::FinalTreasureMinerals => (3, 3),
+            TileGraphic::LockedDoor => (4, 3),
+            TileGraphic::HotGround => (5, 3),
+            TileGraphic::HotWallTop => (6, 3),
+            TileGraphic::HotWallSide => (7, 3),
+            TileGraphic::LaserBeam => (0, 4),
+            TileGraphic::AttackMiss => (1, 4),
+            TileGraphic::AttackHit => (2, 4),
+        }
+    }
+
+    fn source_rect(self, tile_size: u32) -> Rect {
+        let (column, row) = self.atlas_position();
+        let stride = tile_size as i32;
+        Rect::new(column * stride, row * stride, tile_size, tile_size)
+    }
+
     pub const fn dead(self) -> TileGraphic {
         match self {
             TileGraphic::Slime => TileGraphic::DeadSlime,
@@ -76,40 +137,39 @@ impl TileGraphic {
 
 pub struct TilePainter<'r> {
     pub tileset: Texture<'r>,
-    pub shadow_tileset: Texture<'r>,
+    /// The size of the tiles in the tileset texture, in pixels.
+    pub tile_size: u32,
 }
 
 impl TilePainter<'_> {
     pub fn new<'r, T>(texture_creator: &'r TextureCreator<T>) -> Result<TilePainter<'r>, ImageLoadingError> {
-        let bytes: &[u8] = include_bytes!("graphics/tileset-quantized.png");
-        let decoder = png::Decoder::new(bytes);
-        let (info, mut reader) = decoder.read_info()?;
-        let mut buf = vec![0; info.buffer_size()];
-        reader.next_frame(&mut buf)?;
-
-        let format = match (info.color_type, info.bit_depth) {
-            (ColorType::RGBA, BitDepth::Eight) => PixelFormatEnum::RGBA32,
-            _ => return Err(ImageLoadingError::UnsupportedFormat),
+        let external_path = Path::new(crate::ASSETS_DIRECTORY).join(EXTERNAL_TILESET_FILE);
+        let external_image = std::fs::read(&external_path)
+            .ok()
+            .and_then(|bytes| match decode_tileset(&bytes) {
+                Ok(image) => {
+                    log::info!("Using the tileset from {}.", external_path.display());
+                    Some(image)
+                }
+                Err(err) => {
+                    log::error!("Could not use the tileset from {}: {:?}", external_path.display(), err);
+                    None
+                }
+            });
+        let (buf, width, height) = match external_image {
+            Some(image) => image,
+            None => decode_tileset(include_bytes!("graphics/tileset-quantized.png"))?,
         };
-        let pitch = info.width as usize * format.byte_size_per_pixel();
+        let format = PixelFormatEnum::RGBA32;
+        let pitch = width as usize * format.byte_size_per_pixel();
 
-        let mut tileset = texture_creator.create_texture_static(format, info.width, info.height)?;
+        let mut tileset = texture_creator.create_texture_static(format, width, height)?;
         tileset.update(None, &buf, pitch)?;
         tileset.set_blend_mode(BlendMode::Blend);
 
-        let mut shadow_tileset = texture_creator.create_texture_static(format, info.width, info.height)?;
-        for pixel in buf.chunks_mut(4) {
-            pixel[0] = 0x44;
-            pixel[1] = 0x44;
-            pixel[2] = 0x44;
-            pixel[3] /= 2;
-        }
-        shadow_tileset.update(None, &buf, pitch)?;
-        shadow_tileset.set_blend_mode(BlendMode::Blend);
-
         Ok(TilePainter {
             tileset,
-            shadow_tileset,
+            tile_size: width / TILESET_COLUMNS,
         })
     }
 
@@ -117,23 +177,25 @@ impl TilePainter<'_> {
         &mut self,
         canvas: &mut Canvas<RT>,
         tile: TileGraphic,
-        x: i32,
-        y: i32,
-        width: u32,
-        height: u32,
+        dst_rect: Rect,
         flip_h: bool,
         flip_v: bool,
     ) {
-        let tile_x = tile as usize as i32 % TILE_COLUMNS;
-        let tile_y = tile as usize as i32 / TILE_COLUMNS;
-        let src_rect = Rect::new(tile_x * TILE_STRIDE, tile_y * TILE_STRIDE, TILE_WIDTH, TILE_HEIGHT);
-        let dst_rect = Rect::new(x + 4, y - 2, width, height);
-        let _ = canvas.copy_ex(&self.shadow_tileset, src_rect, dst_rect, 0.0, None, flip_h, flip_v);
-        let dst_rect = Rect::new(x - 1, y, width, height);
-        let _ = canvas.copy_ex(&self.shadow_tileset, src_rect, dst_rect, 0.0, None, flip_h, flip_v);
-        let dst_rect = Rect::new(x, y + 1, width, height);
-        let _ = canvas.copy_ex(&self.shadow_tileset, src_rect, dst_rect, 0.0, None, flip_h, flip_v);
-        let dst_rect = Rect::new(x, y, width, height);
+        let src_rect = tile.source_rect(self.tile_size);
+
+        // The shadows are the same tile, darkened and half as opaque
+        let (r, g, b) = self.tileset.color_mod();
+        let alpha = self.tileset.alpha_mod();
+        self.tileset.set_color_mod(SHADOW_GRAY, SHADOW_GRAY, SHADOW_GRAY);
+        self.tileset.set_alpha_mod(alpha / 2);
+        for (offset_x, offset_y) in &[(4, -2), (-1, 0), (0, 1)] {
+            let mut shadow_rect = dst_rect;
+            shadow_rect.offset(*offset_x, *offset_y);
+            let _ = canvas.copy_ex(&self.tileset, src_rect, shadow_rect, 0.0, None, flip_h, flip_v);
+        }
+        self.tileset.set_color_mod(r, g, b);
+        self.tileset.set_alpha_mod(alpha);
+
         let _ = canvas.copy_ex(&self.tileset, src_rect, dst_rect, 0.0, None, flip_h, flip_v);
     }
 
@@ -146,7 +208,8 @@ impl TilePainter<'_> {
         flip_h: bool,
         flip_v: bool,
     ) {
-        self.draw_tile_shadowed_ex(canvas, tile, x, y, TILE_WIDTH, TILE_HEIGHT, flip_h, flip_v);
+        let dst_rect = Rect::new(x, y, TILE_WIDTH, TILE_HEIGHT);
+        self.draw_tile_shadowed_ex(canvas, tile, dst_rect, flip_h, flip_v);
     }
 
     pub fn draw_tile_rotated<RT: RenderTarget>(
@@ -158,13 +221,23 @@ impl TilePainter<'_> {
         angle: f64,
         around: Point,
     ) {
-        let tile_x = tile as usize as i32 % TILE_COLUMNS;
-        let tile_y = tile as usize as i32 / TILE_COLUMNS;
-        let src_rect = Rect::new(tile_x * TILE_STRIDE, tile_y * TILE_STRIDE, TILE_WIDTH, TILE_HEIGHT);
+        let src_rect = tile.source_rect(self.tile_size);
         let dst_rect = Rect::new(x, y, TILE_WIDTH, TILE_HEIGHT);
         let _ = canvas.copy_ex(&self.tileset, src_rect, dst_rect, angle, Some(around), false, false);
     }
 
+    pub fn draw_tile_ex<RT: RenderTarget>(
+        &mut self,
+        canvas: &mut Canvas<RT>,
+        tile: TileGraphic,
+        dst_rect: Rect,
+        flip_h: bool,
+        flip_v: bool,
+    ) {
+        let src_rect = tile.source_rect(self.tile_size);
+        let _ = canvas.copy_ex(&self.tileset, src_rect, dst_rect, 0.0, None, flip_h, flip_v);
+    }
+
     pub fn draw_tile<RT: RenderTarget>(
         &mut self,
         canvas: &mut Canvas<RT>,
@@ -174,12 +247,31 @@ impl TilePainter<'_> {
         flip_h: bool,
         flip_v: bool,
     ) {
-        let tile_x = tile as usize as i32 % TILE_COLUMNS;
-        let tile_y = tile as usize as i32 / TILE_COLUMNS;
-        let src_rect = Rect::new(tile_x * TILE_STRIDE, tile_y * TILE_STRIDE, TILE_WIDTH, TILE_HEIGHT);
         let dst_rect = Rect::new(x, y, TILE_WIDTH, TILE_HEIGHT);
-        let _ = canvas.copy_ex(&self.tileset, src_rect, dst_rect, 0.0, None, flip_h, flip_v);
+        self.draw_tile_ex(canvas, tile, dst_rect, flip_h, flip_v);
+    }
+}
+
+/// Decodes an RGBA PNG into its pixels, width and height, checking
+/// that it's laid out in [TILESET_COLUMNS] columns of square tiles.
+fn decode_tileset(bytes: &[u8]) -> Result<(Vec<u8>, u32, u32), ImageLoadingError> {
+    let decoder = png::Decoder::new(bytes);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+
+    if (info.color_type, info.bit_depth) != (ColorType::RGBA, BitDepth::Eight) {
+        return Err(ImageLoadingError::UnsupportedFormat);
+    }
+    let tile_size = info.width / TILESET_COLUMNS;
+    if tile_size == 0
+        || info.width % TILESET_COLUMNS != 0
+        || info.height % tile_size != 0
+        || info.height / tile_size < MIN_TILESET_ROWS
+    {
+        return Err(ImageLoadingError::UnsupportedDimensions(info.width, info.height));
     }
+    Ok((buf, info.width, info.height))
 }
 
 #[derive(Debug)]
@@ -188,6 +280,7 @@ pub enum ImageLoadingError {
     TextureCreation(TextureValueError),
     TextureUpload(UpdateTextureError),
     UnsupportedFormat,
+    UnsupportedDimensions(u32, u32),
 }
 
 impl From<png::DecodingError> for ImageLoadingError {
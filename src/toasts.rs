@@ -0,0 +1,144 @@
+use crate::{interface, Dungeon, Language, LocalizableString, Name, Text, TextPainter};
+use fontdue::layout::{HorizontalAlign, LayoutSettings};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+use std::collections::VecDeque;
+
+/// How long a toast stays on screen, in seconds.
+const TOAST_DURATION: f32 = 2.5;
+/// How long the fade out at the end of a toast's life takes.
+const TOAST_FADE_DURATION: f32 = 0.5;
+/// Older toasts are dropped when there's more than this many.
+const MAX_TOASTS: usize = 3;
+const TOAST_WIDTH: u32 = 300;
+const TOAST_HEIGHT: u32 = 32;
+
+struct Toast {
+    text: LocalizableString,
+    time_left: f32,
+}
+
+/// Short announcements for the big moments of a run, shown top center
+/// for a couple of seconds. Noticed by comparing the dungeon to how it
+/// was on the previous frame, so the dungeon doesn't need to know
+/// about them.
+#[derive(Default)]
+pub struct Toasts {
+    queue: VecDeque<Toast>,
+    level: usize,
+    round: u64,
+    locked_doors_opened: u32,
+    critical_health: bool,
+    seen_enemies: Vec<Name>,
+}
+
+impl Toasts {
+    pub fn new() -> Toasts {
+        Toasts::default()
+    }
+
+    /// Should be called every frame, ages the toasts and adds new ones
+    /// for whatever happened since the last call.
+    pub fn update(&mut self, dungeon: &Dungeon, delta_seconds: f32) {
+        for toast in &mut self.queue {
+            toast.time_left -= delta_seconds;
+        }
+        self.queue.retain(|toast| toast.time_left > 0.0);
+
+        if dungeon.round() < self.round {
+            // A new run, or an older save was loaded
+            self.reset(dungeon);
+        }
+        self.round = dungeon.round();
+
+        if dungeon.level_nth() > self.level {
+            self.push(LocalizableString::ToastLevelCleared);
+        }
+        self.level = dungeon.level_nth();
+
+        let locked_doors_opened = dungeon.run_stats().locked_doors_opened;
+        if locked_doors_opened > self.locked_doors_opened {
+            self.push(LocalizableString::ToastLockedRoomOpened);
+        }
+        self.locked_doors_opened = locked_doors_opened;
+
+        let player = dungeon.player();
+        let critical_health = player.stats.health > 0 && player.stats.health <= player.stats.max_health / 3;
+        if critical_health && !self.critical_health {
+            self.push(LocalizableString::ToastCriticalHealth);
+        }
+        self.critical_health = critical_health;
+
+        let visible_ids = dungeon.get_selectable_fighter_ids();
+        for fighter in dungeon.enemies() {
+            let seen = self.seen_enemies.contains(&fighter.name);
+            if !seen && fighter.stats.health > 0 && visible_ids.contains(&fighter.id) {
+                self.seen_enemies.push(fighter.name.clone());
+                self.push(LocalizableString::ToastNewEnemy(fighter.name.clone()));
+            }
+        }
+    }
+
+    /// Drops the toasts of the previous dungeon, and starts watching
+    /// `dungeon` from where it is now. Should be called whenever the
+    /// dungeon is replaced.
+    pub fn reset(&mut self, dungeon: &Dungeon) {
+        *self = Toasts::new();
+        self.level = dungeon.level_nth();
+        self.round = dungeon.round();
+        self.locked_doors_opened = dungeon.run_stats().locked_doors_opened;
+    }
+
+    fn push(&mut self, text: LocalizableString) {
+        self.queue.push_back(Toast {
+            text,
+            time_left: TOAST_DURATION,
+        });
+        while self.queue.len() > MAX_TOASTS {
+            self.queue.pop_front();
+        }
+    }
+
+    /// Draws the toasts in a column centered at `center_x`, the oldest
+    /// at `top`.
+    pub fn draw<RT: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<RT>,
+        text_painter: &mut TextPainter,
+        center_x: i32,
+        top: i32,
+    ) {
+        for (i, toast) in self.queue.iter().enumerate() {
+            // Quantized, so the glyph cache doesn't fill up with every shade of the text
+            let opacity = ((toast.time_left / TOAST_FADE_DURATION).min(1.0) * 8.0).ceil() / 8.0;
+            let fade = |color: Color| Color::RGBA(color.r, color.g, color.b, (color.a as f32 * opacity) as u8);
+
+            let rect = Rect::new(
+                center_x - TOAST_WIDTH as i32 / 2,
+                top + i as i32 * (TOAST_HEIGHT as i32 + 4),
+                TOAST_WIDTH,
+                TOAST_HEIGHT,
+            );
+            canvas.set_draw_color(fade(interface::HUD_BACKGROUND_TRANSPARENT));
+            let _ = canvas.fill_rect(rect);
+            canvas.set_draw_color(fade(interface::HUD_BORDER));
+            let _ = canvas.draw_rect(rect);
+
+            let text = toast
+                .text
+                .localize(Language::English)
+                .into_iter()
+                .map(|Text(font, size, color, string)| Text(font, size, fade(color), string))
+                .collect::<Vec<Text>>();
+            let layout = LayoutSettings {
+                x: rect.x as f32,
+                y: (rect.y + 6) as f32,
+                max_width: Some(rect.width() as f32),
+                horizontal_align: HorizontalAlign::Center,
+                ..LayoutSettings::default()
+            };
+            text_painter.draw_text(canvas, &layout, &text);
+        }
+    }
+}
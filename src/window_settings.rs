@@ -0,0 +1,63 @@
+use sdl2::video::{FullscreenType, Window};
+use sdl2::VideoSubsystem;
+
+/// The window settings are stored here between sessions.
+static WINDOW_SETTINGS_FILE: &str = "excavation-site-mercury-window.txt";
+
+/// Which monitor the game opens on, and whether it covers it as a
+/// borderless window.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct WindowSettings {
+    pub display: i32,
+    pub borderless: bool,
+}
+
+impl WindowSettings {
+    pub fn load() -> WindowSettings {
+        let mut settings = WindowSettings::default();
+        let contents = std::fs::read_to_string(WINDOW_SETTINGS_FILE).unwrap_or_default();
+        for line in contents.lines() {
+            match line.split_once('=') {
+                Some(("display", value)) => settings.display = value.trim().parse().unwrap_or(0),
+                Some(("borderless", value)) => settings.borderless = value.trim() == "true",
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self) {
+        let contents = format!("display={}\nborderless={}\n", self.display, self.borderless);
+        if let Err(err) = std::fs::write(WINDOW_SETTINGS_FILE, contents) {
+            log::error!(
+                "Failed to save the window settings to {}: {}",
+                WINDOW_SETTINGS_FILE,
+                err
+            );
+        }
+    }
+
+    /// Moves the window to the center of the remembered display (or the
+    /// first one, if it's not connected anymore), and makes it
+    /// borderless if it should be.
+    pub fn apply(&self, video_subsystem: &VideoSubsystem, window: &mut Window) {
+        let display_count = video_subsystem.num_video_displays().unwrap_or(1).max(1);
+        let display = if self.display < display_count { self.display } else { 0 };
+        if let Ok(bounds) = video_subsystem.display_bounds(display) {
+            let _ = window.set_fullscreen(FullscreenType::Off);
+            let (width, height) = window.size();
+            let x = bounds.x() + (bounds.width() as i32 - width as i32) / 2;
+            let y = bounds.y() + (bounds.height() as i32 - height as i32) / 2;
+            window.set_position(x.into(), y.into());
+        }
+        if self.borderless {
+            let _ = window.set_fullscreen(FullscreenType::Desktop);
+        }
+    }
+
+    /// Picks the next display, wrapping around to the first one.
+    pub fn next_display(&mut self, video_subsystem: &VideoSubsystem) {
+        let display_count = video_subsystem.num_video_displays().unwrap_or(1).max(1);
+        self.display = (self.display + 1) % display_count;
+    }
+}